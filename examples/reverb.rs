@@ -1,6 +1,5 @@
-use rodio::Source;
+use rodio::source::{ReverbSettings, Source};
 use std::io::BufReader;
-use std::time::Duration;
 
 fn main() {
     let (_stream, handle) = rodio::OutputStream::try_default().unwrap();
@@ -8,7 +7,7 @@ fn main() {
 
     let file = std::fs::File::open("examples/music.ogg").unwrap();
     let source = rodio::Decoder::new(BufReader::new(file)).unwrap();
-    let with_reverb = source.buffered().reverb(Duration::from_millis(40), 0.7);
+    let with_reverb = source.reverb(ReverbSettings::default());
     sink.append(with_reverb);
 
     sink.sleep_until_end();
@@ -0,0 +1,237 @@
+//! Positions of the speakers that the channels of a sound are meant for.
+
+/// The position of a speaker.
+///
+/// The positions are the ones of the WAV channel mask, in the same order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Speaker {
+    /// The front left speaker.
+    FrontLeft,
+    /// The front right speaker.
+    FrontRight,
+    /// The front center speaker.
+    FrontCenter,
+    /// The low frequency effects channel, or subwoofer.
+    LowFrequency,
+    /// The back left speaker.
+    BackLeft,
+    /// The back right speaker.
+    BackRight,
+    /// The speaker between the front left and front center ones.
+    FrontLeftOfCenter,
+    /// The speaker between the front right and front center ones.
+    FrontRightOfCenter,
+    /// The back center speaker.
+    BackCenter,
+    /// The left side speaker.
+    SideLeft,
+    /// The right side speaker.
+    SideRight,
+    /// The speaker above the listener.
+    TopCenter,
+    /// The top front left speaker.
+    TopFrontLeft,
+    /// The top front center speaker.
+    TopFrontCenter,
+    /// The top front right speaker.
+    TopFrontRight,
+    /// The top back left speaker.
+    TopBackLeft,
+    /// The top back center speaker.
+    TopBackCenter,
+    /// The top back right speaker.
+    TopBackRight,
+    /// A channel that is not meant for any particular speaker.
+    Unknown,
+}
+
+/// The speakers of the WAV channel mask, in the order of its bits.
+const MASK_SPEAKERS: [Speaker; 18] = [
+    Speaker::FrontLeft,
+    Speaker::FrontRight,
+    Speaker::FrontCenter,
+    Speaker::LowFrequency,
+    Speaker::BackLeft,
+    Speaker::BackRight,
+    Speaker::FrontLeftOfCenter,
+    Speaker::FrontRightOfCenter,
+    Speaker::BackCenter,
+    Speaker::SideLeft,
+    Speaker::SideRight,
+    Speaker::TopCenter,
+    Speaker::TopFrontLeft,
+    Speaker::TopFrontCenter,
+    Speaker::TopFrontRight,
+    Speaker::TopBackLeft,
+    Speaker::TopBackCenter,
+    Speaker::TopBackRight,
+];
+
+/// The speaker of each channel of a sound, in the order in which the channels are interleaved.
+///
+/// ```
+/// use rodio::channel_layout::{ChannelLayout, Speaker};
+///
+/// let layout = ChannelLayout::default_for(6);
+/// assert_eq!(layout.index_of(Speaker::LowFrequency), Some(3));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelLayout {
+    speakers: Vec<Speaker>,
+}
+
+impl ChannelLayout {
+    /// Builds a layout from the speaker of each channel.
+    #[inline]
+    pub fn new(speakers: Vec<Speaker>) -> ChannelLayout {
+        ChannelLayout { speakers }
+    }
+
+    /// Returns the usual layout for a number of channels, which is the one used by WAV and FLAC
+    /// files: mono, stereo, 3.0, quadraphonic, 5.0, 5.1, 6.1 and 7.1.
+    ///
+    /// Channels beyond the 8th are not meant for any particular speaker.
+    pub fn default_for(channels: u16) -> ChannelLayout {
+        use self::Speaker::*;
+        let speakers: &[Speaker] = match channels {
+            1 => &[FrontCenter],
+            2 => &[FrontLeft, FrontRight],
+            3 => &[FrontLeft, FrontRight, FrontCenter],
+            4 => &[FrontLeft, FrontRight, BackLeft, BackRight],
+            5 => &[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight],
+            6 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+            ],
+            7 => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackCenter,
+                SideLeft,
+                SideRight,
+            ],
+            _ => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+            ],
+        };
+        ChannelLayout::with_unknown(speakers, channels)
+    }
+
+    /// Returns the layout defined by the Vorbis specification for a number of channels, which
+    /// Opus uses as well.
+    pub fn vorbis(channels: u16) -> ChannelLayout {
+        use self::Speaker::*;
+        let speakers: &[Speaker] = match channels {
+            1 => &[FrontCenter],
+            2 => &[FrontLeft, FrontRight],
+            3 => &[FrontLeft, FrontCenter, FrontRight],
+            4 => &[FrontLeft, FrontRight, BackLeft, BackRight],
+            5 => &[FrontLeft, FrontCenter, FrontRight, BackLeft, BackRight],
+            6 => &[
+                FrontLeft,
+                FrontCenter,
+                FrontRight,
+                BackLeft,
+                BackRight,
+                LowFrequency,
+            ],
+            7 => &[
+                FrontLeft,
+                FrontCenter,
+                FrontRight,
+                SideLeft,
+                SideRight,
+                BackCenter,
+                LowFrequency,
+            ],
+            _ => &[
+                FrontLeft,
+                FrontCenter,
+                FrontRight,
+                SideLeft,
+                SideRight,
+                BackLeft,
+                BackRight,
+                LowFrequency,
+            ],
+        };
+        ChannelLayout::with_unknown(speakers, channels)
+    }
+
+    /// Returns the layout described by a WAV channel mask, whose set bits give the speakers of
+    /// the channels in order.
+    ///
+    /// Channels that the mask does not describe are not meant for any particular speaker.
+    pub fn from_wav_mask(mask: u32, channels: u16) -> ChannelLayout {
+        let speakers: Vec<Speaker> = MASK_SPEAKERS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| mask & (1 << bit) != 0)
+            .map(|(_, &speaker)| speaker)
+            .collect();
+        ChannelLayout::with_unknown(&speakers, channels)
+    }
+
+    /// Takes the first speakers of a list, adding unknown ones to reach the number of channels.
+    fn with_unknown(speakers: &[Speaker], channels: u16) -> ChannelLayout {
+        let channels = channels as usize;
+        let mut speakers = speakers[..channels.min(speakers.len())].to_vec();
+        speakers.resize(channels, Speaker::Unknown);
+        ChannelLayout { speakers }
+    }
+
+    /// Returns the speaker of each channel.
+    #[inline]
+    pub fn speakers(&self) -> &[Speaker] {
+        &self.speakers
+    }
+
+    /// Returns the number of channels.
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.speakers.len() as u16
+    }
+
+    /// Returns the index of the channel meant for a speaker, if there is one.
+    #[inline]
+    pub fn index_of(&self, speaker: Speaker) -> Option<usize> {
+        self.speakers.iter().position(|&s| s == speaker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelLayout, Speaker};
+
+    #[test]
+    fn wav_mask() {
+        // 5.1 with side speakers, and an extra channel.
+        let layout = ChannelLayout::from_wav_mask(0x60f, 7);
+        assert_eq!(
+            layout.speakers(),
+            [
+                Speaker::FrontLeft,
+                Speaker::FrontRight,
+                Speaker::FrontCenter,
+                Speaker::LowFrequency,
+                Speaker::SideLeft,
+                Speaker::SideRight,
+                Speaker::Unknown,
+            ]
+        );
+        assert_eq!(ChannelLayout::from_wav_mask(0x3, 1).channels(), 1);
+    }
+}
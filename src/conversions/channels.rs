@@ -39,6 +39,12 @@ where
         }
     }
 
+    /// Returns a mutable reference to the underlying iterator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
     /// Destroys this iterator and returns the underlying iterator.
     #[inline]
     pub fn into_inner(self) -> I {
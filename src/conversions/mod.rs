@@ -8,10 +8,20 @@ This includes conversion between sample formats, channels or sample rates.
 pub use self::channels::ChannelCountConverter;
 pub use self::sample::DataConverter;
 pub use self::sample::Sample;
-pub use self::sample_rate::SampleRateConverter;
+pub use self::sample_rate::{ResampleQuality, SampleRateConverter};
 
 mod channels;
 // TODO: < shouldn't be public ; there's a bug in Rust 1.4 and below that makes This
 // `pub` mandatory
 pub mod sample;
 mod sample_rate;
+mod sinc;
+
+/// Returns the samples of a full scale sine wave at `freq` Hz, from the sample `range.start` to
+/// the sample `range.end`.
+#[cfg(test)]
+pub fn sine(freq: f32, rate: u32, range: std::ops::Range<usize>) -> Vec<f32> {
+    range
+        .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+        .collect()
+}
@@ -18,6 +18,12 @@ impl<I, O> DataConverter<I, O> {
         }
     }
 
+    /// Returns a mutable reference to the underlying iterator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
     /// Destroys this iterator and returns the underlying iterator.
     #[inline]
     pub fn into_inner(self) -> I {
@@ -52,6 +58,35 @@ where
 {
 }
 
+/// Converts an integer sample of the given bit depth into an f32 in the range [-1.0, 1.0].
+///
+/// Positive values are divided by the largest value and negative values by the smallest one,
+/// like cpal does for `i16`, so that 16 bits samples convert back to the same `i16`. Every bit
+/// depth up to 24 bits fits in the mantissa of an f32, so no precision is lost for those.
+#[cfg(any(feature = "wav", feature = "aiff", feature = "flac"))]
+pub(crate) fn int_to_f32(value: i32, bits_per_sample: u32) -> f32 {
+    let min = 1u64 << (bits_per_sample - 1);
+    if value < 0 {
+        value as f32 / min as f32
+    } else {
+        value as f32 / (min - 1) as f32
+    }
+}
+
+/// Converts a sample in the range [-1.0, 1.0] into an integer of the given bit depth, clipping
+/// it if needed. This is the inverse of `int_to_f32`.
+#[cfg(feature = "wav")]
+pub(crate) fn f32_to_int(sample: f32, bits_per_sample: u32) -> i32 {
+    let min = (1u64 << (bits_per_sample - 1)) as f32;
+    let max = ((1u64 << (bits_per_sample - 1)) - 1) as f32;
+    let value = if sample < 0.0 {
+        sample * min
+    } else {
+        sample * max
+    };
+    value.round().max(-min).min(max) as i32
+}
+
 /// Represents a value of a single sample.
 ///
 /// This trait is implemented by default on three types: `i16`, `u16` and `f32`.
@@ -1,7 +1,61 @@
+use crate::conversions::sinc::SincResampler;
 use crate::conversions::Sample;
 
 use std::mem;
 
+/// Algorithm used to convert between sample rates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResampleQuality {
+    /// Linear interpolation between two input samples. This is the cheapest, but it lets through
+    /// high frequencies that cannot be represented, which are heard as aliasing.
+    Linear,
+    /// Windowed-sinc interpolation from 8 input samples on each side.
+    Low,
+    /// Windowed-sinc interpolation from 16 input samples on each side.
+    Medium,
+    /// Windowed-sinc interpolation from 32 input samples on each side, with the sharpest cutoff.
+    High,
+}
+
+impl ResampleQuality {
+    const ALL: [ResampleQuality; 4] = [
+        ResampleQuality::Linear,
+        ResampleQuality::Low,
+        ResampleQuality::Medium,
+        ResampleQuality::High,
+    ];
+
+    /// Returns the quality as a number, so that it can be stored in an atomic.
+    #[inline]
+    pub(crate) fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the quality stored as a number by [`to_u8`](ResampleQuality::to_u8).
+    #[inline]
+    pub(crate) fn from_u8(value: u8) -> ResampleQuality {
+        ResampleQuality::ALL[value as usize]
+    }
+
+    /// Returns the number of input samples on each side and the fraction of the Nyquist frequency
+    /// kept by the windowed-sinc filter, if any.
+    fn sinc_params(self) -> Option<(usize, f64)> {
+        match self {
+            ResampleQuality::Linear => None,
+            ResampleQuality::Low => Some((8, 0.8)),
+            ResampleQuality::Medium => Some((16, 0.9)),
+            ResampleQuality::High => Some((32, 0.95)),
+        }
+    }
+}
+
+impl Default for ResampleQuality {
+    #[inline]
+    fn default() -> ResampleQuality {
+        ResampleQuality::Linear
+    }
+}
+
 /// Iterator that converts from a certain sample rate to another.
 #[derive(Clone, Debug)]
 pub struct SampleRateConverter<I>
@@ -27,6 +81,8 @@ where
     next_output_frame_pos_in_chunk: u32,
     /// The buffer containing the samples waiting to be output.
     output_buffer: Vec<I::Item>,
+    /// The windowed-sinc resampler used instead of linear interpolation, if any.
+    sinc: Option<SincResampler>,
 }
 
 impl<I> SampleRateConverter<I>
@@ -46,6 +102,7 @@ where
         from: cpal::SampleRate,
         to: cpal::SampleRate,
         num_channels: cpal::ChannelCount,
+        quality: ResampleQuality,
     ) -> SampleRateConverter<I> {
         let from = from.0;
         let to = to.0;
@@ -67,10 +124,24 @@ where
             gcd(from, to)
         };
 
+        let sinc = match quality.sinc_params() {
+            Some((half_len, rolloff)) if from != to => Some(SincResampler::new(
+                from / gcd,
+                to / gcd,
+                num_channels,
+                half_len,
+                rolloff,
+            )),
+            _ => None,
+        };
+
         let (first_samples, next_samples) = if from == to {
             // if `from` == `to` == 1, then we just pass through
             debug_assert_eq!(from, gcd);
             (Vec::new(), Vec::new())
+        } else if sinc.is_some() {
+            // The sinc resampler reads the input itself.
+            (Vec::new(), Vec::new())
         } else {
             let first = input
                 .by_ref()
@@ -93,9 +164,16 @@ where
             current_frame: first_samples,
             next_frame: next_samples,
             output_buffer: Vec::with_capacity(num_channels as usize - 1),
+            sinc,
         }
     }
 
+    /// Returns a mutable reference to the underlying iterator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
     /// Destroys this iterator and returns the underlying iterator.
     #[inline]
     pub fn into_inner(self) -> I {
@@ -131,6 +209,15 @@ where
             return self.input.next();
         }
 
+        if let Some(sinc) = &mut self.sinc {
+            if self.output_buffer.is_empty()
+                && !sinc.next_frame(&mut self.input, &mut self.output_buffer)
+            {
+                return None;
+            }
+            return Some(self.output_buffer.remove(0));
+        }
+
         // Short circuit if there are some samples waiting.
         if !self.output_buffer.is_empty() {
             return Some(self.output_buffer.remove(0));
@@ -234,6 +321,10 @@ where
 
         if self.from == self.to {
             self.input.size_hint()
+        } else if let Some(sinc) = &self.sinc {
+            let apply = |samples: usize| sinc.len_after(samples) + self.output_buffer.len();
+            let (min, max) = self.input.size_hint();
+            (apply(min), max.map(apply))
         } else {
             let (min, max) = self.input.size_hint();
             (apply(min), max.map(apply))
@@ -250,7 +341,8 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::SampleRateConverter;
+    use super::{ResampleQuality, SampleRateConverter};
+    use crate::conversions::sine;
     use core::time::Duration;
     use cpal::SampleRate;
     use quickcheck::quickcheck;
@@ -270,7 +362,7 @@ mod test {
 
             let input: Vec<u16> = Vec::new();
             let output =
-                SampleRateConverter::new(input.into_iter(), from, to, n)
+                SampleRateConverter::new(input.into_iter(), from, to, n, ResampleQuality::Linear)
                   .collect::<Vec<_>>();
 
             assert_eq!(output, []);
@@ -282,7 +374,7 @@ mod test {
             if n == 0 { return; }
 
             let output =
-                SampleRateConverter::new(input.clone().into_iter(), from, from, n)
+                SampleRateConverter::new(input.clone().into_iter(), from, from, n, ResampleQuality::Linear)
                   .collect::<Vec<_>>();
 
             assert_eq!(input, output);
@@ -304,7 +396,7 @@ mod test {
             };
 
             let output =
-                SampleRateConverter::new(input.clone().into_iter(), from, to, n)
+                SampleRateConverter::new(input.clone().into_iter(), from, to, n, ResampleQuality::Linear)
                   .collect::<Vec<_>>();
 
             assert_eq!(input.chunks_exact(n.into())
@@ -328,7 +420,7 @@ mod test {
             };
 
             let output =
-                SampleRateConverter::new(input.clone().into_iter(), from, to, n)
+                SampleRateConverter::new(input.clone().into_iter(), from, to, n, ResampleQuality::Linear)
                   .collect::<Vec<_>>();
 
             assert_eq!(input,
@@ -349,11 +441,11 @@ mod test {
             let from = SampleRate(source.sample_rate());
 
             let resampled =
-                SampleRateConverter::new(source, from, to, 1);
+                SampleRateConverter::new(source, from, to, 1, ResampleQuality::Linear);
             let duration =
                 Duration::from_secs_f32(resampled.count() as f32 / to.0 as f32);
 
-            let delta = if d < duration { duration - d } else { d - duration };
+            let delta = d.abs_diff(duration);
             assert!(delta < Duration::from_millis(1),
                     "Resampled duration ({:?}) is not close to original ({:?}); Δ = {:?}",
                     duration, d, delta);
@@ -363,11 +455,59 @@ mod test {
     #[test]
     fn upsample() {
         let input = vec![2u16, 16, 4, 18, 6, 20, 8, 22];
-        let output =
-            SampleRateConverter::new(input.into_iter(), SampleRate(2000), SampleRate(3000), 2);
+        let output = SampleRateConverter::new(
+            input.into_iter(),
+            SampleRate(2000),
+            SampleRate(3000),
+            2,
+            ResampleQuality::Linear,
+        );
         assert_eq!(output.len(), 12);
 
         let output = output.collect::<Vec<_>>();
         assert_eq!(output, [2, 16, 3, 17, 4, 18, 6, 20, 7, 21, 8, 22]);
     }
+
+    #[test]
+    fn sinc_keeps_low_frequencies() {
+        let input = sine(1000.0, 44100, 0..4410);
+        let output = SampleRateConverter::new(
+            input.into_iter(),
+            SampleRate(44100),
+            SampleRate(48000),
+            1,
+            ResampleQuality::High,
+        );
+        assert_eq!(output.len(), 4800);
+
+        let output = output.collect::<Vec<_>>();
+        assert_eq!(output.len(), 4800);
+        let expected = sine(1000.0, 48000, 0..4800);
+        // Away from the edges, where the input is cut.
+        for (out, exp) in output[100..4700].iter().zip(&expected[100..4700]) {
+            assert!((out - exp).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn sinc_removes_aliasing() {
+        // 15 kHz cannot be represented at 22050 Hz.
+        let rms = |quality| {
+            let input = sine(15000.0, 48000, 0..48000);
+            let output = SampleRateConverter::new(
+                input.into_iter(),
+                SampleRate(48000),
+                SampleRate(22050),
+                1,
+                quality,
+            )
+            .skip(100)
+            .take(20000)
+            .collect::<Vec<f32>>();
+            (output.iter().map(|s| s * s).sum::<f32>() / output.len() as f32).sqrt()
+        };
+        assert!(rms(ResampleQuality::Linear) > 0.1);
+        assert!(rms(ResampleQuality::Low) < 0.01);
+        assert!(rms(ResampleQuality::High) < 0.001);
+    }
 }
@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+
+use cpal::Sample as CpalSample;
+
+use crate::conversions::Sample;
+
+/// Largest number of filters computed for positions between two input samples. The filters for
+/// the positions in between are interpolated.
+const MAX_PHASES: usize = 256;
+
+/// Number of input samples that are dropped at once once they are not needed anymore.
+const DRAIN_LEN: usize = 4096;
+
+/// Band-limited conversion between sample rates, by interpolating the input with a windowed sinc.
+///
+/// The filters are computed in advance for a set of positions between two input samples, which is
+/// known as a polyphase filter.
+#[derive(Clone, Debug)]
+pub struct SincResampler {
+    // Number of input samples on each side of the position of an output sample that it is
+    // computed from.
+    half_len: usize,
+    // Filters for `phases + 1` positions evenly spaced between two input samples, of
+    // `2 * half_len` taps each.
+    table: Vec<f32>,
+    phases: usize,
+    // We convert chunks of `from` samples into chunks of `to` samples.
+    from: u64,
+    to: u64,
+    channels: usize,
+    // Input samples, starting with the inter-channel sample at `history_start`.
+    history: Vec<f32>,
+    history_start: u64,
+    // Number of inter-channel samples read from the input.
+    read_frames: u64,
+    ended: bool,
+    // Number of inter-channel samples output.
+    output_frame: u64,
+}
+
+impl SincResampler {
+    /// Builds a resampler from `half_len` input samples on each side, keeping the frequencies
+    /// up to `rolloff` times the lowest of the two Nyquist frequencies.
+    pub fn new(from: u32, to: u32, channels: u16, half_len: usize, rolloff: f64) -> SincResampler {
+        // When lowering the sample rate, the frequencies that the output cannot hold are removed.
+        let cutoff = rolloff * (to as f64 / from as f64).min(1.0);
+        let phases = (to as usize).min(MAX_PHASES);
+        let taps = 2 * half_len;
+
+        let mut table = Vec::with_capacity((phases + 1) * taps);
+        for phase in 0..=phases {
+            let fraction = phase as f64 / phases as f64;
+            let filter: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    // Distance from the position of the output sample to the input sample.
+                    let distance = tap as f64 - (half_len - 1) as f64 - fraction;
+                    cutoff * sinc(cutoff * distance) * blackman(distance / half_len as f64)
+                })
+                .collect();
+            // The filters keep the level of a constant signal.
+            let sum: f64 = filter.iter().sum();
+            table.extend(filter.iter().map(|&tap| (tap / sum) as f32));
+        }
+
+        SincResampler {
+            half_len,
+            table,
+            phases,
+            from: from as u64,
+            to: to as u64,
+            channels: channels as usize,
+            history: Vec::new(),
+            history_start: 0,
+            read_frames: 0,
+            ended: false,
+            output_frame: 0,
+        }
+    }
+
+    /// Computes the next inter-channel sample of the output and adds it to `out`. Returns false at
+    /// the end of the input.
+    pub fn next_frame<I>(&mut self, input: &mut I, out: &mut Vec<I::Item>) -> bool
+    where
+        I: Iterator,
+        I::Item: Sample,
+    {
+        let position = self.output_frame * self.from;
+        let frame = position / self.to;
+        let half_len = self.half_len as u64;
+
+        while !self.ended && self.read_frames <= frame + half_len {
+            let len = self.history.len();
+            self.history.extend(
+                input
+                    .by_ref()
+                    .take(self.channels)
+                    .map(|sample| sample.to_f32()),
+            );
+            if self.history.len() == len {
+                self.ended = true;
+            } else {
+                // A truncated last frame is completed with silence.
+                self.history.resize(len + self.channels, 0.0);
+                self.read_frames += 1;
+            }
+        }
+        if self.ended && frame >= self.read_frames {
+            return false;
+        }
+
+        let phase = (position % self.to) as f64 * self.phases as f64 / self.to as f64;
+        let fraction = phase.fract() as f32;
+        let taps = 2 * self.half_len;
+        let before = &self.table[phase as usize * taps..][..taps];
+        let after = &self.table[(phase as usize + 1).min(self.phases) * taps..][..taps];
+
+        // The input samples before the start and after the end are silence.
+        let first = (frame + 1) as i64 - half_len as i64;
+        for channel in 0..self.channels {
+            let mut value = 0.0;
+            for tap in 0..taps {
+                let index = first + tap as i64 - self.history_start as i64;
+                if index < 0 {
+                    continue;
+                }
+                let sample = match self.history.get(index as usize * self.channels + channel) {
+                    Some(&sample) => sample,
+                    None => break,
+                };
+                value += sample * (before[tap] + (after[tap] - before[tap]) * fraction);
+            }
+            out.push(CpalSample::from(&value));
+        }
+        self.output_frame += 1;
+
+        // The next output samples never need the input samples before the current one.
+        let unused = (first.max(0) as u64 - self.history_start) as usize;
+        if unused >= DRAIN_LEN {
+            self.history.drain(..unused * self.channels);
+            self.history_start += unused as u64;
+        }
+        true
+    }
+
+    /// Returns the number of samples left to output, if the input has `input_len` samples left.
+    pub fn len_after(&self, input_len: usize) -> usize {
+        let input_frames = if self.ended {
+            self.read_frames
+        } else {
+            let channels = self.channels.max(1);
+            self.read_frames + input_len.div_ceil(channels) as u64
+        };
+        let output_frames = (input_frames * self.to).div_ceil(self.from);
+        output_frames.saturating_sub(self.output_frame) as usize * self.channels
+    }
+}
+
+/// The normalized sinc function.
+#[inline]
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The Blackman window, from -1 to 1.
+#[inline]
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+    }
+}
@@ -0,0 +1,413 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use super::metadata::Metadata;
+use super::DecoderError;
+use crate::conversions::sample::int_to_f32;
+use crate::source::SeekError;
+use crate::Source;
+
+/// Number of bytes read from the data at once.
+const BUFFER_LEN: usize = 16 * 1024;
+
+/// Decoder for the AIFF and AIFF-C formats.
+pub struct AiffDecoder<R>
+where
+    R: Read + Seek,
+{
+    data: R,
+    encoding: Encoding,
+    sample_rate: u32,
+    channels: u16,
+    // Position of the first sample in the data.
+    samples_start: u64,
+    // Number of samples of all channels.
+    samples_len: u64,
+    samples_read: u64,
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    metadata: Metadata,
+    error: Option<DecoderError>,
+}
+
+/// How the samples are stored.
+#[derive(Clone, Copy, Debug)]
+enum Encoding {
+    /// Signed integers stored in the given number of bytes.
+    BigEndian(usize),
+    LittleEndian(usize),
+    /// Unsigned 8 bits integers.
+    Unsigned,
+    Float32,
+    Float64,
+}
+
+impl Encoding {
+    /// Number of bytes of a sample.
+    fn sample_len(self) -> usize {
+        match self {
+            Encoding::BigEndian(len) | Encoding::LittleEndian(len) => len,
+            Encoding::Unsigned => 1,
+            Encoding::Float32 => 4,
+            Encoding::Float64 => 8,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            Encoding::BigEndian(len) => {
+                let value = bytes.iter().fold(0u32, |v, &b| (v << 8) | b as u32);
+                decode_int(value, len)
+            }
+            Encoding::LittleEndian(len) => {
+                let value = bytes.iter().rev().fold(0u32, |v, &b| (v << 8) | b as u32);
+                decode_int(value, len)
+            }
+            Encoding::Unsigned => int_to_f32(bytes[0] as i32 - 128, 8),
+            Encoding::Float32 => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            Encoding::Float64 => {
+                let mut value = [0; 8];
+                value.copy_from_slice(bytes);
+                f64::from_be_bytes(value) as f32
+            }
+        }
+    }
+}
+
+impl<R> AiffDecoder<R>
+where
+    R: Read + Seek,
+{
+    /// Attempts to decode the data as AIFF or AIFF-C.
+    pub fn new(mut data: R) -> Result<AiffDecoder<R>, DecoderError> {
+        let mut header = [0; 12];
+        data.read_exact(&mut header)?;
+        let compressed = &header[8..12] == b"AIFC";
+
+        let mut common = None;
+        let mut sound_data = None;
+        let mut metadata = Metadata::default();
+
+        // The chunks are read until the end of the data is reached.
+        let mut chunk_header = [0; 8];
+        while read_chunk_header(data.by_ref(), &mut chunk_header)? {
+            let len = u32::from_be_bytes([
+                chunk_header[4],
+                chunk_header[5],
+                chunk_header[6],
+                chunk_header[7],
+            ]) as u64;
+            let start = data.stream_position()?;
+
+            match &chunk_header[0..4] {
+                b"COMM" => {
+                    let mut chunk = vec![0; len.min(64) as usize];
+                    data.read_exact(&mut chunk)?;
+                    common = Some(CommonChunk::parse(&chunk, compressed)?);
+                }
+                b"SSND" => {
+                    let mut offset = [0; 4];
+                    data.read_exact(&mut offset)?;
+                    // The offset is followed by the block size, which only matters for writing.
+                    let offset = u32::from_be_bytes(offset) as u64;
+                    sound_data = Some(start + 8 + offset);
+                }
+                id @ b"NAME" | id @ b"AUTH" | id @ b"(c) " | id @ b"ANNO" => {
+                    let mut value = vec![0; len.min(4096) as usize];
+                    data.read_exact(&mut value)?;
+                    let name = match id {
+                        b"NAME" => "TITLE",
+                        b"AUTH" => "ARTIST",
+                        b"(c) " => "COPYRIGHT",
+                        _ => "COMMENT",
+                    };
+                    metadata.push_tag(name, &String::from_utf8_lossy(&value));
+                }
+                _ => (),
+            }
+
+            // Chunks are padded to an even length.
+            match data.seek(SeekFrom::Start(start + len + len % 2)) {
+                Ok(_) => (),
+                // Streams that cannot seek may not be able to skip the sound data, in which case
+                // the chunks after it are ignored.
+                Err(err)
+                    if err.kind() == io::ErrorKind::Unsupported
+                        && common.is_some()
+                        && sound_data.is_some() =>
+                {
+                    break
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let common =
+            common.ok_or_else(|| DecoderError::DecodeError("missing COMM chunk".into()))?;
+        let samples_start =
+            sound_data.ok_or_else(|| DecoderError::DecodeError("missing SSND chunk".into()))?;
+        data.seek(SeekFrom::Start(samples_start))?;
+
+        Ok(AiffDecoder {
+            data,
+            encoding: common.encoding,
+            sample_rate: common.sample_rate,
+            channels: common.channels,
+            samples_start,
+            samples_len: common.frames as u64 * common.channels as u64,
+            samples_read: 0,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            metadata,
+            error: None,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.data
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        self.error.take()
+    }
+
+    /// Reads the next samples into the buffer.
+    ///
+    /// If the data ends early, the samples that could be read are kept and the error is only
+    /// returned once they have all been used.
+    fn fill_buffer(&mut self) -> Result<(), DecoderError> {
+        let sample_len = self.encoding.sample_len();
+        let remaining = (self.samples_len - self.samples_read) as usize;
+        self.buffer
+            .resize(remaining.min(BUFFER_LEN / sample_len) * sample_len, 0);
+        self.buffer_pos = 0;
+
+        let mut len = 0;
+        while len < self.buffer.len() {
+            match self.data.read(&mut self.buffer[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => {
+                    self.buffer.clear();
+                    return Err(err.into());
+                }
+            }
+        }
+
+        self.buffer.truncate(len - len % sample_len);
+        if self.buffer.is_empty() {
+            return Err(DecoderError::Truncated);
+        }
+        Ok(())
+    }
+}
+
+/// Content of the `COMM` chunk.
+struct CommonChunk {
+    channels: u16,
+    frames: u32,
+    sample_rate: u32,
+    encoding: Encoding,
+}
+
+impl CommonChunk {
+    fn parse(chunk: &[u8], compressed: bool) -> Result<CommonChunk, DecoderError> {
+        if chunk.len() < 18 || (compressed && chunk.len() < 22) {
+            return Err(DecoderError::DecodeError("invalid COMM chunk".to_owned()));
+        }
+
+        let channels = u16::from_be_bytes([chunk[0], chunk[1]]);
+        let frames = u32::from_be_bytes([chunk[2], chunk[3], chunk[4], chunk[5]]);
+        let bits_per_sample = u16::from_be_bytes([chunk[6], chunk[7]]);
+        let sample_rate = extended_to_f64(&chunk[8..18]).round();
+        if channels == 0 || !(1.0..=u32::MAX as f64).contains(&sample_rate) {
+            return Err(DecoderError::DecodeError("invalid COMM chunk".to_owned()));
+        }
+
+        // Integer samples are stored in as many bytes as needed.
+        let int_len = bits_per_sample.div_ceil(8) as usize;
+        let compression_type = if compressed { &chunk[18..22] } else { b"NONE" };
+        let encoding = match (compression_type, int_len) {
+            (b"NONE", 1..=4) | (b"twos", 1..=4) => Encoding::BigEndian(int_len),
+            (b"sowt", 1..=4) => Encoding::LittleEndian(int_len),
+            (b"in24", _) => Encoding::BigEndian(3),
+            (b"in32", _) => Encoding::BigEndian(4),
+            (b"23ni", _) => Encoding::LittleEndian(3),
+            (b"42ni", _) => Encoding::LittleEndian(4),
+            (b"raw ", 1) => Encoding::Unsigned,
+            (b"fl32", _) | (b"FL32", _) => Encoding::Float32,
+            (b"fl64", _) | (b"FL64", _) => Encoding::Float64,
+            (compression_type, _) => {
+                return Err(DecoderError::UnsupportedEncoding(format!(
+                    "{} bits AIFF samples with {:?} compression",
+                    bits_per_sample,
+                    String::from_utf8_lossy(compression_type)
+                )))
+            }
+        };
+
+        Ok(CommonChunk {
+            channels,
+            frames,
+            sample_rate: sample_rate as u32,
+            encoding,
+        })
+    }
+}
+
+impl<R> Source for AiffDecoder<R>
+where
+    R: Read + Seek,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.samples_len / self.channels as u64;
+        Some(Duration::from_micros(
+            frames * 1_000_000 / self.sample_rate as u64,
+        ))
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        // Seeking past the end of the stream saturates at the end.
+        let frames = self.samples_len / self.channels as u64;
+        let frame = ((pos.as_secs_f64() * self.sample_rate as f64) as u64).min(frames);
+        let samples_read = frame * self.channels as u64;
+
+        let byte_pos = self.samples_start + samples_read * self.encoding.sample_len() as u64;
+        self.data
+            .seek(SeekFrom::Start(byte_pos))
+            .map_err(SeekError::Io)?;
+        self.samples_read = samples_read;
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        Ok(())
+    }
+}
+
+impl<R> Iterator for AiffDecoder<R>
+where
+    R: Read + Seek,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.samples_read == self.samples_len || self.error.is_some() {
+            return None;
+        }
+        if self.buffer_pos == self.buffer.len() {
+            if let Err(error) = self.fill_buffer() {
+                self.error = Some(error);
+                return None;
+            }
+        }
+
+        let sample_len = self.encoding.sample_len();
+        let bytes = &self.buffer[self.buffer_pos..self.buffer_pos + sample_len];
+        self.buffer_pos += sample_len;
+        self.samples_read += 1;
+        Some(self.encoding.decode(bytes))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.samples_len - self.samples_read) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<R> ExactSizeIterator for AiffDecoder<R> where R: Read + Seek {}
+
+/// Returns true if the stream starts with an AIFF or AIFF-C header, then resets it to where it
+/// was.
+pub fn is_aiff<R>(mut data: R) -> io::Result<bool>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+
+    let mut header = [0; 12];
+    let is_aiff = match data.read_exact(&mut header) {
+        Ok(()) => {
+            &header[0..4] == b"FORM" && (&header[8..12] == b"AIFF" || &header[8..12] == b"AIFC")
+        }
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err),
+    };
+
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(is_aiff)
+}
+
+/// Reads the header of the next chunk. Returns false if the end of the data has been reached.
+fn read_chunk_header<R>(mut data: R, chunk_header: &mut [u8; 8]) -> io::Result<bool>
+where
+    R: Read,
+{
+    match data.read_exact(chunk_header) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Converts an 80 bits IEEE 754 extended precision number, which is how AIFF stores the sample
+/// rate.
+fn extended_to_f64(bytes: &[u8]) -> f64 {
+    let sign = if bytes[0] & 0x80 != 0 { -1.0 } else { 1.0 };
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32;
+    let mut mantissa = [0; 8];
+    mantissa.copy_from_slice(&bytes[2..10]);
+    let mantissa = u64::from_be_bytes(mantissa);
+
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+/// Returns an integer sample stored in `len` bytes as an f32 in the range [-1.0, 1.0].
+///
+/// Samples whose bit depth is not a multiple of 8 are left-justified, so they can be read as if
+/// they used all the bits.
+fn decode_int(value: u32, len: usize) -> f32 {
+    let bits = len as u32 * 8;
+    // Moving the sign bit to the top of an i32 sign-extends the value.
+    let value = ((value << (32 - bits)) as i32) >> (32 - bits);
+    int_to_f32(value, bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extended_to_f64;
+
+    #[test]
+    fn extended_sample_rates() {
+        let rate = |bytes: [u8; 10]| extended_to_f64(&bytes);
+        assert_eq!(rate([0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]), 44100.0);
+        assert_eq!(rate([0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]), 48000.0);
+        assert_eq!(rate([0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]), 8000.0);
+    }
+}
@@ -0,0 +1,370 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+use std::sync::Arc;
+
+use super::custom::{self, CustomFormat, CustomSamples};
+#[cfg(any(feature = "flac", feature = "vorbis", feature = "mp3"))]
+use super::Resync;
+use super::{Decoder, DecoderError, DecoderImpl, LoopedDecoder, Unseekable};
+
+/// Default size of the look-ahead buffer of readers that cannot seek, in bytes.
+const DEFAULT_LOOK_AHEAD: usize = 256 * 1024;
+
+/// The audio formats that can be decoded.
+///
+/// Formats whose cargo feature is disabled are never detected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// WAV, with the `wav` feature.
+    Wav,
+    /// AIFF and AIFF-C, with the `aiff` feature.
+    Aiff,
+    /// FLAC, with the `flac` feature.
+    Flac,
+    /// Vorbis in an Ogg container, with the `vorbis` feature.
+    Vorbis,
+    /// Opus in an Ogg container, with the `opus` feature.
+    Opus,
+    /// MP3, with the `mp3` feature.
+    Mp3,
+}
+
+impl Format {
+    /// All the formats, in the order in which they are tried when there is no hint.
+    const ALL: [Format; 6] = [
+        Format::Wav,
+        Format::Aiff,
+        Format::Flac,
+        Format::Vorbis,
+        Format::Opus,
+        Format::Mp3,
+    ];
+
+    /// Returns the formats that files with the given extension usually contain.
+    fn from_extension(extension: &str) -> &'static [Format] {
+        match &*extension.to_ascii_lowercase() {
+            "wav" | "wave" => &[Format::Wav],
+            "aif" | "aiff" | "aifc" => &[Format::Aiff],
+            "flac" => &[Format::Flac],
+            "ogg" | "oga" => &[Format::Vorbis, Format::Opus],
+            "opus" => &[Format::Opus],
+            "mp3" | "mpga" => &[Format::Mp3],
+            _ => &[],
+        }
+    }
+
+    /// Returns the formats that data of the given MIME type usually contains.
+    fn from_mime_type(mime_type: &str) -> &'static [Format] {
+        // Parameters such as `codecs` are ignored.
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        match &*essence.to_ascii_lowercase() {
+            "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => &[Format::Wav],
+            "audio/aiff" | "audio/x-aiff" => &[Format::Aiff],
+            "audio/flac" | "audio/x-flac" => &[Format::Flac],
+            "audio/ogg" | "application/ogg" => &[Format::Vorbis, Format::Opus],
+            "audio/vorbis" => &[Format::Vorbis],
+            "audio/opus" => &[Format::Opus],
+            "audio/mpeg" | "audio/mp3" => &[Format::Mp3],
+            _ => &[],
+        }
+    }
+}
+
+/// Builds a [`Decoder`] with control over how the format of the data is detected.
+///
+/// By default every format is tried in turn, which requires reading the start of the data and
+/// rewinding it for each of them. Hints, such as the extension of the file, let the right format
+/// be tried first. If the data turns out not to match any of the hinted formats, the other ones
+/// are still tried.
+///
+/// ```no_run
+/// use rodio::decoder::{DecoderBuilder, Format};
+///
+/// let decoder = DecoderBuilder::new()
+///     .with_mime_type("audio/mpeg")
+///     .with_format(Format::Wav, false)
+///     .open("music.mp3")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DecoderBuilder {
+    hints: Vec<Format>,
+    disabled: Vec<Format>,
+    look_ahead: usize,
+    resync: bool,
+}
+
+impl Default for DecoderBuilder {
+    #[inline]
+    fn default() -> DecoderBuilder {
+        DecoderBuilder::new()
+    }
+}
+
+impl DecoderBuilder {
+    /// Creates a builder that tries every format.
+    #[inline]
+    pub fn new() -> DecoderBuilder {
+        DecoderBuilder {
+            hints: Vec::new(),
+            disabled: Vec::new(),
+            look_ahead: DEFAULT_LOOK_AHEAD,
+            resync: false,
+        }
+    }
+
+    /// Tries the given format before the other ones.
+    ///
+    /// Formats hinted first are tried first.
+    #[inline]
+    pub fn with_hint(mut self, format: Format) -> DecoderBuilder {
+        if !self.hints.contains(&format) {
+            self.hints.push(format);
+        }
+        self
+    }
+
+    /// Tries first the formats that files with the given extension usually contain, for example
+    /// `"mp3"`. Unknown extensions are ignored.
+    pub fn with_extension(self, extension: &str) -> DecoderBuilder {
+        Format::from_extension(extension)
+            .iter()
+            .fold(self, |builder, &format| builder.with_hint(format))
+    }
+
+    /// Tries first the formats that data of the given MIME type usually contains, for example
+    /// `"audio/ogg"`. Unknown MIME types are ignored.
+    pub fn with_mime_type(self, mime_type: &str) -> DecoderBuilder {
+        Format::from_mime_type(mime_type)
+            .iter()
+            .fold(self, |builder, &format| builder.with_hint(format))
+    }
+
+    /// Enables or disables the detection of a format. All formats are enabled by default.
+    #[inline]
+    pub fn with_format(mut self, format: Format, enabled: bool) -> DecoderBuilder {
+        self.disabled.retain(|&f| f != format);
+        if !enabled {
+            self.disabled.push(format);
+        }
+        self
+    }
+
+    /// Sets the size of the look-ahead buffer used by [`build_from_read`](Self::build_from_read),
+    /// in bytes. The default is 256 KiB.
+    ///
+    /// Probing the format fails if it needs to rewind further than this, which can happen with
+    /// large tags or embedded pictures at the start of the data.
+    #[inline]
+    pub fn with_look_ahead(mut self, bytes: usize) -> DecoderBuilder {
+        self.look_ahead = bytes;
+        self
+    }
+
+    /// Enables or disables skipping the damaged parts of MP3, Flac and Vorbis data. Disabled by
+    /// default.
+    ///
+    /// When enabled, a frame or page that cannot be decoded is skipped and decoding resumes at
+    /// the next valid one, instead of ending the decoder with an error. This plays as a short
+    /// glitch. The number of frames skipped is returned by
+    /// [`Decoder::skipped_frames`](super::Decoder::skipped_frames).
+    ///
+    /// MP3 decoding always skips the data that is not part of a valid frame. Enabling this makes
+    /// the skipped frames counted as well.
+    #[inline]
+    pub fn with_resync(mut self, enabled: bool) -> DecoderBuilder {
+        self.resync = enabled;
+        self
+    }
+
+    /// Builds a decoder for the data, detecting its format.
+    ///
+    /// The formats added with [`register_format`](super::register_format) are tried as well,
+    /// according to their priority.
+    pub fn build<R>(&self, mut data: R) -> Result<Decoder<R>, DecoderError>
+    where
+        R: Read + Seek + Send,
+    {
+        let custom_formats = custom::registered_formats();
+        let (before, after) =
+            custom_formats.split_at(custom_formats.partition_point(|&(priority, _)| priority > 0));
+
+        for (_, format) in before {
+            if format.probe(&mut data)? {
+                return new_custom_decoder(format, data);
+            }
+        }
+        for format in self.formats() {
+            if is_format(format, data.by_ref())? {
+                return new_decoder(format, data, self.resync);
+            }
+        }
+        for (_, format) in after {
+            if format.probe(&mut data)? {
+                return new_custom_decoder(format, data);
+            }
+        }
+        Err(DecoderError::UnrecognizedFormat)
+    }
+
+    /// Builds a decoder for data that cannot seek, such as a pipe or the output of a process.
+    ///
+    /// The start of the data is kept in a bounded look-ahead buffer while probing its format.
+    /// The decoder cannot seek: [`try_seek`](crate::Source::try_seek) returns
+    /// `SeekError::NotSupported`, and durations that are only known from the end of the data
+    /// are not available.
+    pub fn build_from_read<R>(&self, data: R) -> Result<Decoder<Unseekable<R>>, DecoderError>
+    where
+        R: Read + Send,
+    {
+        let mut decoder = self.build(Unseekable::new(data, self.look_ahead))?;
+        decoder.seekable = false;
+        Ok(decoder)
+    }
+
+    /// Builds a decoder that loops over the data once it reaches its end.
+    #[inline]
+    pub fn build_looped<R>(&self, data: R) -> Result<LoopedDecoder<R>, DecoderError>
+    where
+        R: Read + Seek + Send,
+    {
+        self.build(data).map(LoopedDecoder::new)
+    }
+
+    /// Opens a file and builds a decoder for it. The file is read through a `BufReader`.
+    ///
+    /// The extension of the file is used as a hint, after the hints that have already been given.
+    pub fn open<P>(&self, path: P) -> Result<Decoder<BufReader<File>>, DecoderError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => self
+                .clone()
+                .with_extension(extension)
+                .build(BufReader::new(file)),
+            None => self.build(BufReader::new(file)),
+        }
+    }
+
+    /// Returns the enabled formats in the order in which they are tried.
+    fn formats(&self) -> impl Iterator<Item = Format> + '_ {
+        self.hints
+            .iter()
+            .copied()
+            .chain(
+                Format::ALL
+                    .iter()
+                    .copied()
+                    .filter(move |f| !self.hints.contains(f)),
+            )
+            .filter(move |f| !self.disabled.contains(f))
+    }
+}
+
+impl Decoder<BufReader<File>> {
+    /// Opens a file and builds a decoder for it, using its extension as a hint of its format.
+    ///
+    /// The file is read through a `BufReader`.
+    #[inline]
+    pub fn open<P>(path: P) -> Result<Decoder<BufReader<File>>, DecoderError>
+    where
+        P: AsRef<Path>,
+    {
+        DecoderBuilder::new().open(path)
+    }
+}
+
+impl<R> Decoder<Unseekable<R>>
+where
+    R: Read + Send,
+{
+    /// Builds a decoder for data that cannot seek, such as a pipe or the output of a process.
+    ///
+    /// See [`DecoderBuilder::build_from_read`].
+    #[inline]
+    pub fn from_read(data: R) -> Result<Decoder<Unseekable<R>>, DecoderError> {
+        DecoderBuilder::new().build_from_read(data)
+    }
+}
+
+impl TryFrom<File> for Decoder<BufReader<File>> {
+    type Error = DecoderError;
+
+    /// Builds a decoder for a file, which is read through a `BufReader`.
+    #[inline]
+    fn try_from(file: File) -> Result<Self, Self::Error> {
+        DecoderBuilder::new().build(BufReader::new(file))
+    }
+}
+
+/// Returns true if the data looks like the given format, then resets it to where it was.
+#[allow(unused_variables)]
+fn is_format<R>(format: Format, data: &mut R) -> io::Result<bool>
+where
+    R: Read + Seek,
+{
+    match format {
+        #[cfg(feature = "wav")]
+        Format::Wav => super::wav::is_wave(data),
+        #[cfg(feature = "aiff")]
+        Format::Aiff => super::aiff::is_aiff(data),
+        #[cfg(feature = "flac")]
+        Format::Flac => super::flac::is_flac(data),
+        #[cfg(feature = "vorbis")]
+        Format::Vorbis => super::vorbis::is_vorbis(data),
+        #[cfg(feature = "opus")]
+        Format::Opus => super::opus::is_opus(data),
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => super::mp3::is_mp3(data),
+        #[allow(unreachable_patterns)]
+        _ => Ok(false),
+    }
+}
+
+/// Builds a decoder for data that has been detected as the given format.
+#[allow(unused_variables)]
+fn new_decoder<R>(format: Format, data: R, resync: bool) -> Result<Decoder<R>, DecoderError>
+where
+    R: Read + Seek + Send,
+{
+    let decoder = match format {
+        #[cfg(feature = "wav")]
+        Format::Wav => DecoderImpl::Wav(super::wav::WavDecoder::new(data)?),
+        #[cfg(feature = "aiff")]
+        Format::Aiff => DecoderImpl::Aiff(super::aiff::AiffDecoder::new(data)?),
+        #[cfg(feature = "flac")]
+        Format::Flac => {
+            DecoderImpl::Flac(super::flac::FlacDecoder::new(data, Resync::new(resync))?)
+        }
+        #[cfg(feature = "vorbis")]
+        Format::Vorbis => DecoderImpl::Vorbis(super::vorbis::VorbisDecoder::new(
+            data,
+            Resync::new(resync),
+        )?),
+        #[cfg(feature = "opus")]
+        Format::Opus => DecoderImpl::Opus(super::opus::OpusDecoder::new(data)?),
+        #[cfg(feature = "mp3")]
+        Format::Mp3 => DecoderImpl::Mp3(super::mp3::Mp3Decoder::new(data, Resync::new(resync))?),
+        #[allow(unreachable_patterns)]
+        _ => return Err(DecoderError::UnrecognizedFormat),
+    };
+    Ok(Decoder::from_impl(decoder))
+}
+
+/// Builds a decoder for data that has been detected as a custom format.
+fn new_custom_decoder<R>(
+    format: &Arc<dyn CustomFormat>,
+    data: R,
+) -> Result<Decoder<R>, DecoderError>
+where
+    R: Read + Seek + Send,
+{
+    let samples = CustomSamples::new(data, format.clone())?;
+    Ok(Decoder::from_impl(DecoderImpl::Custom(samples)))
+}
@@ -0,0 +1,215 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::metadata::Metadata;
+use super::DecoderError;
+use crate::source::SeekError;
+use crate::Source;
+
+/// Data that can be read and seeked, as passed to custom decoders.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T> ReadSeek for T where T: Read + Seek + ?Sized {}
+
+/// An audio format decoded outside of rodio, added to the formats that
+/// [`Decoder::new`](super::Decoder::new) detects with [`register_format`].
+///
+/// The data stays owned by the [`Decoder`](super::Decoder), which passes it to every call.
+pub trait CustomFormat: Send + Sync {
+    /// Returns true if the data looks like this format, then resets it to where it was.
+    fn probe(&self, data: &mut dyn ReadSeek) -> io::Result<bool>;
+
+    /// Starts decoding data that has been detected as this format, from its start.
+    fn open(&self, data: &mut dyn ReadSeek) -> Result<Box<dyn CustomDecoder>, DecoderError>;
+}
+
+/// The state of the decoding of a [`CustomFormat`].
+pub trait CustomDecoder: Send {
+    /// Decodes the next samples, interleaved, into `buffer`, which is empty. Leaving it empty
+    /// ends the decoding.
+    fn decode(
+        &mut self,
+        data: &mut dyn ReadSeek,
+        buffer: &mut Vec<f32>,
+    ) -> Result<(), DecoderError>;
+
+    /// Returns the number of channels of the samples decoded last.
+    fn channels(&self) -> u16;
+
+    /// Returns the sample rate of the samples decoded last.
+    fn sample_rate(&self) -> u32;
+
+    /// Returns the duration of the whole stream, if known.
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Moves to a position of the stream, so that the next call to `decode` starts there.
+    ///
+    /// The default implementation does not support seeking.
+    #[inline]
+    fn seek(&mut self, _data: &mut dyn ReadSeek, _pos: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: std::any::type_name::<Self>(),
+        })
+    }
+
+    /// Returns the tags of the stream, as Vorbis comment names and values.
+    #[inline]
+    fn tags(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
+
+/// The registered formats with their priority, sorted by decreasing priority.
+static REGISTRY: RwLock<Vec<(i32, Arc<dyn CustomFormat>)>> = RwLock::new(Vec::new());
+
+/// Adds a format to the ones detected by [`Decoder::new`](super::Decoder::new) and
+/// [`DecoderBuilder`](super::DecoderBuilder).
+///
+/// Formats are tried by decreasing priority. The formats of rodio have a priority of 0: custom
+/// formats with a higher priority are tried before them, and the other ones after them. Formats
+/// with the same priority are tried in the order in which they were registered.
+pub fn register_format<F>(format: F, priority: i32)
+where
+    F: CustomFormat + 'static,
+{
+    let mut registry = REGISTRY.write().unwrap_or_else(|err| err.into_inner());
+    let index = registry.partition_point(|&(other, _)| other >= priority);
+    registry.insert(index, (priority, Arc::new(format)));
+}
+
+/// Returns the registered formats with their priority, sorted by decreasing priority.
+pub(crate) fn registered_formats() -> Vec<(i32, Arc<dyn CustomFormat>)> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// Decoder for a [`CustomFormat`].
+pub struct CustomSamples<R>
+where
+    R: Read + Seek,
+{
+    data: R,
+    format: Arc<dyn CustomFormat>,
+    decoder: Box<dyn CustomDecoder>,
+    buffer: Vec<f32>,
+    // Position in `buffer` of the next sample.
+    pos: usize,
+    metadata: Metadata,
+    error: Option<DecoderError>,
+}
+
+impl<R> CustomSamples<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(
+        mut data: R,
+        format: Arc<dyn CustomFormat>,
+    ) -> Result<CustomSamples<R>, DecoderError> {
+        let decoder = format.open(&mut data)?;
+        let mut metadata = Metadata::default();
+        for (name, value) in decoder.tags() {
+            metadata.push_tag(&name, &value);
+        }
+        let mut samples = CustomSamples {
+            data,
+            format,
+            decoder,
+            buffer: Vec::new(),
+            pos: 0,
+            metadata,
+            error: None,
+        };
+        samples.fill_buffer();
+        match samples.error.take() {
+            Some(error) => Err(error),
+            None => Ok(samples),
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        self.error.take()
+    }
+
+    /// Starts decoding again from the start of the data.
+    pub fn restart(mut self) -> Result<CustomSamples<R>, DecoderError> {
+        self.data.seek(SeekFrom::Start(0))?;
+        CustomSamples::new(self.data, self.format)
+    }
+
+    /// Decodes the next samples, keeping the error if that fails.
+    fn fill_buffer(&mut self) {
+        self.buffer.clear();
+        self.pos = 0;
+        if let Err(error) = self.decoder.decode(&mut self.data, &mut self.buffer) {
+            self.buffer.clear();
+            self.error = Some(error);
+        }
+    }
+}
+
+impl<R> Iterator for CustomSamples<R>
+where
+    R: Read + Seek,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = *self.buffer.get(self.pos)?;
+        self.pos += 1;
+        // The next samples are decoded right away, so that their format is known.
+        if self.pos == self.buffer.len() {
+            self.fill_buffer();
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.buffer.len() - self.pos, None)
+    }
+}
+
+impl<R> Source for CustomSamples<R>
+where
+    R: Read + Seek,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.len() - self.pos)
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.decoder.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.decoder.seek(&mut self.data, pos)?;
+        self.error = None;
+        self.fill_buffer();
+        Ok(())
+    }
+}
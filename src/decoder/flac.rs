@@ -1,25 +1,69 @@
-use std::cmp::Ordering;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::mem;
 use std::time::Duration;
 
+use super::metadata::{self, Metadata, Picture};
+use super::{DecoderError, Resync};
+use crate::conversions::sample::int_to_f32;
+use crate::source::SeekError;
 use crate::Source;
 
+use claxon::frame::{Block, FrameReader};
+use claxon::input::BufferedReader;
 use claxon::FlacReader;
 
+/// Size of the chunks in which the data is searched for the next frame when resynchronizing.
+const SYNC_SEARCH_CHUNK: usize = 16 * 1024;
+
+/// How far back from the position of the data to look for the next frame after an error, on top
+/// of the largest frame. Claxon reads the data in chunks of 2 KiB.
+const SYNC_BACKTRACK: u64 = 2048;
+
+/// Seeks forward by less than this many seconds decode the blocks in between, rather than
+/// looking for the frame to seek to in the data.
+const DECODE_AHEAD_SECS: u64 = 1;
+
+/// The frame to seek to is searched for by bisection until it is known to be within this many
+/// bytes, then the blocks in between are decoded.
+const BISECT_SPAN: u64 = 64 * 1024;
+
 /// Decoder for the Flac format.
 pub struct FlacDecoder<R>
 where
     R: Read + Seek,
 {
-    reader: FlacReader<R>,
+    frames: Frames<R>,
     current_block: Vec<i32>,
     current_block_channel_len: usize,
     current_block_off: usize,
+    // Position of the first sample of `current_block`, in inter-channel samples.
+    current_block_time: u64,
     bits_per_sample: u32,
     sample_rate: u32,
     channels: u16,
     samples: Option<u64>,
+    max_block_size: u16,
+    // Largest frame of the stream in bytes, if known.
+    max_frame_size: Option<u32>,
+    metadata: Metadata,
+    // Time and byte offset of the frames listed in the `SEEKTABLE` block.
+    seek_points: Vec<(u64, u64)>,
+    // Byte offset of the first frame, if the metadata blocks could be read.
+    first_frame: Option<u64>,
+    resync: Resync,
+    error: Option<DecoderError>,
+}
+
+/// Where the frames are read from.
+enum Frames<R>
+where
+    R: Read,
+{
+    Stream(FlacReader<R>),
+    /// Frames read from the middle of the stream, after damaged data or a seek.
+    Resynced(FrameReader<BufferedReader<R>>),
+    /// Only while switching from one to the other.
+    None,
 }
 
 impl<R> FlacDecoder<R>
@@ -27,29 +71,174 @@ where
     R: Read + Seek,
 {
     /// Attempts to decode the data as Flac.
-    pub fn new(mut data: R) -> Result<FlacDecoder<R>, R> {
-        if !is_flac(data.by_ref()) {
-            return Err(data);
-        }
-
-        let reader = FlacReader::new(data).unwrap();
+    pub fn new(mut data: R, resync: Resync) -> Result<FlacDecoder<R>, DecoderError> {
+        let extra = read_extra_metadata(data.by_ref())?;
+        let reader = FlacReader::new(data)?;
         let spec = reader.streaminfo();
 
+        let mut metadata = Metadata::default();
+        metadata.push_vorbis_comments(reader.tags());
+        for picture in extra.pictures {
+            metadata.push_picture(picture);
+        }
+
         Ok(FlacDecoder {
-            reader,
+            frames: Frames::Stream(reader),
             current_block: Vec::with_capacity(
                 spec.max_block_size as usize * spec.channels as usize,
             ),
             current_block_channel_len: 1,
             current_block_off: 0,
+            current_block_time: 0,
             bits_per_sample: spec.bits_per_sample,
             sample_rate: spec.sample_rate,
             channels: spec.channels as u16,
             samples: spec.samples,
+            max_block_size: spec.max_block_size,
+            max_frame_size: spec.max_frame_size,
+            metadata,
+            seek_points: extra.seek_points,
+            first_frame: extra.first_frame,
+            resync,
+            error: None,
         })
     }
     pub fn into_inner(self) -> R {
-        self.reader.into_inner()
+        match self.frames {
+            Frames::Stream(reader) => reader.into_inner(),
+            Frames::Resynced(frames) => frames.into_inner().into_inner(),
+            Frames::None => unreachable!(),
+        }
+    }
+
+    pub fn resync(&self) -> &Resync {
+        &self.resync
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        self.error.take()
+    }
+
+    /// Returns the current playback position, in inter-channel samples.
+    pub fn current_position(&self) -> u64 {
+        self.current_block_time + (self.current_block_off / self.channels as usize) as u64
+    }
+
+    /// Converts a duration into a position in inter-channel samples.
+    pub fn position_of(&self, pos: Duration) -> u64 {
+        (pos.as_secs_f64() * self.sample_rate as f64) as u64
+    }
+
+    /// Loads the next block. Returns `false` at the end of the stream.
+    ///
+    /// When resynchronizing, a block that cannot be decoded is skipped along with the data up to
+    /// the next valid one.
+    fn load_next_block(&mut self) -> Result<bool, claxon::Error> {
+        let next_time = self.next_block_time();
+        self.current_block_off = 0;
+        let buffer = mem::replace(&mut self.current_block, Vec::new());
+        let block = match self.read_next_block(buffer) {
+            Err(claxon::Error::FormatError(_)) if self.resync.enabled => {
+                self.skip_to_block(next_time)?
+            }
+            result => result?,
+        };
+        match block {
+            Some(block) => {
+                self.current_block_channel_len = (block.len() / block.channels()) as usize;
+                self.current_block_time = block.time();
+                self.current_block = block.into_buffer();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the time of the block after the current one.
+    fn next_block_time(&self) -> u64 {
+        // Blocks follow each other, so the next one starts where the current one ends.
+        if self.current_block.is_empty() {
+            self.current_block_time
+        } else {
+            self.current_block_time + self.current_block_channel_len as u64
+        }
+    }
+
+    fn read_next_block(&mut self, buffer: Vec<i32>) -> Result<Option<Block>, claxon::Error> {
+        match &mut self.frames {
+            Frames::Stream(reader) => reader.blocks().read_next_or_eof(buffer),
+            Frames::Resynced(frames) => frames.read_next_or_eof(buffer),
+            Frames::None => unreachable!(),
+        }
+    }
+
+    /// Skips to the first valid block that starts at `time` or later, then reads it.
+    fn skip_to_block(&mut self, time: u64) -> Result<Option<Block>, claxon::Error> {
+        let mut data = self.take_data();
+        let found = data
+            .stream_position()
+            .and_then(|pos| self.find_block_read_at(data.by_ref(), pos, time));
+        self.frames = Frames::Resynced(FrameReader::new(BufferedReader::new(data)));
+
+        let block_len = self.max_block_size.max(1) as u64;
+        match found? {
+            Some(found) => {
+                self.resync.skip(((found - time) / block_len).max(1));
+                self.read_next_block(Vec::new())
+            }
+            None => {
+                // The damaged data goes on until the end of the stream.
+                let left = self
+                    .samples
+                    .map_or(0, |samples| samples.saturating_sub(time));
+                self.resync.skip(left.div_ceil(block_len).max(1));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Moves the data to the last frame that starts at `time` or before, so that its block is
+    /// the next one read.
+    ///
+    /// If that fails, the data is moved back to the block that was going to be read next.
+    fn move_to_frame(&mut self, first_frame: u64, time: u64) -> Result<(), SeekError> {
+        let mut data = self.take_data();
+        let found = data.stream_position().and_then(|read_pos| {
+            let found = locate_frame(data.by_ref(), &self.seek_points, first_frame, time);
+            if found.is_err() {
+                let _ = self.find_block_read_at(data.by_ref(), read_pos, self.next_block_time());
+            }
+            found
+        });
+        self.frames = Frames::Resynced(FrameReader::new(BufferedReader::new(data)));
+
+        self.current_block_time = found.map_err(SeekError::Io)?;
+        self.current_block.clear();
+        self.current_block_off = 0;
+        Ok(())
+    }
+
+    /// Finds the first valid block that starts at `time` or later, around the position `pos` the
+    /// frames were read up to, then moves the data to it.
+    fn find_block_read_at(&self, data: &mut R, pos: u64, time: u64) -> io::Result<Option<u64>> {
+        // The last block may have been read well past its end, but not further than the largest
+        // frame.
+        let backtrack = SYNC_BACKTRACK + self.max_frame_size.unwrap_or(u16::MAX as u32) as u64;
+        find_block(data, pos.saturating_sub(backtrack), time)
+    }
+
+    /// Takes the data out of the frame reader, which has to be replaced.
+    fn take_data(&mut self) -> R {
+        match mem::replace(&mut self.frames, Frames::None) {
+            Frames::Stream(reader) => reader.into_inner(),
+            Frames::Resynced(frames) => frames.into_inner().into_inner(),
+            Frames::None => unreachable!(),
+        }
     }
 }
 
@@ -79,16 +268,51 @@ where
         self.samples
             .map(|s| Duration::from_micros(s * 1_000_000 / self.sample_rate as u64))
     }
+
+    /// Seeks to the block containing `pos`, then skips the samples before `pos` in that block.
+    ///
+    /// Claxon cannot seek, so the frame of that block is searched for in the data: from the
+    /// closest points of the `SEEKTABLE` block if there is one, by bisection otherwise. Short
+    /// seeks forward decode the blocks in between instead.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let target = self.position_of(pos);
+        let current = self.current_position();
+        let ahead = target.saturating_sub(current);
+        if target < current || ahead >= DECODE_AHEAD_SECS * self.sample_rate as u64 {
+            match self.first_frame {
+                Some(first_frame) => self.move_to_frame(first_frame, target)?,
+                None if target < current => {
+                    return Err(SeekError::NotSupported {
+                        underlying_source: std::any::type_name::<Self>(),
+                    })
+                }
+                None => (),
+            }
+        }
+
+        loop {
+            let block_end = self.current_block_time + self.current_block_channel_len as u64;
+            if self.current_block_off < self.current_block.len() && target < block_end {
+                let offset = (target - self.current_block_time) as usize * self.channels as usize;
+                self.current_block_off = offset;
+                return Ok(());
+            }
+
+            if !self.load_next_block().map_err(SeekError::ClaxonDecoder)? {
+                return Ok(());
+            }
+        }
+    }
 }
 
 impl<R> Iterator for FlacDecoder<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
         loop {
             if self.current_block_off < self.current_block.len() {
                 // Read from current block.
@@ -97,40 +321,218 @@ where
                     + self.current_block_off / self.channels as usize;
                 let raw_val = self.current_block[real_offset];
                 self.current_block_off += 1;
-                let real_val = match self.bits_per_sample.cmp(&16) {
-                    Ordering::Less => (raw_val << (16 - self.bits_per_sample)) as i16,
-                    Ordering::Equal => raw_val as i16,
-                    Ordering::Greater => (raw_val >> (self.bits_per_sample - 16)) as i16,
-                };
-                return Some(real_val as i16);
+                return Some(int_to_f32(raw_val, self.bits_per_sample));
             }
 
             // Load the next block.
-            self.current_block_off = 0;
-            let buffer = mem::replace(&mut self.current_block, Vec::new());
-            match self.reader.blocks().read_next_or_eof(buffer) {
-                Ok(Some(block)) => {
-                    self.current_block_channel_len = (block.len() / block.channels()) as usize;
-                    self.current_block = block.into_buffer();
+            match self.load_next_block() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(error) => {
+                    self.error = Some(error.into());
+                    return None;
                 }
-                _ => return None,
             }
         }
     }
 }
 
-/// Returns true if the stream contains Flac data, then resets it to where it was.
-fn is_flac<R>(mut data: R) -> bool
+/// Finds the first block that can be decoded, starting at `start` or later in the data and at
+/// `time` or later in the stream, then moves the data to it. Returns the time of the block, or
+/// `None` if there is none.
+fn find_block<R>(mut data: R, start: u64, time: u64) -> io::Result<Option<u64>>
 where
     R: Read + Seek,
 {
-    let stream_pos = data.seek(SeekFrom::Current(0)).unwrap();
+    let mut chunk = Vec::with_capacity(SYNC_SEARCH_CHUNK);
+    let mut chunk_start = start;
+    loop {
+        data.seek(SeekFrom::Start(chunk_start))?;
+        chunk.clear();
+        data.by_ref()
+            .take(SYNC_SEARCH_CHUNK as u64)
+            .read_to_end(&mut chunk)?;
+
+        // Frames start with a 14 bits sync code, a reserved bit and the blocking strategy bit.
+        for offset in 0..chunk.len().saturating_sub(1) {
+            if chunk[offset] != 0xff || chunk[offset + 1] & 0xfe != 0xf8 {
+                continue;
+            }
+            // A sync code can be found by chance, but the CRCs of a whole frame cannot.
+            let frame_start = chunk_start + offset as u64;
+            data.seek(SeekFrom::Start(frame_start))?;
+            let mut frames = FrameReader::new(BufferedReader::new(data.by_ref()));
+            match frames.read_next_or_eof(Vec::new()) {
+                Ok(Some(block)) if block.time() >= time => {
+                    data.seek(SeekFrom::Start(frame_start))?;
+                    return Ok(Some(block.time()));
+                }
+                Err(claxon::Error::IoError(err)) if err.kind() != io::ErrorKind::UnexpectedEof => {
+                    return Err(err)
+                }
+                _ => (),
+            }
+        }
 
-    if FlacReader::new(data.by_ref()).is_err() {
-        data.seek(SeekFrom::Start(stream_pos)).unwrap();
-        return false;
+        if chunk.len() < SYNC_SEARCH_CHUNK {
+            return Ok(None);
+        }
+        // The last byte may be the start of a sync code.
+        chunk_start += chunk.len() as u64 - 1;
     }
+}
 
-    data.seek(SeekFrom::Start(stream_pos)).unwrap();
-    true
+/// Finds the last frame that starts at `time` or before, then moves the data to it. Returns the
+/// time of the frame.
+///
+/// The frame is searched for between the seek points around `time`, or between the first frame
+/// and the end of the data if there are none.
+fn locate_frame<R>(
+    mut data: R,
+    seek_points: &[(u64, u64)],
+    first_frame: u64,
+    time: u64,
+) -> io::Result<u64>
+where
+    R: Read + Seek,
+{
+    let (mut start, mut start_time) = (first_frame, 0);
+    let mut end = data.seek(SeekFrom::End(0))?;
+    for &(point_time, offset) in seek_points {
+        // Points that are out of order or past the end of the data are ignored.
+        if offset < start || offset >= end {
+            continue;
+        }
+        if point_time > time {
+            end = offset;
+            break;
+        }
+        start = offset;
+        start_time = point_time;
+    }
+
+    while end.saturating_sub(start) > BISECT_SPAN {
+        let middle = start + (end - start) / 2;
+        match find_block(data.by_ref(), middle, 0)? {
+            Some(frame_time) if frame_time <= time => {
+                start = data.stream_position()?;
+                start_time = frame_time;
+            }
+            _ => end = middle,
+        }
+    }
+
+    data.seek(SeekFrom::Start(start))?;
+    Ok(start_time)
+}
+
+/// Returns true if the stream starts with the Flac signature, then resets it to where it was.
+pub fn is_flac<R>(mut data: R) -> io::Result<bool>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+
+    let mut signature = [0; 4];
+    let is_flac = match data.read_exact(&mut signature) {
+        Ok(()) => &signature == b"fLaC",
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err),
+    };
+
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(is_flac)
+}
+
+/// What claxon skips in the metadata blocks.
+#[derive(Default)]
+struct ExtraMetadata {
+    pictures: Vec<Picture>,
+    /// Time and byte offset of the frames listed in the `SEEKTABLE` block.
+    seek_points: Vec<(u64, u64)>,
+    /// Byte offset of the first frame, right after the metadata blocks.
+    first_frame: Option<u64>,
+}
+
+/// Reads the `PICTURE` and `SEEKTABLE` metadata blocks, which claxon skips, then resets the
+/// stream to where it was.
+fn read_extra_metadata<R>(mut data: R) -> io::Result<ExtraMetadata>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+
+    let mut extra = ExtraMetadata::default();
+    let _ = read_metadata_blocks(data.by_ref(), &mut extra);
+
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(extra)
+}
+
+fn read_metadata_blocks<R>(mut data: R, extra: &mut ExtraMetadata) -> io::Result<()>
+where
+    R: Read + Seek,
+{
+    let mut header = [0; 4];
+    data.read_exact(&mut header)?;
+    if &header != b"fLaC" {
+        return Ok(());
+    }
+
+    let mut seek_points = Vec::new();
+    loop {
+        data.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        match header[0] & 0x7f {
+            3 => {
+                // Each point takes 18 bytes: the time of the frame, its offset from the first
+                // frame and its length in samples. Placeholders have all bits of the time set.
+                for point in read_block(data.by_ref(), len)?.chunks_exact(18) {
+                    let read_u64 = |bytes: &[u8]| {
+                        bytes
+                            .iter()
+                            .fold(0, |value, &byte| value << 8 | byte as u64)
+                    };
+                    let time = read_u64(&point[..8]);
+                    if time != u64::MAX {
+                        seek_points.push((time, read_u64(&point[8..16])));
+                    }
+                }
+            }
+            6 => {
+                let block = read_block(data.by_ref(), len)?;
+                extra.pictures.extend(metadata::parse_flac_picture(&block));
+            }
+            _ => {
+                data.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+
+        // The high bit marks the last metadata block.
+        if header[0] & 0x80 != 0 {
+            let first_frame = data.stream_position()?;
+            extra.first_frame = Some(first_frame);
+            extra.seek_points = seek_points
+                .into_iter()
+                .filter_map(|(time, offset)| Some((time, offset.checked_add(first_frame)?)))
+                .collect();
+            return Ok(());
+        }
+    }
+}
+
+/// Reads the content of a metadata block of `len` bytes.
+///
+/// The buffer grows as the bytes are read, since the length comes from the file and may be far
+/// larger than the file itself.
+fn read_block<R>(data: &mut R, len: usize) -> io::Result<Vec<u8>>
+where
+    R: Read,
+{
+    let mut block = Vec::new();
+    data.take(len as u64).read_to_end(&mut block)?;
+    if block.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(block)
 }
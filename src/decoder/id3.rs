@@ -0,0 +1,347 @@
+//! Reading of the ID3v1 and ID3v2 tags found in MP3 files.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::metadata::{Metadata, Picture};
+
+/// Reads the ID3v2 tag at the current position and the ID3v1 tag at the end of the data.
+///
+/// Afterwards the data is positioned right after the ID3v2 tag, where the audio starts. Tags that
+/// cannot be parsed are ignored.
+pub fn read_tags<R>(mut data: R) -> io::Result<Metadata>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    let mut metadata = Metadata::default();
+
+    let audio_start = match read_id3v2(data.by_ref(), &mut metadata) {
+        Ok(Some(len)) => stream_pos + len,
+        _ => stream_pos,
+    };
+    let _ = read_id3v1(data.by_ref(), &mut metadata);
+
+    data.seek(SeekFrom::Start(audio_start))?;
+    Ok(metadata)
+}
+
+/// Reads an ID3v2 tag and returns its length in bytes, or `None` if there is no tag.
+fn read_id3v2<R>(mut data: R, metadata: &mut Metadata) -> io::Result<Option<u64>>
+where
+    R: Read,
+{
+    let mut header = [0; 10];
+    data.read_exact(&mut header)?;
+    if &header[0..3] != b"ID3" {
+        return Ok(None);
+    }
+
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]) as usize;
+    let footer_len = if flags & 0x10 != 0 { 10 } else { 0 };
+
+    // The buffer grows as the tag is read, since its size may be far larger than the data.
+    let mut tag = Vec::new();
+    data.take(size as u64).read_to_end(&mut tag)?;
+    if tag.len() < size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if version < 4 && flags & 0x80 != 0 {
+        tag = remove_unsynchronisation(&tag);
+    }
+
+    let mut frames = &tag[..];
+    if flags & 0x40 != 0 && frames.len() >= 4 {
+        // The extended header only contains information about the tag itself.
+        let extended_len = match version {
+            3 => u32::from_be_bytes([frames[0], frames[1], frames[2], frames[3]]) as usize + 4,
+            _ => syncsafe(&frames[0..4]) as usize,
+        };
+        frames = frames.get(extended_len..).unwrap_or(&[]);
+    }
+    if (2..=4).contains(&version) {
+        read_frames(version, frames, metadata);
+    }
+
+    Ok(Some((10 + size + footer_len) as u64))
+}
+
+/// Reads the frames of an ID3v2 tag.
+fn read_frames(version: u8, mut frames: &[u8], metadata: &mut Metadata) {
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+
+    while frames.len() >= header_len && frames[0] != 0 {
+        let id = String::from_utf8_lossy(&frames[..id_len]).into_owned();
+        let size = match version {
+            2 => u32::from_be_bytes([0, frames[3], frames[4], frames[5]]),
+            3 => u32::from_be_bytes([frames[4], frames[5], frames[6], frames[7]]),
+            _ => syncsafe(&frames[4..8]),
+        } as usize;
+        let flags = if version == 2 {
+            0
+        } else {
+            u16::from_be_bytes([frames[8], frames[9]])
+        };
+
+        let body = match frames.get(header_len..header_len + size) {
+            Some(body) => body,
+            None => return,
+        };
+        frames = &frames[header_len + size..];
+
+        // Compressed and encrypted frames are skipped. Grouped frames start with a group id, and
+        // ID3v2.4 frames may start with their decoded length.
+        let (skipped, group, unsynchronised, data_len) = match version {
+            3 => (flags & 0x00c0 != 0, flags & 0x0020 != 0, false, false),
+            4 => (
+                flags & 0x000c != 0,
+                flags & 0x0040 != 0,
+                flags & 0x0002 != 0,
+                flags & 0x0001 != 0,
+            ),
+            _ => (false, false, false, false),
+        };
+        if skipped {
+            continue;
+        }
+        let prefix_len = if group { 1 } else { 0 } + if data_len { 4 } else { 0 };
+        let body = match body.get(prefix_len..) {
+            Some(body) => body,
+            None => continue,
+        };
+        if unsynchronised {
+            read_frame(&id, &remove_unsynchronisation(body), metadata);
+        } else {
+            read_frame(&id, body, metadata);
+        }
+    }
+}
+
+/// Reads the content of a single ID3v2 frame.
+fn read_frame(id: &str, body: &[u8], metadata: &mut Metadata) {
+    let (&encoding, body) = match body.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    match id {
+        "TXXX" | "TXX" => {
+            let (name, value) = split_terminated(encoding, body);
+            metadata.push_tag(&decode_text(encoding, name), &decode_text(encoding, value));
+        }
+        "COMM" | "COM" => {
+            // Skip the language code and the short description.
+            let (_, text) = split_terminated(encoding, body.get(3..).unwrap_or(&[]));
+            metadata.push_tag("COMMENT", &decode_text(encoding, text));
+        }
+        "APIC" => {
+            let (mime_type, rest) = split_terminated(0, body);
+            if let Some((&picture_type, rest)) = rest.split_first() {
+                let (description, data) = split_terminated(encoding, rest);
+                metadata.push_picture(Picture {
+                    picture_type,
+                    mime_type: decode_text(0, mime_type),
+                    description: decode_text(encoding, description),
+                    data: data.to_vec(),
+                });
+            }
+        }
+        "PIC" if body.len() >= 4 => {
+            let mime_type = match &body[0..3] {
+                b"PNG" => "image/png".to_owned(),
+                b"JPG" => "image/jpeg".to_owned(),
+                other => format!("image/{}", String::from_utf8_lossy(other).to_lowercase()),
+            };
+            let (description, data) = split_terminated(encoding, &body[4..]);
+            metadata.push_picture(Picture {
+                picture_type: body[3],
+                mime_type,
+                description: decode_text(encoding, description),
+                data: data.to_vec(),
+            });
+        }
+        _ if id.starts_with('T') => {
+            let name = match id {
+                "TIT2" | "TT2" => "TITLE",
+                "TPE1" | "TP1" => "ARTIST",
+                "TALB" | "TAL" => "ALBUM",
+                "TPE2" | "TP2" => "ALBUMARTIST",
+                "TRCK" | "TRK" => "TRACKNUMBER",
+                "TPOS" | "TPA" => "DISCNUMBER",
+                "TYER" | "TYE" | "TDRC" => "DATE",
+                "TCON" | "TCO" => "GENRE",
+                "TCOM" | "TCM" => "COMPOSER",
+                "TCOP" | "TCR" => "COPYRIGHT",
+                "TSSE" | "TSS" => "ENCODER",
+                other => other,
+            };
+            // ID3v2.4 separates multiple values with null characters.
+            for value in decode_text(encoding, body).split('\0') {
+                metadata.push_tag(name, value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Reads an ID3v1 tag from the last 128 bytes of the data.
+///
+/// Its fields are only used when the ID3v2 tag did not already provide them.
+fn read_id3v1<R>(mut data: R, metadata: &mut Metadata) -> io::Result<()>
+where
+    R: Read + Seek,
+{
+    let mut tag = [0; 128];
+    data.seek(SeekFrom::End(-128))?;
+    data.read_exact(&mut tag)?;
+    if &tag[0..3] != b"TAG" {
+        return Ok(());
+    }
+
+    // ID3v1.1 stores the track number at the end of the comment.
+    let (comment, track) = if tag[125] == 0 && tag[126] != 0 {
+        (&tag[97..125], Some(tag[126]))
+    } else {
+        (&tag[97..127], None)
+    };
+    let track = track.map(|track| track.to_string());
+
+    let fields = [
+        ("TITLE", decode_text(0, &tag[3..33])),
+        ("ARTIST", decode_text(0, &tag[33..63])),
+        ("ALBUM", decode_text(0, &tag[63..93])),
+        ("DATE", decode_text(0, &tag[93..97])),
+        ("COMMENT", decode_text(0, comment)),
+        ("TRACKNUMBER", track.unwrap_or_default()),
+    ];
+    for (name, value) in fields.iter() {
+        if metadata.get(name).is_none() {
+            metadata.push_tag(name, value.trim_end_matches(&['\0', ' '][..]));
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a 28-bit integer stored in the low 7 bits of 4 bytes.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 7) | (byte & 0x7f) as u32)
+}
+
+/// Reverts the unsynchronisation scheme, which inserts a null byte after every `0xff`.
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = 0;
+    for &byte in data {
+        if !(previous == 0xff && byte == 0) {
+            output.push(byte);
+        }
+        previous = byte;
+    }
+    output
+}
+
+/// Splits a string terminated by a null character from the data that follows it.
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let end = match encoding {
+        // UTF-16 strings end with two null bytes, aligned on a character boundary.
+        1 | 2 => data
+            .chunks(2)
+            .position(|c| c == [0, 0])
+            .map(|position| (position * 2, position * 2 + 2)),
+        _ => data
+            .iter()
+            .position(|&b| b == 0)
+            .map(|position| (position, position + 1)),
+    };
+    match end {
+        Some((end, next)) => (&data[..end], &data[next..]),
+        None => (data, &[]),
+    }
+}
+
+/// Decodes a string using one of the ID3v2 text encodings.
+fn decode_text(encoding: u8, data: &[u8]) -> String {
+    let utf16 = |data: &[u8], big_endian: bool| {
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|c| {
+                if big_endian {
+                    u16::from_be_bytes([c[0], c[1]])
+                } else {
+                    u16::from_le_bytes([c[0], c[1]])
+                }
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    match encoding {
+        // ISO-8859-1, whose code points are the first 256 of Unicode.
+        0 => data.iter().map(|&b| b as char).collect(),
+        1 => match data {
+            [0xfe, 0xff, rest @ ..] => utf16(rest, true),
+            [0xff, 0xfe, rest @ ..] => utf16(rest, false),
+            _ => utf16(data, false),
+        },
+        2 => utf16(data, true),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn text_frame(id: &[u8], encoding: u8, text: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, encoding]);
+        frame.extend_from_slice(text);
+        frame
+    }
+
+    #[test]
+    fn id3v2_and_id3v1() {
+        let mut frames = text_frame(b"TIT2", 0, b"Caf\xe9");
+        frames.extend(text_frame(b"TPE1", 1, &[0xff, 0xfe, b'A', 0, b'B', 0]));
+        frames.extend(text_frame(b"TRCK", 3, b"7/9"));
+
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend_from_slice(&[0, 0, 0, frames.len() as u8]);
+        data.extend(frames);
+        data.extend_from_slice(b"audio");
+        let mut id3v1 = vec![0; 128];
+        id3v1[..3].copy_from_slice(b"TAG");
+        id3v1[3..8].copy_from_slice(b"Other");
+        id3v1[63..68].copy_from_slice(b"Album");
+        data.extend(id3v1);
+
+        let mut cursor = Cursor::new(data);
+        let metadata = read_tags(&mut cursor).unwrap();
+        assert_eq!(metadata.title(), Some("Café"));
+        assert_eq!(metadata.artist(), Some("AB"));
+        assert_eq!(metadata.album(), Some("Album"));
+        assert_eq!(metadata.track_number(), Some(7));
+
+        let mut audio = [0; 5];
+        cursor.read_exact(&mut audio).unwrap();
+        assert_eq!(&audio, b"audio");
+    }
+
+    #[test]
+    fn truncated_id3v2() {
+        // The tag claims to hold 256 MiB.
+        let mut data = b"ID3\x04\x00\x00\x7f\x7f\x7f\x7f".to_vec();
+        data.extend(text_frame(b"TIT2", 3, b"Title"));
+
+        let mut cursor = Cursor::new(data);
+        let metadata = read_tags(&mut cursor).unwrap();
+        assert_eq!(metadata.title(), None);
+        assert_eq!(cursor.position(), 0);
+    }
+}
@@ -0,0 +1,298 @@
+//! Tags, pictures and markers embedded in audio files.
+
+/// Tags, pictures and markers read from an audio file.
+///
+/// Tag names follow the Vorbis comment conventions (`TITLE`, `ARTIST`, `ALBUM`, `TRACKNUMBER`,
+/// `DATE`, `GENRE`, ...). Tags coming from other formats, such as ID3 or WAV `INFO` chunks, are
+/// translated to these names when there is an equivalent. Tag names are case-insensitive.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    tags: Vec<(String, String)>,
+    pictures: Vec<Picture>,
+    markers: Vec<Marker>,
+}
+
+/// A picture embedded in an audio file, such as the cover art of an album.
+#[derive(Clone, Debug)]
+pub struct Picture {
+    /// The kind of picture, using the ID3v2 `APIC` numbering. For example `3` is the front cover.
+    pub picture_type: u8,
+    /// MIME type of the picture data, for example `image/jpeg`.
+    pub mime_type: String,
+    /// Free-form description of the picture.
+    pub description: String,
+    /// The encoded picture.
+    pub data: Vec<u8>,
+}
+
+/// A named position in an audio file, such as a WAV cue point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Marker {
+    /// Position of the marker, in inter-channel samples from the start of the file.
+    pub position: u64,
+    /// Label of the marker, empty if it has none.
+    pub label: String,
+}
+
+/// Metadata of decoders that do not have any.
+pub(crate) static EMPTY: Metadata = Metadata {
+    tags: Vec::new(),
+    pictures: Vec::new(),
+    markers: Vec::new(),
+};
+
+impl Metadata {
+    /// Returns the title of the track.
+    #[inline]
+    pub fn title(&self) -> Option<&str> {
+        self.get("TITLE")
+    }
+
+    /// Returns the artist of the track.
+    #[inline]
+    pub fn artist(&self) -> Option<&str> {
+        self.get("ARTIST")
+    }
+
+    /// Returns the album the track belongs to.
+    #[inline]
+    pub fn album(&self) -> Option<&str> {
+        self.get("ALBUM")
+    }
+
+    /// Returns the position of the track in its album.
+    ///
+    /// Values such as `3/12` are understood as the track number followed by the track count.
+    pub fn track_number(&self) -> Option<u32> {
+        let value = self.get("TRACKNUMBER")?;
+        let digits = value.trim().split('/').next()?;
+        digits.trim().parse().ok()
+    }
+
+    /// Returns the loop points of the track, as the position of the first sample of the loop and
+    /// the position right after its last sample, if known. Positions are in inter-channel samples.
+    ///
+    /// They are read from the `LOOPSTART` tag and either the `LOOPLENGTH` or the `LOOPEND` tag.
+    /// WAV files store them in their `smpl` chunk instead, which is translated to these tags.
+    /// Loop points whose end does not fit in a `u64` are ignored.
+    pub fn loop_points(&self) -> Option<(u64, Option<u64>)> {
+        let read = |name| {
+            self.get(name)
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let start = read("LOOPSTART")?;
+        let end = match (read("LOOPLENGTH"), read("LOOPEND")) {
+            (Some(len), _) if len > 0 => Some(start.checked_add(len)?),
+            (_, Some(end)) if end > start => Some(end),
+            _ => None,
+        };
+        Some((start, end))
+    }
+
+    /// Returns the front cover if there is one, otherwise the first picture.
+    pub fn cover(&self) -> Option<&Picture> {
+        self.pictures
+            .iter()
+            .find(|picture| picture.picture_type == 3)
+            .or_else(|| self.pictures.first())
+    }
+
+    /// Returns the first value of a tag. The name is case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns all the values of a tag. The name is case-insensitive.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.tags
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns all the tags, in the order they appear in the file.
+    pub fn tags(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tags
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Returns all the embedded pictures.
+    #[inline]
+    pub fn pictures(&self) -> &[Picture] {
+        &self.pictures
+    }
+
+    /// Returns the markers, sorted by position.
+    ///
+    /// They are read from the `cue ` chunk of WAV files, with the labels of its `LIST` chunk of
+    /// type `adtl`.
+    #[inline]
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /// Returns true if no tag, no picture and no marker was found.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.pictures.is_empty() && self.markers.is_empty()
+    }
+
+    /// Adds a tag. Empty values are ignored.
+    pub(crate) fn push_tag(&mut self, name: &str, value: &str) {
+        let value = value.trim_end_matches('\0').trim();
+        if !value.is_empty() {
+            self.tags
+                .push((name.to_ascii_uppercase(), value.to_owned()));
+        }
+    }
+
+    /// Adds a picture.
+    #[allow(dead_code)]
+    pub(crate) fn push_picture(&mut self, picture: Picture) {
+        self.pictures.push(picture);
+    }
+
+    /// Adds a marker, keeping the markers sorted by position.
+    #[allow(dead_code)]
+    pub(crate) fn push_marker(&mut self, marker: Marker) {
+        let index = self
+            .markers
+            .partition_point(|other| other.position <= marker.position);
+        self.markers.insert(index, marker);
+    }
+
+    /// Adds a list of Vorbis comments.
+    ///
+    /// Pictures stored as base64 in `METADATA_BLOCK_PICTURE` comments are decoded as well.
+    #[cfg(any(feature = "flac", feature = "vorbis", feature = "opus"))]
+    pub(crate) fn push_vorbis_comments<'a, I>(&mut self, comments: I)
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        for (name, value) in comments {
+            if name.eq_ignore_ascii_case("METADATA_BLOCK_PICTURE") {
+                if let Some(picture) = decode_base64(value).and_then(|d| parse_flac_picture(&d)) {
+                    self.pictures.push(picture);
+                }
+            } else {
+                self.push_tag(name, value);
+            }
+        }
+    }
+}
+
+/// Parses the content of a FLAC `PICTURE` metadata block.
+#[cfg(any(feature = "flac", feature = "vorbis", feature = "opus"))]
+pub(crate) fn parse_flac_picture(data: &[u8]) -> Option<Picture> {
+    fn read_u32(data: &mut &[u8]) -> Option<u32> {
+        if data.len() < 4 {
+            return None;
+        }
+        let (value, rest) = data.split_at(4);
+        *data = rest;
+        Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+    }
+
+    fn read_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = read_u32(data)? as usize;
+        if data.len() < len {
+            return None;
+        }
+        let (value, rest) = data.split_at(len);
+        *data = rest;
+        Some(value)
+    }
+
+    let mut data = data;
+    let picture_type = read_u32(&mut data)?;
+    let mime_type = String::from_utf8_lossy(read_bytes(&mut data)?).into_owned();
+    let description = String::from_utf8_lossy(read_bytes(&mut data)?).into_owned();
+    // Width, height, color depth and number of colors.
+    for _ in 0..4 {
+        read_u32(&mut data)?;
+    }
+    let picture = read_bytes(&mut data)?.to_vec();
+
+    Some(Picture {
+        picture_type: picture_type as u8,
+        mime_type,
+        description,
+        data: picture,
+    })
+}
+
+/// Decodes standard base64, as used by `METADATA_BLOCK_PICTURE` comments.
+#[cfg(any(feature = "flac", feature = "vorbis", feature = "opus"))]
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for byte in input.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+
+    #[test]
+    #[cfg(any(feature = "flac", feature = "vorbis", feature = "opus"))]
+    fn picture_from_vorbis_comment() {
+        // A 1 byte front cover of type "image/png" with description "a".
+        let mut metadata = Metadata::default();
+        metadata.push_vorbis_comments(vec![(
+            "METADATA_BLOCK_PICTURE",
+            "AAAAAwAAAAlpbWFnZS9wbmcAAAABYQAAAAAAAAAAAAAAAAAAAAAAAAABKg==",
+        )]);
+
+        let cover = metadata.cover().unwrap();
+        assert_eq!(cover.picture_type, 3);
+        assert_eq!(cover.mime_type, "image/png");
+        assert_eq!(cover.description, "a");
+        assert_eq!(cover.data, [42]);
+    }
+
+    #[test]
+    fn loop_points() {
+        let mut metadata = Metadata::default();
+        assert_eq!(metadata.loop_points(), None);
+        metadata.push_tag("LoopStart", "1000");
+        assert_eq!(metadata.loop_points(), Some((1000, None)));
+        metadata.push_tag("LOOPEND", "5000");
+        assert_eq!(metadata.loop_points(), Some((1000, Some(5000))));
+        metadata.push_tag("LOOPLENGTH", "2000");
+        assert_eq!(metadata.loop_points(), Some((1000, Some(3000))));
+    }
+
+    #[test]
+    fn loop_points_out_of_range() {
+        let mut metadata = Metadata::default();
+        metadata.push_tag("LOOPSTART", &u64::MAX.to_string());
+        assert_eq!(metadata.loop_points(), Some((u64::MAX, None)));
+        metadata.push_tag("LOOPLENGTH", "1");
+        assert_eq!(metadata.loop_points(), None);
+    }
+}
@@ -1,33 +1,89 @@
 //! Decodes samples from an audio file.
 
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 #[allow(unused_imports)]
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::mem;
+#[cfg(any(feature = "flac", feature = "vorbis", feature = "mp3"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::channel_layout::ChannelLayout;
+use crate::source::SeekError;
 use crate::Source;
 
+pub use self::builder::{DecoderBuilder, Format};
+pub use self::custom::{register_format, CustomDecoder, CustomFormat, ReadSeek};
+pub use self::metadata::{Marker, Metadata, Picture};
+pub use self::unseekable::Unseekable;
+
+#[cfg(feature = "aiff")]
+mod aiff;
+mod builder;
+mod custom;
 #[cfg(feature = "flac")]
 mod flac;
 #[cfg(feature = "mp3")]
+mod id3;
+mod metadata;
+#[cfg(feature = "mp3")]
 mod mp3;
+#[cfg(feature = "mp3")]
+mod mpeg;
+#[cfg(any(feature = "vorbis", feature = "opus"))]
+mod ogg_page;
+#[cfg(feature = "opus")]
+mod opus;
+mod unseekable;
 #[cfg(feature = "vorbis")]
 mod vorbis;
 #[cfg(feature = "wav")]
 mod wav;
+#[cfg(feature = "wav")]
+mod wav_codec;
 
 /// Source of audio samples from decoding a file.
 ///
-/// Supports MP3, WAV, Vorbis and Flac.
-pub struct Decoder<R>(DecoderImpl<R>)
+/// Supports MP3, WAV, AIFF, Vorbis and Flac, as well as Opus when the `opus` feature is enabled.
+/// Other formats can be added with [`register_format`].
+///
+/// Samples are produced as `f32`s, so files with a bit depth above 16 bits keep their full
+/// precision. Use [`convert_samples`](Source::convert_samples) to get another sample type.
+///
+/// If the data turns out to be corrupted or truncated while playing, the decoder ends early. The
+/// error is then available through [`error`](Decoder::error) and passed to the callback set with
+/// [`set_error_callback`](Decoder::set_error_callback). MP3, Flac and Vorbis decoders can
+/// instead skip the damaged parts of the data, see [`DecoderBuilder::with_resync`].
+pub struct Decoder<R>
 where
-    R: Read + Seek;
+    R: Read + Seek,
+{
+    inner: DecoderImpl<R>,
+    errors: ErrorReporter,
+    // False when the data comes from a reader that cannot seek.
+    seekable: bool,
+}
 
-pub struct LoopedDecoder<R>(DecoderImpl<R>)
+/// Source of audio samples from decoding a file, which starts over once it reaches its end.
+///
+/// By default the whole file is looped. Loop points, in inter-channel samples, restrict the loop
+/// to a section of the file that follows an intro. They are read from the `LOOPSTART` and
+/// `LOOPLENGTH` tags or from the `smpl` chunk of WAV files, and can be changed with
+/// [`set_loop_points`](LoopedDecoder::set_loop_points).
+pub struct LoopedDecoder<R>
 where
-    R: Read + Seek;
+    R: Read + Seek,
+{
+    inner: DecoderImpl<R>,
+    errors: ErrorReporter,
+    loop_start: u64,
+    loop_end: Option<u64>,
+    // Number of samples since the start of the file.
+    position: u64,
+}
 
 enum DecoderImpl<R>
 where
@@ -35,12 +91,17 @@ where
 {
     #[cfg(feature = "wav")]
     Wav(wav::WavDecoder<R>),
+    #[cfg(feature = "aiff")]
+    Aiff(aiff::AiffDecoder<R>),
     #[cfg(feature = "vorbis")]
     Vorbis(vorbis::VorbisDecoder<R>),
     #[cfg(feature = "flac")]
     Flac(flac::FlacDecoder<R>),
     #[cfg(feature = "mp3")]
     Mp3(mp3::Mp3Decoder<R>),
+    #[cfg(feature = "opus")]
+    Opus(opus::OpusDecoder<R>),
+    Custom(custom::CustomSamples<R>),
     None(::std::marker::PhantomData<R>),
 }
 
@@ -50,125 +111,527 @@ where
 {
     /// Builds a new decoder.
     ///
-    /// Attempts to automatically detect the format of the source of data.
-    #[allow(unused_variables)]
+    /// Attempts to automatically detect the format of the source of data. Use a
+    /// [`DecoderBuilder`] to give hints about the format or to restrict the formats that are
+    /// tried.
+    #[inline]
     pub fn new(data: R) -> Result<Decoder<R>, DecoderError> {
-        #[cfg(feature = "wav")]
-        let data = match wav::WavDecoder::new(data) {
-            Err(data) => data,
-            Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Wav(decoder)));
-            }
-        };
-
-        #[cfg(feature = "flac")]
-        let data = match flac::FlacDecoder::new(data) {
-            Err(data) => data,
-            Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Flac(decoder)));
-            }
-        };
-
-        #[cfg(feature = "vorbis")]
-        let data = match vorbis::VorbisDecoder::new(data) {
-            Err(data) => data,
-            Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Vorbis(decoder)));
-            }
-        };
-
-        #[cfg(feature = "mp3")]
-        let data = match mp3::Mp3Decoder::new(data) {
-            Err(data) => data,
-            Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Mp3(decoder)));
-            }
-        };
-
-        Err(DecoderError::UnrecognizedFormat)
+        DecoderBuilder::new().build(data)
     }
+
     pub fn new_looped(data: R) -> Result<LoopedDecoder<R>, DecoderError> {
         Self::new(data).map(LoopedDecoder::new)
     }
 
     /// Builds a new decoder from wav data.
     #[cfg(feature = "wav")]
-    pub fn new_wav(data: R) -> Result<Decoder<R>, DecoderError> {
-        match wav::WavDecoder::new(data) {
-            Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Wav(decoder))),
+    pub fn new_wav(mut data: R) -> Result<Decoder<R>, DecoderError> {
+        if !wav::is_wave(data.by_ref())? {
+            return Err(DecoderError::UnrecognizedFormat);
         }
+        let decoder = wav::WavDecoder::new(data)?;
+        Ok(Decoder::from_impl(DecoderImpl::Wav(decoder)))
+    }
+
+    /// Builds a new decoder from AIFF or AIFF-C data.
+    #[cfg(feature = "aiff")]
+    pub fn new_aiff(mut data: R) -> Result<Decoder<R>, DecoderError> {
+        if !aiff::is_aiff(data.by_ref())? {
+            return Err(DecoderError::UnrecognizedFormat);
+        }
+        let decoder = aiff::AiffDecoder::new(data)?;
+        Ok(Decoder::from_impl(DecoderImpl::Aiff(decoder)))
     }
 
     /// Builds a new decoder from flac data.
     #[cfg(feature = "flac")]
-    pub fn new_flac(data: R) -> Result<Decoder<R>, DecoderError> {
-        match flac::FlacDecoder::new(data) {
-            Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Flac(decoder))),
+    pub fn new_flac(mut data: R) -> Result<Decoder<R>, DecoderError> {
+        if !flac::is_flac(data.by_ref())? {
+            return Err(DecoderError::UnrecognizedFormat);
         }
+        let decoder = flac::FlacDecoder::new(data, Resync::default())?;
+        Ok(Decoder::from_impl(DecoderImpl::Flac(decoder)))
     }
 
     /// Builds a new decoder from vorbis data.
     #[cfg(feature = "vorbis")]
-    pub fn new_vorbis(data: R) -> Result<Decoder<R>, DecoderError> {
-        match vorbis::VorbisDecoder::new(data) {
-            Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Vorbis(decoder))),
+    pub fn new_vorbis(mut data: R) -> Result<Decoder<R>, DecoderError> {
+        if !vorbis::is_vorbis(data.by_ref())? {
+            return Err(DecoderError::UnrecognizedFormat);
         }
+        let decoder = vorbis::VorbisDecoder::new(data, Resync::default())?;
+        Ok(Decoder::from_impl(DecoderImpl::Vorbis(decoder)))
     }
 
     /// Builds a new decoder from mp3 data.
     #[cfg(feature = "mp3")]
-    pub fn new_mp3(data: R) -> Result<Decoder<R>, DecoderError> {
-        match mp3::Mp3Decoder::new(data) {
-            Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Mp3(decoder))),
+    pub fn new_mp3(mut data: R) -> Result<Decoder<R>, DecoderError> {
+        if !mp3::is_mp3(data.by_ref())? {
+            return Err(DecoderError::UnrecognizedFormat);
+        }
+        let decoder = mp3::Mp3Decoder::new(data, Resync::default())?;
+        Ok(Decoder::from_impl(DecoderImpl::Mp3(decoder)))
+    }
+
+    /// Builds a new decoder from ogg/opus data.
+    ///
+    /// Opus streams are always decoded at 48 kHz.
+    #[cfg(feature = "opus")]
+    pub fn new_opus(mut data: R) -> Result<Decoder<R>, DecoderError> {
+        if !opus::is_opus(data.by_ref())? {
+            return Err(DecoderError::UnrecognizedFormat);
         }
+        let decoder = opus::OpusDecoder::new(data)?;
+        Ok(Decoder::from_impl(DecoderImpl::Opus(decoder)))
     }
 }
 
-impl<R> LoopedDecoder<R>
+impl<R> Decoder<R>
 where
-    R: Read + Seek + Send,
+    R: Read + Seek,
 {
-    fn new(decoder: Decoder<R>) -> LoopedDecoder<R> {
-        Self(decoder.0)
+    #[inline]
+    fn from_impl(inner: DecoderImpl<R>) -> Decoder<R> {
+        Decoder {
+            inner,
+            errors: ErrorReporter::default(),
+            seekable: true,
+        }
+    }
+
+    /// Returns the error that stopped the decoding, if any.
+    ///
+    /// Decoding stops early when the data turns out to be corrupted or truncated.
+    #[inline]
+    pub fn error(&self) -> Option<&DecoderError> {
+        self.errors.error.as_ref()
+    }
+
+    /// Sets a function that is called with the error that stops the decoding, if any.
+    ///
+    /// This is useful to find out about errors once the decoder has been handed over to a `Sink`.
+    /// The function is called from the thread that reads the samples, which is usually the audio
+    /// thread, so it should return quickly.
+    pub fn set_error_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(DecoderError) + Send + 'static,
+    {
+        self.errors.callback = Some(Box::new(callback));
+    }
+
+    /// Returns the tags and pictures found in the file.
+    ///
+    /// They are read from Vorbis comments for Vorbis, Opus and Flac, from ID3 tags for MP3, from
+    /// `INFO` chunks for WAV and from text chunks for AIFF.
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    /// Returns the speaker of each channel.
+    ///
+    /// It is read from the channel mask of WAV files, and follows the order defined by the
+    /// format otherwise.
+    #[inline]
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.inner.channel_layout()
+    }
+
+    /// Returns the number of frames of damaged data that have been skipped so far.
+    ///
+    /// This is always zero unless the decoder has been built with
+    /// [`DecoderBuilder::with_resync`].
+    #[inline]
+    pub fn skipped_frames(&self) -> u64 {
+        self.inner.skipped_frames()
     }
 }
 
-impl<R> Iterator for Decoder<R>
+impl<R> DecoderImpl<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    #[inline]
+    fn metadata(&self) -> &Metadata {
+        match self {
+            #[cfg(feature = "wav")]
+            DecoderImpl::Wav(source) => source.metadata(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.metadata(),
+            #[cfg(feature = "vorbis")]
+            DecoderImpl::Vorbis(source) => source.metadata(),
+            #[cfg(feature = "flac")]
+            DecoderImpl::Flac(source) => source.metadata(),
+            #[cfg(feature = "mp3")]
+            DecoderImpl::Mp3(source) => source.metadata(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.metadata(),
+            DecoderImpl::Custom(source) => source.metadata(),
+            DecoderImpl::None(_) => &metadata::EMPTY,
+        }
+    }
+
+    fn channel_layout(&self) -> ChannelLayout {
+        match self {
+            #[cfg(feature = "wav")]
+            DecoderImpl::Wav(source) => source.channel_layout().clone(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => ChannelLayout::default_for(source.channels()),
+            #[cfg(feature = "vorbis")]
+            DecoderImpl::Vorbis(source) => ChannelLayout::vorbis(source.channels()),
+            #[cfg(feature = "flac")]
+            DecoderImpl::Flac(source) => ChannelLayout::default_for(source.channels()),
+            #[cfg(feature = "mp3")]
+            DecoderImpl::Mp3(source) => ChannelLayout::default_for(source.channels()),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.channel_layout().clone(),
+            DecoderImpl::Custom(source) => ChannelLayout::default_for(source.channels()),
+            DecoderImpl::None(_) => ChannelLayout::new(Vec::new()),
+        }
+    }
+
+    #[inline]
+    fn skipped_frames(&self) -> u64 {
+        match self {
+            #[cfg(feature = "vorbis")]
+            DecoderImpl::Vorbis(source) => source.resync().skipped(),
+            #[cfg(feature = "flac")]
+            DecoderImpl::Flac(source) => source.resync().skipped(),
+            #[cfg(feature = "mp3")]
+            DecoderImpl::Mp3(source) => source.resync().skipped(),
+            _ => 0,
+        }
+    }
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
-        match &mut self.0 {
+    fn next_sample(&mut self) -> Option<f32> {
+        match self {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.next(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.next(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.next(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.next(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.next(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.next(),
+            DecoderImpl::Custom(source) => source.next(),
             DecoderImpl::None(_) => None,
         }
     }
 
+    /// Returns the error that made the decoder stop, if any.
+    #[inline]
+    fn take_error(&mut self) -> Option<DecoderError> {
+        match self {
+            #[cfg(feature = "wav")]
+            DecoderImpl::Wav(source) => source.take_error(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.take_error(),
+            #[cfg(feature = "vorbis")]
+            DecoderImpl::Vorbis(source) => source.take_error(),
+            #[cfg(feature = "flac")]
+            DecoderImpl::Flac(source) => source.take_error(),
+            #[cfg(feature = "mp3")]
+            DecoderImpl::Mp3(source) => source.take_error(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.take_error(),
+            DecoderImpl::Custom(source) => source.take_error(),
+            DecoderImpl::None(_) => None,
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        match self {
+            #[cfg(feature = "wav")]
+            DecoderImpl::Wav(source) => source.try_seek(pos),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.try_seek(pos),
+            #[cfg(feature = "vorbis")]
+            DecoderImpl::Vorbis(source) => source.try_seek(pos),
+            #[cfg(feature = "flac")]
+            DecoderImpl::Flac(source) => source.try_seek(pos),
+            #[cfg(feature = "mp3")]
+            DecoderImpl::Mp3(_) => {
+                // The MP3 decoder has to be rebuilt in order to seek. It is given back along with
+                // the error if that fails, so that a failed seek does not end playback.
+                let (decoder, result) =
+                    match mem::replace(self, DecoderImpl::None(Default::default())) {
+                        DecoderImpl::Mp3(source) => source.seek(pos),
+                        _ => unreachable!(),
+                    };
+                *self = DecoderImpl::Mp3(decoder);
+                result
+            }
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.try_seek(pos),
+            DecoderImpl::Custom(source) => source.try_seek(pos),
+            DecoderImpl::None(_) => Ok(()),
+        }
+    }
+}
+
+impl<R> LoopedDecoder<R>
+where
+    R: Read + Seek + Send,
+{
+    fn new(decoder: Decoder<R>) -> LoopedDecoder<R> {
+        let (loop_start, loop_end) = decoder.inner.metadata().loop_points().unwrap_or((0, None));
+        LoopedDecoder {
+            inner: decoder.inner,
+            errors: decoder.errors,
+            loop_start,
+            loop_end,
+            position: 0,
+        }
+    }
+
+    /// Sets the section that is looped, as the position of its first sample and the position
+    /// right after its last sample, in inter-channel samples. The loop ends at the end of the
+    /// file if `end` is `None`.
+    ///
+    /// The samples before `start` are only played once. Jumping back to the start of the loop
+    /// is sample-accurate, except for MP3 files whose loop does not start at zero.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `end` is not after `start`.
+    pub fn set_loop_points(&mut self, start: u64, end: Option<u64>) {
+        if let Some(end) = end {
+            assert!(end > start, "the loop must end after its start");
+        }
+        self.loop_start = start;
+        self.loop_end = end;
+    }
+
+    /// Returns the section that is looped, as the position of its first sample and the position
+    /// right after its last sample, in inter-channel samples.
+    #[inline]
+    pub fn loop_points(&self) -> (u64, Option<u64>) {
+        (self.loop_start, self.loop_end)
+    }
+
+    /// Returns the error that stopped the decoding, if any.
+    ///
+    /// Looping stops as well when that happens.
+    #[inline]
+    pub fn error(&self) -> Option<&DecoderError> {
+        self.errors.error.as_ref()
+    }
+
+    /// Sets a function that is called with the error that stops the decoding, if any.
+    pub fn set_error_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(DecoderError) + Send + 'static,
+    {
+        self.errors.callback = Some(Box::new(callback));
+    }
+
+    /// Returns the tags and pictures found in the file.
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    /// Returns the speaker of each channel.
+    #[inline]
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.inner.channel_layout()
+    }
+
+    /// Returns the number of frames of damaged data that have been skipped so far, over all the
+    /// loops.
+    #[inline]
+    pub fn skipped_frames(&self) -> u64 {
+        self.inner.skipped_frames()
+    }
+}
+
+impl<R> LoopedDecoder<R>
+where
+    R: Read + Seek,
+{
+    /// Goes back to the start of the loop and returns its first sample.
+    fn restart_loop(&mut self) -> Option<f32> {
+        let channels = self.channels() as u64;
+        let sample_rate = self.sample_rate() as u128;
+        // Rounded up, so that the decoders convert it back to the same position.
+        let nanos = (self.loop_start as u128 * 1_000_000_000).div_ceil(sample_rate);
+        // A loop start too far to be reached is ignored, and the loop starts at zero instead.
+        let start = self
+            .loop_start
+            .checked_mul(channels)
+            .zip(u64::try_from(nanos).ok());
+        match start {
+            Some((position, nanos)) if self.loop_start > 0 => {
+                self.inner.try_seek(Duration::from_nanos(nanos)).ok()?;
+                self.position = position;
+                let sample = self.inner.next_sample()?;
+                self.position += 1;
+                return Some(sample);
+            }
+            _ => (),
+        }
+
+        let decoder = mem::replace(&mut self.inner, DecoderImpl::None(Default::default()));
+        let (decoder, sample) = match decoder {
+            #[cfg(feature = "wav")]
+            DecoderImpl::Wav(source) => {
+                let mut reader = source.into_inner();
+                reader.seek(SeekFrom::Start(0)).ok()?;
+                let mut source = wav::WavDecoder::new(reader).ok()?;
+                let sample = source.next();
+                (DecoderImpl::Wav(source), sample)
+            }
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => {
+                let mut reader = source.into_inner();
+                reader.seek(SeekFrom::Start(0)).ok()?;
+                let mut source = aiff::AiffDecoder::new(reader).ok()?;
+                let sample = source.next();
+                (DecoderImpl::Aiff(source), sample)
+            }
+            #[cfg(feature = "vorbis")]
+            DecoderImpl::Vorbis(source) => {
+                use lewton::inside_ogg::OggStreamReader;
+                let resync = source.resync().clone();
+                let mut reader = source.into_inner().into_inner();
+                reader.seek_bytes(SeekFrom::Start(0)).ok()?;
+                let mut source = vorbis::VorbisDecoder::from_stream_reader(
+                    OggStreamReader::from_ogg_reader(reader).ok()?,
+                    resync,
+                );
+                let sample = source.next();
+                (DecoderImpl::Vorbis(source), sample)
+            }
+            #[cfg(feature = "flac")]
+            DecoderImpl::Flac(source) => {
+                let resync = source.resync().clone();
+                let mut reader = source.into_inner();
+                reader.seek(SeekFrom::Start(0)).ok()?;
+                let mut source = flac::FlacDecoder::new(reader, resync).ok()?;
+                let sample = source.next();
+                (DecoderImpl::Flac(source), sample)
+            }
+            #[cfg(feature = "mp3")]
+            DecoderImpl::Mp3(source) => {
+                let resync = source.resync().clone();
+                let mut reader = source.into_inner();
+                reader.seek(SeekFrom::Start(0)).ok()?;
+                let mut source = mp3::Mp3Decoder::new(reader, resync).ok()?;
+                let sample = source.next();
+                (DecoderImpl::Mp3(source), sample)
+            }
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => {
+                let mut reader = source.into_inner();
+                reader.seek(SeekFrom::Start(0)).ok()?;
+                let mut source = opus::OpusDecoder::new(reader).ok()?;
+                let sample = source.next();
+                (DecoderImpl::Opus(source), sample)
+            }
+            DecoderImpl::Custom(source) => {
+                let mut source = source.restart().ok()?;
+                let sample = source.next();
+                (DecoderImpl::Custom(source), sample)
+            }
+            none @ DecoderImpl::None(_) => (none, None),
+        };
+        self.inner = decoder;
+        self.position = sample.is_some() as u64;
+        sample
+    }
+}
+
+/// Keeps the error that stopped a decoder and passes it to the user's callback.
+#[derive(Default)]
+struct ErrorReporter {
+    error: Option<DecoderError>,
+    callback: Option<Box<dyn FnMut(DecoderError) + Send>>,
+}
+
+impl ErrorReporter {
+    fn report(&mut self, error: DecoderError) {
+        if let Some(callback) = &mut self.callback {
+            callback(error.clone());
+        }
+        self.error = Some(error);
+    }
+}
+
+/// Whether decoders skip the damaged parts of the data instead of stopping, and the number of
+/// frames they skipped.
+///
+/// Clones share the same counter, so that it keeps counting when a decoder is rebuilt.
+#[cfg(any(feature = "flac", feature = "vorbis", feature = "mp3"))]
+#[derive(Clone, Debug, Default)]
+struct Resync {
+    enabled: bool,
+    skipped: Arc<AtomicU64>,
+}
+
+#[cfg(any(feature = "flac", feature = "vorbis", feature = "mp3"))]
+impl Resync {
+    #[inline]
+    fn new(enabled: bool) -> Resync {
+        Resync {
+            enabled,
+            skipped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    #[inline]
+    fn skip(&self, frames: u64) {
+        self.skipped.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+impl<R> Iterator for Decoder<R>
+where
+    R: Read + Seek,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.errors.error.is_some() {
+            return None;
+        }
+
+        let sample = self.inner.next_sample();
+        if sample.is_none() {
+            if let Some(error) = self.inner.take_error() {
+                self.errors.report(error);
+            }
+        }
+        sample
+    }
+
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.size_hint(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.size_hint(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.size_hint(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.size_hint(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.size_hint(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.size_hint(),
+            DecoderImpl::Custom(source) => source.size_hint(),
             DecoderImpl::None(_) => (0, None),
         }
     }
@@ -180,141 +643,146 @@ where
 {
     #[inline]
     fn current_frame_len(&self) -> Option<usize> {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.current_frame_len(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.current_frame_len(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.current_frame_len(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.current_frame_len(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.current_frame_len(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.current_frame_len(),
+            DecoderImpl::Custom(source) => source.current_frame_len(),
             DecoderImpl::None(_) => Some(0),
         }
     }
 
     #[inline]
     fn channels(&self) -> u16 {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.channels(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.channels(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.channels(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.channels(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.channels(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.channels(),
+            DecoderImpl::Custom(source) => source.channels(),
             DecoderImpl::None(_) => 0,
         }
     }
 
     #[inline]
     fn sample_rate(&self) -> u32 {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.sample_rate(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.sample_rate(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.sample_rate(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.sample_rate(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.sample_rate(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.sample_rate(),
+            DecoderImpl::Custom(source) => source.sample_rate(),
             DecoderImpl::None(_) => 1,
         }
     }
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.total_duration(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.total_duration(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.total_duration(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.total_duration(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.total_duration(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.total_duration(),
+            DecoderImpl::Custom(source) => source.total_duration(),
             DecoderImpl::None(_) => Some(Duration::default()),
         }
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if !self.seekable {
+            return Err(SeekError::NotSupported {
+                underlying_source: std::any::type_name::<Self>(),
+            });
+        }
+        self.inner.try_seek(pos)?;
+        // Decoding resumes from the new position.
+        self.errors.error = None;
+        Ok(())
+    }
 }
 
 impl<R> Iterator for LoopedDecoder<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
-        if let Some(sample) = match &mut self.0 {
-            #[cfg(feature = "wav")]
-            DecoderImpl::Wav(source) => source.next(),
-            #[cfg(feature = "vorbis")]
-            DecoderImpl::Vorbis(source) => source.next(),
-            #[cfg(feature = "flac")]
-            DecoderImpl::Flac(source) => source.next(),
-            #[cfg(feature = "mp3")]
-            DecoderImpl::Mp3(source) => source.next(),
-            DecoderImpl::None(_) => None,
-        } {
+    fn next(&mut self) -> Option<f32> {
+        // A loop end too far to be reached is ignored.
+        let end = self
+            .loop_end
+            .and_then(|end| end.checked_mul(self.channels() as u64));
+        if let Some(end) = end {
+            if self.position >= end {
+                return self.restart_loop();
+            }
+        }
+
+        if let Some(sample) = self.inner.next_sample() {
+            self.position += 1;
             Some(sample)
         } else {
-            let decoder = mem::replace(&mut self.0, DecoderImpl::None(Default::default()));
-            let (decoder, sample) = match decoder {
-                #[cfg(feature = "wav")]
-                DecoderImpl::Wav(source) => {
-                    let mut reader = source.into_inner();
-                    reader.seek(SeekFrom::Start(0)).ok()?;
-                    let mut source = wav::WavDecoder::new(reader).ok()?;
-                    let sample = source.next();
-                    (DecoderImpl::Wav(source), sample)
-                }
-                #[cfg(feature = "vorbis")]
-                DecoderImpl::Vorbis(source) => {
-                    use lewton::inside_ogg::OggStreamReader;
-                    let mut reader = source.into_inner().into_inner();
-                    reader.seek_bytes(SeekFrom::Start(0)).ok()?;
-                    let mut source = vorbis::VorbisDecoder::from_stream_reader(
-                        OggStreamReader::from_ogg_reader(reader).ok()?,
-                    );
-                    let sample = source.next();
-                    (DecoderImpl::Vorbis(source), sample)
-                }
-                #[cfg(feature = "flac")]
-                DecoderImpl::Flac(source) => {
-                    let mut reader = source.into_inner();
-                    reader.seek(SeekFrom::Start(0)).ok()?;
-                    let mut source = flac::FlacDecoder::new(reader).ok()?;
-                    let sample = source.next();
-                    (DecoderImpl::Flac(source), sample)
-                }
-                #[cfg(feature = "mp3")]
-                DecoderImpl::Mp3(source) => {
-                    let mut reader = source.into_inner();
-                    reader.seek(SeekFrom::Start(0)).ok()?;
-                    let mut source = mp3::Mp3Decoder::new(reader).ok()?;
-                    let sample = source.next();
-                    (DecoderImpl::Mp3(source), sample)
-                }
-                none @ DecoderImpl::None(_) => (none, None),
-            };
-            self.0 = decoder;
-            sample
+            // Restarting a stream that failed would only fail again.
+            if let Some(error) = self.inner.take_error() {
+                self.errors.report(error);
+                self.inner = DecoderImpl::None(Default::default());
+                return None;
+            }
+            self.restart_loop()
         }
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => (source.size_hint().0, None),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => (source.size_hint().0, None),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => (source.size_hint().0, None),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => (source.size_hint().0, None),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => (source.size_hint().0, None),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => (source.size_hint().0, None),
+            DecoderImpl::Custom(source) => (source.size_hint().0, None),
             DecoderImpl::None(_) => (0, None),
         }
     }
@@ -326,45 +794,60 @@ where
 {
     #[inline]
     fn current_frame_len(&self) -> Option<usize> {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.current_frame_len(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.current_frame_len(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.current_frame_len(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.current_frame_len(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.current_frame_len(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.current_frame_len(),
+            DecoderImpl::Custom(source) => source.current_frame_len(),
             DecoderImpl::None(_) => Some(0),
         }
     }
 
     #[inline]
     fn channels(&self) -> u16 {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.channels(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.channels(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.channels(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.channels(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.channels(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.channels(),
+            DecoderImpl::Custom(source) => source.channels(),
             DecoderImpl::None(_) => 0,
         }
     }
 
     #[inline]
     fn sample_rate(&self) -> u32 {
-        match &self.0 {
+        match &self.inner {
             #[cfg(feature = "wav")]
             DecoderImpl::Wav(source) => source.sample_rate(),
+            #[cfg(feature = "aiff")]
+            DecoderImpl::Aiff(source) => source.sample_rate(),
             #[cfg(feature = "vorbis")]
             DecoderImpl::Vorbis(source) => source.sample_rate(),
             #[cfg(feature = "flac")]
             DecoderImpl::Flac(source) => source.sample_rate(),
             #[cfg(feature = "mp3")]
             DecoderImpl::Mp3(source) => source.sample_rate(),
+            #[cfg(feature = "opus")]
+            DecoderImpl::Opus(source) => source.sample_rate(),
+            DecoderImpl::Custom(source) => source.sample_rate(),
             DecoderImpl::None(_) => 1,
         }
     }
@@ -373,19 +856,41 @@ where
     fn total_duration(&self) -> Option<Duration> {
         None
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        let frame = (pos.as_secs_f64() * self.sample_rate() as f64) as u64;
+        self.position = frame.saturating_mul(self.channels() as u64);
+        Ok(())
+    }
 }
 
-/// Error that can happen when creating a decoder.
+/// Error that can happen when creating a decoder, or that stops it while decoding.
 #[derive(Debug, Clone)]
 pub enum DecoderError {
     /// The format of the data has not been recognized.
     UnrecognizedFormat,
+    /// The data ends before the end of the stream.
+    Truncated,
+    /// The format has been recognized, but the way its samples are encoded is not supported.
+    UnsupportedEncoding(String),
+    /// Reading the data failed.
+    ///
+    /// The error is shared so that `DecoderError` can be cloned.
+    IoError(Arc<io::Error>),
+    /// The data is not valid for its format.
+    DecodeError(String),
 }
 
 impl fmt::Display for DecoderError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             DecoderError::UnrecognizedFormat => write!(f, "Unrecognized format"),
+            DecoderError::Truncated => write!(f, "Truncated data"),
+            DecoderError::UnsupportedEncoding(msg) => write!(f, "Unsupported encoding: {}", msg),
+            DecoderError::IoError(err) => write!(f, "IO error: {}", err),
+            DecoderError::DecodeError(msg) => write!(f, "Decode error: {}", msg),
         }
     }
 }
@@ -394,6 +899,95 @@ impl Error for DecoderError {
     fn description(&self) -> &str {
         match self {
             DecoderError::UnrecognizedFormat => "Unrecognized format",
+            DecoderError::Truncated => "Truncated data",
+            DecoderError::UnsupportedEncoding(_) => "Unsupported encoding",
+            DecoderError::IoError(_) => "IO error",
+            DecoderError::DecodeError(_) => "Decode error",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecoderError::IoError(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DecoderError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => DecoderError::Truncated,
+            _ => DecoderError::IoError(Arc::new(err)),
+        }
+    }
+}
+
+#[cfg(feature = "wav")]
+impl From<hound::Error> for DecoderError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            // Data that ends too early is reported as `UnexpectedEof` by the WAV decoder's reader.
+            hound::Error::IoError(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                DecoderError::Truncated
+            }
+            hound::Error::IoError(err) => err.into(),
+            hound::Error::Unsupported => DecoderError::UnsupportedEncoding(err.to_string()),
+            err => DecoderError::DecodeError(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "flac")]
+impl From<claxon::Error> for DecoderError {
+    fn from(err: claxon::Error) -> Self {
+        match err {
+            claxon::Error::IoError(err) => err.into(),
+            claxon::Error::Unsupported(msg) => DecoderError::UnsupportedEncoding(msg.to_owned()),
+            claxon::Error::FormatError(msg) => DecoderError::DecodeError(msg.to_owned()),
+        }
+    }
+}
+
+#[cfg(feature = "vorbis")]
+impl From<lewton::VorbisError> for DecoderError {
+    fn from(err: lewton::VorbisError) -> Self {
+        match err {
+            lewton::VorbisError::OggError(lewton::OggReadError::ReadError(err)) => err.into(),
+            err => DecoderError::DecodeError(format!("{}: {:?}", err, err)),
+        }
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<ogg::OggReadError> for DecoderError {
+    fn from(err: ogg::OggReadError) -> Self {
+        match err {
+            ogg::OggReadError::ReadError(err) => err.into(),
+            err => DecoderError::DecodeError(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<opus_codec::Error> for DecoderError {
+    fn from(err: opus_codec::Error) -> Self {
+        match err.code() {
+            opus_codec::ErrorCode::Unimplemented => {
+                DecoderError::UnsupportedEncoding(err.to_string())
+            }
+            _ => DecoderError::DecodeError(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "mp3")]
+impl From<minimp3::Error> for DecoderError {
+    fn from(err: minimp3::Error) -> Self {
+        match err {
+            minimp3::Error::Io(err) => err.into(),
+            minimp3::Error::Eof | minimp3::Error::InsufficientData => DecoderError::Truncated,
+            err => DecoderError::DecodeError(err.to_string()),
         }
     }
 }
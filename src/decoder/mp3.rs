@@ -1,36 +1,251 @@
-use std::io::{Read, Seek};
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::time::Duration;
 
+use super::id3;
+use super::metadata::Metadata;
+use super::mpeg::{self, FrameReader};
+use super::{DecoderError, Resync};
+use crate::source::SeekError;
 use crate::Source;
 
+use cpal::Sample as CpalSample;
 use minimp3::{Decoder, Frame};
 
 pub struct Mp3Decoder<R>
 where
     R: Read + Seek,
 {
-    decoder: Decoder<R>,
+    decoder: Decoder<FrameReader<R>>,
     current_frame: Frame,
     current_frame_offset: usize,
+    // Frames decoded in advance, so that the padding can be removed once the end is reached.
+    next_frames: VecDeque<Frame>,
+    // Whether the decoder has no more frames to give.
+    ended: bool,
+    // Error that happened while decoding in advance, reported once the queued frames are played.
+    pending_error: Option<minimp3::Error>,
+    // Number of samples per channel that remain to be dropped at the start of the stream.
+    skip: u64,
+    // Byte offset at which the stream starts, used to estimate seek positions.
+    start_byte: u64,
+    info: mpeg::StreamInfo,
+    metadata: Metadata,
+    resync: Resync,
+    error: Option<DecoderError>,
 }
 
 impl<R> Mp3Decoder<R>
 where
     R: Read + Seek,
 {
-    pub fn new(data: R) -> Result<Self, ()> {
-        let mut decoder = Decoder::new(data);
-        let current_frame = decoder.next_frame().map_err(|_| ())?;
+    pub fn new(mut data: R, resync: Resync) -> Result<Self, DecoderError> {
+        // Also skips the ID3v2 tag, so that the stream starts at the first frame.
+        let metadata = id3::read_tags(data.by_ref())?;
+        let start_byte = data.stream_position()?;
+        let info = mpeg::stream_info(data.by_ref())?;
 
-        Ok(Mp3Decoder {
+        let mut decoder = Mp3Decoder {
+            decoder: Decoder::new(FrameReader::new(data, resync.clone())),
+            current_frame: Frame {
+                data: Vec::new(),
+                sample_rate: 0,
+                channels: 0,
+                layer: 0,
+                bitrate: 0,
+            },
+            current_frame_offset: 0,
+            next_frames: VecDeque::new(),
+            ended: false,
+            pending_error: None,
+            skip: info.start_padding,
+            start_byte,
+            info,
+            metadata,
+            resync,
+            error: None,
+        };
+        decoder.current_frame = decoder.next_frame()?.ok_or(DecoderError::Truncated)?;
+        Ok(decoder)
+    }
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner().into_inner()
+    }
+
+    pub fn resync(&self) -> &Resync {
+        &self.resync
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        self.error.take()
+    }
+
+    /// Rebuilds the decoder so that playback resumes at `pos`.
+    ///
+    /// MP3 streams have no index, so the byte position is estimated from the table of contents of
+    /// the Xing or VBRI header when there is one, and from the average bitrate of the stream
+    /// otherwise. When the duration of the stream is not known either, the bitrate of the current
+    /// frame is used. Only constant bitrate streams are sought exactly.
+    ///
+    /// The decoder is given back even if that fails. It is left as it was if the data could not
+    /// be moved, and ends at the new position if the frame there could not be decoded.
+    pub fn seek(mut self, pos: Duration) -> (Self, Result<(), SeekError>) {
+        let at_start = pos == Duration::from_secs(0);
+        let sample = (pos.as_secs_f64() * self.current_frame.sample_rate as f64) as u64;
+        let byte = if at_start {
+            self.start_byte
+        } else {
+            // The seek points count the samples dropped at the start too.
+            match self.info.byte_position(sample + self.info.start_padding) {
+                Some(byte) => byte,
+                // The bitrate is in kbit/s, which is conveniently equal to bits per millisecond.
+                None => {
+                    let offset = pos.as_millis() as u64 * self.current_frame.bitrate as u64 / 8;
+                    self.start_byte + offset
+                }
+            }
+        };
+        let moved = move_data(self.decoder.reader_mut().get_mut(), byte);
+        if let Err(err) = moved {
+            return (self, Err(SeekError::Io(err)));
+        }
+
+        let Mp3Decoder {
             decoder,
             current_frame,
+            start_byte,
+            info,
+            metadata,
+            resync,
+            ..
+        } = self;
+        let reader = decoder.into_inner().into_inner();
+
+        let mut decoder = Mp3Decoder {
+            decoder: Decoder::new(FrameReader::new(reader, resync.clone())),
+            current_frame: Frame {
+                data: Vec::new(),
+                ..current_frame
+            },
             current_frame_offset: 0,
-        })
+            next_frames: VecDeque::new(),
+            ended: false,
+            pending_error: None,
+            // The encoder delay is only found at the very start of the stream.
+            skip: if at_start { info.start_padding } else { 0 },
+            start_byte,
+            info,
+            metadata,
+            resync,
+            error: None,
+        };
+        let result = match decoder.next_frame() {
+            Ok(Some(frame)) => {
+                decoder.current_frame = frame;
+                Ok(())
+            }
+            // Seeking past the end is not an error, the source simply ends.
+            Ok(None) => Ok(()),
+            Err(err) => Err(SeekError::Minimp3Decoder(err)),
+        };
+        (decoder, result)
     }
-    pub fn into_inner(self) -> R {
-        self.decoder.into_inner()
+
+    /// Returns the next frame, without the samples added by the encoder at the start and the end
+    /// of the stream.
+    fn next_frame(&mut self) -> Result<Option<Frame>, minimp3::Error> {
+        loop {
+            // The frame given back may only contain padding if the end of the stream is close,
+            // which is known once enough frames follow it.
+            while !self.ended
+                && (self.next_frames.is_empty()
+                    || queued_samples(self.next_frames.iter().skip(1)) < self.info.end_padding)
+            {
+                match self.decoder.next_frame() {
+                    Ok(frame) => self.next_frames.push_back(frame),
+                    Err(minimp3::Error::Eof) => {
+                        self.ended = true;
+                        self.trim_end_padding();
+                    }
+                    Err(error) => {
+                        self.ended = true;
+                        self.pending_error = Some(error);
+                    }
+                }
+            }
+
+            let mut frame = match self.next_frames.pop_front() {
+                Some(frame) => frame,
+                None => {
+                    return match self.pending_error.take() {
+                        Some(error) => Err(error),
+                        None => Ok(None),
+                    }
+                }
+            };
+
+            let skipped = self.skip.min(queued_samples(Some(&frame)));
+            frame.data.drain(..skipped as usize * frame.channels);
+            self.skip -= skipped;
+            if !frame.data.is_empty() {
+                return Ok(Some(frame));
+            }
+        }
     }
+
+    /// Removes the padding from the frames at the end of the stream.
+    fn trim_end_padding(&mut self) {
+        let mut padding = self.info.end_padding;
+        while padding > 0 {
+            let frame = match self.next_frames.back_mut() {
+                Some(frame) => frame,
+                None => return,
+            };
+            let len = queued_samples(Some(&*frame));
+            if len <= padding {
+                self.next_frames.pop_back();
+                padding -= len;
+            } else {
+                frame
+                    .data
+                    .truncate((len - padding) as usize * frame.channels);
+                padding = 0;
+            }
+        }
+    }
+}
+
+/// Moves the data to `pos`, or to its end if it is shorter.
+///
+/// If that fails, the data is moved back to where it was so that the current decoder can go on.
+fn move_data<R>(data: &mut R, pos: u64) -> io::Result<()>
+where
+    R: Seek,
+{
+    let read_pos = data.stream_position()?;
+    let moved = data
+        .seek(SeekFrom::End(0))
+        .and_then(|end| data.seek(SeekFrom::Start(pos.min(end))));
+    if moved.is_err() {
+        let _ = data.seek(SeekFrom::Start(read_pos));
+    }
+    moved.map(|_| ())
+}
+
+/// Returns the number of samples per channel of the frames.
+fn queued_samples<'a, I>(frames: I) -> u64
+where
+    I: IntoIterator<Item = &'a Frame>,
+{
+    frames
+        .into_iter()
+        .map(|frame| (frame.data.len() / frame.channels.max(1)) as u64)
+        .sum()
 }
 
 impl<R> Source for Mp3Decoder<R>
@@ -39,7 +254,7 @@ where
 {
     #[inline]
     fn current_frame_len(&self) -> Option<usize> {
-        Some(self.current_frame.data.len())
+        Some(self.current_frame.data.len() - self.current_frame_offset)
     }
 
     #[inline]
@@ -54,7 +269,7 @@ where
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        None
+        self.info.duration
     }
 }
 
@@ -62,21 +277,39 @@ impl<R> Iterator for Mp3Decoder<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
+        // The current frame is only empty once the end of the stream has been reached.
+        let v = *self.current_frame.data.get(self.current_frame_offset)?;
+        self.current_frame_offset += 1;
+
+        // The next frame is read right away, so that the channels count and sample rate always
+        // describe the next sample, even when they change between frames.
         if self.current_frame_offset == self.current_frame.data.len() {
-            match self.decoder.next_frame() {
-                Ok(frame) => self.current_frame = frame,
-                _ => return None,
+            match self.next_frame() {
+                Ok(Some(frame)) => {
+                    self.current_frame = frame;
+                    self.current_frame_offset = 0;
+                }
+                Ok(None) => (),
+                Err(error) => self.error = Some(error.into()),
             }
-            self.current_frame_offset = 0;
         }
 
-        let v = self.current_frame.data[self.current_frame_offset];
-        self.current_frame_offset += 1;
-
-        Some(v)
+        Some(v.to_f32())
     }
 }
+
+/// Returns true if the stream contains an MP3 frame, then resets it to where it was.
+pub fn is_mp3<R>(mut data: R) -> io::Result<bool>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    id3::read_tags(data.by_ref())?;
+    let is_mp3 = Decoder::new(data.by_ref()).next_frame().is_ok();
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(is_mp3)
+}
@@ -0,0 +1,650 @@
+//! Parsing of MPEG audio frame headers, used to find the duration and the gapless playback
+//! information of MP3 streams, and to skip their damaged parts.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use super::Resync;
+
+/// Bitrates in kbit/s, indexed by the bitrate index of the header.
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+const BITRATES_V2: [[u32; 15]; 3] = [
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// How far to look for the first frame when the stream does not start with one.
+const MAX_SYNC_SEARCH: usize = 64 * 1024;
+
+/// The 4 bytes header found at the start of every MPEG audio frame.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameHeader {
+    /// MPEG-1, as opposed to MPEG-2 and MPEG-2.5.
+    mpeg1: bool,
+    layer: u8,
+    /// In kbit/s.
+    bitrate: u32,
+    sample_rate: u32,
+    padding: bool,
+    mono: bool,
+}
+
+impl FrameHeader {
+    /// Parses a frame header. Returns `None` if the bytes are not a valid header.
+    pub fn parse(bytes: [u8; 4]) -> Option<FrameHeader> {
+        if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        let version = (bytes[1] >> 3) & 0b11;
+        let layer = match (bytes[1] >> 1) & 0b11 {
+            0b11 => 1,
+            0b10 => 2,
+            0b01 => 3,
+            _ => return None,
+        };
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let sample_rate_index = ((bytes[2] >> 2) & 0b11) as usize;
+        // Free format streams have no bitrate in their headers, and are not supported.
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+
+        let (mpeg1, bitrates, sample_rates) = match version {
+            0b11 => (true, &BITRATES_V1, [44100, 48000, 32000]),
+            0b10 => (false, &BITRATES_V2, [22050, 24000, 16000]),
+            0b00 => (false, &BITRATES_V2, [11025, 12000, 8000]),
+            _ => return None,
+        };
+
+        Some(FrameHeader {
+            mpeg1,
+            layer,
+            bitrate: bitrates[layer as usize - 1][bitrate_index],
+            sample_rate: sample_rates[sample_rate_index],
+            padding: bytes[2] & 0b10 != 0,
+            mono: bytes[3] >> 6 == 0b11,
+        })
+    }
+
+    /// Number of samples per channel in the frame.
+    pub fn samples(&self) -> u32 {
+        match (self.layer, self.mpeg1) {
+            (1, _) => 384,
+            (3, false) => 576,
+            _ => 1152,
+        }
+    }
+
+    /// Length of the frame in bytes, header included.
+    pub fn frame_len(&self) -> usize {
+        let padding = self.padding as u32;
+        let len = match (self.layer, self.mpeg1) {
+            (1, _) => (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4,
+            (3, false) => 72 * self.bitrate * 1000 / self.sample_rate + padding,
+            _ => 144 * self.bitrate * 1000 / self.sample_rate + padding,
+        };
+        len as usize
+    }
+
+    /// Offset of the Xing header from the start of the frame, which comes after the side
+    /// information of layer III frames.
+    fn xing_offset(&self) -> usize {
+        match (self.mpeg1, self.mono) {
+            (true, false) => 4 + 32,
+            (true, true) | (false, false) => 4 + 17,
+            (false, true) => 4 + 9,
+        }
+    }
+}
+
+/// Number of samples per channel by which decoders delay their output.
+const DECODER_DELAY: u64 = 529;
+
+/// Information about an MPEG audio stream, read from its first frames.
+#[derive(Clone, Debug, Default)]
+pub struct StreamInfo {
+    /// Duration of the audio, without the delay and padding added by the encoder.
+    pub duration: Option<Duration>,
+    /// Number of decoded samples per channel to drop at the start of the stream.
+    ///
+    /// This includes the frame holding the Xing, Info or VBRI header, which decodes to silence.
+    pub start_padding: u64,
+    /// Number of decoded samples per channel to drop at the end of the stream.
+    pub end_padding: u64,
+    /// Known byte positions in the data, with the number of samples per channel decoded from the
+    /// first frame up to them, sorted. Used to estimate where to seek.
+    pub seek_points: Vec<(u64, u64)>,
+}
+
+impl StreamInfo {
+    /// Estimates the byte position in the data from which the given number of samples per
+    /// channel, counted from the first frame, is reached. Returns `None` if the stream has no
+    /// seek points.
+    pub fn byte_position(&self, sample: u64) -> Option<u64> {
+        let next = self.seek_points.iter().position(|&(s, _)| s > sample);
+        let ((start_sample, start_byte), (end_sample, end_byte)) = match next {
+            Some(0) => return self.seek_points.first().map(|&(_, byte)| byte),
+            Some(next) => (self.seek_points[next - 1], self.seek_points[next]),
+            None => return self.seek_points.last().map(|&(_, byte)| byte),
+        };
+
+        // The bitrate is assumed to be constant between two points.
+        let bytes = end_byte.saturating_sub(start_byte) as u128;
+        let offset = (sample - start_sample) as u128 * bytes / (end_sample - start_sample) as u128;
+        Some(start_byte + offset as u64)
+    }
+}
+
+/// Content of the frame that holds a Xing, Info or VBRI header instead of audio.
+struct InfoFrame {
+    frames: Option<u32>,
+    /// Length in bytes of the stream, starting at this frame.
+    bytes: Option<u32>,
+    toc: Option<Toc>,
+    /// Samples per channel added by the encoder at the start and at the end of the stream, as
+    /// written by LAME for gapless playback.
+    gapless: Option<(u32, u32)>,
+}
+
+/// Table of contents of a Xing or VBRI header, telling where parts of the stream start.
+#[derive(Debug, PartialEq)]
+enum Toc {
+    /// Positions at each percent of the duration, in 256ths of the length of the stream.
+    Xing(Vec<u8>),
+    /// Lengths in bytes of consecutive parts of the stream, that each hold the same number of
+    /// frames.
+    Vbri {
+        frames_per_entry: u32,
+        lengths: Vec<u32>,
+    },
+}
+
+/// Reads the information of the MPEG audio stream starting at the current position, then resets
+/// the stream to where it was.
+///
+/// The number of frames is read from the Xing, Info or VBRI header of the first frame when there
+/// is one. Otherwise the headers of all the frames are read.
+pub fn stream_info<R>(mut data: R) -> io::Result<StreamInfo>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    let info = read_stream_info(data.by_ref());
+    data.seek(SeekFrom::Start(stream_pos))?;
+    info
+}
+
+fn read_stream_info<R>(mut data: R) -> io::Result<StreamInfo>
+where
+    R: Read + Seek,
+{
+    let (frame_start, header) = match find_first_frame(data.by_ref())? {
+        Some(first) => first,
+        None => return Ok(StreamInfo::default()),
+    };
+
+    let mut frame = vec![0; header.frame_len()];
+    data.seek(SeekFrom::Start(frame_start))?;
+    let frame_len = read_up_to(data.by_ref(), &mut frame)?;
+    frame.truncate(frame_len);
+
+    let frame_samples = header.samples() as u64;
+    let info_frame = read_info_frame(&header, &frame);
+    let stream_end = stream_end(data.by_ref())?;
+    let total_samples = match info_frame.as_ref().and_then(|info| info.frames) {
+        Some(frames) => Some(frames as u64 * frame_samples),
+        // Counting the frames reads the whole stream, so it is skipped for streams that cannot
+        // seek, which could not be rewound afterwards.
+        None if stream_end.is_none() => None,
+        None => {
+            data.seek(SeekFrom::Start(frame_start))?;
+            let samples = scan_frames(data)?;
+            if info_frame.is_some() {
+                Some(samples.saturating_sub(frame_samples))
+            } else {
+                Some(samples)
+            }
+        }
+    };
+
+    let mut info = StreamInfo::default();
+    let stream_bytes = match info_frame.as_ref().and_then(|info| info.bytes) {
+        Some(bytes) => Some(bytes as u64),
+        None => stream_end.map(|end| end.saturating_sub(frame_start)),
+    };
+    info.seek_points = seek_points(
+        info_frame.as_ref().and_then(|info| info.toc.as_ref()),
+        frame_start,
+        frame_samples,
+        total_samples,
+        stream_bytes,
+    );
+
+    let mut audio_samples = total_samples;
+    if let Some(info_frame) = info_frame {
+        info.start_padding = frame_samples;
+        if let Some((delay, padding)) = info_frame.gapless {
+            let (delay, padding) = (delay as u64, padding as u64);
+            audio_samples = total_samples.map(|samples| samples.saturating_sub(delay + padding));
+            // The decoder delay shifts the whole stream, so less padding remains at the end.
+            info.start_padding += delay + DECODER_DELAY;
+            info.end_padding = padding.saturating_sub(DECODER_DELAY);
+        }
+    }
+    info.duration = audio_samples
+        .map(|samples| Duration::from_micros(samples * 1_000_000 / header.sample_rate as u64));
+    Ok(info)
+}
+
+/// Returns the position of the end of the stream, or `None` if it cannot be reached by seeking.
+fn stream_end<R>(mut data: R) -> io::Result<Option<u64>>
+where
+    R: Read + Seek,
+{
+    match data.seek(SeekFrom::End(0)) {
+        Ok(end) => Ok(Some(end)),
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Builds the seek points of a stream whose first frame starts at `frame_start`.
+///
+/// They come from the table of contents of the first frame when it has one. Otherwise the
+/// average bitrate is used, if the length and the duration of the stream are known.
+fn seek_points(
+    toc: Option<&Toc>,
+    frame_start: u64,
+    frame_samples: u64,
+    total_samples: Option<u64>,
+    stream_bytes: Option<u64>,
+) -> Vec<(u64, u64)> {
+    if let Some(Toc::Vbri {
+        frames_per_entry,
+        lengths,
+    }) = toc
+    {
+        let entry_samples = *frames_per_entry as u64 * frame_samples;
+        let mut points = vec![(0, frame_start)];
+        let mut byte = frame_start;
+        for (i, &len) in lengths.iter().enumerate() {
+            byte += len as u64;
+            points.push(((i as u64 + 1) * entry_samples, byte));
+        }
+        return points;
+    }
+
+    let (total_samples, stream_bytes) = match (total_samples, stream_bytes) {
+        (Some(samples), Some(bytes)) if samples > 0 => (samples, bytes),
+        _ => return Vec::new(),
+    };
+
+    let mut points = match toc {
+        Some(Toc::Xing(toc)) => toc
+            .iter()
+            .enumerate()
+            .map(|(percent, &pos)| {
+                (
+                    percent as u64 * total_samples / 100,
+                    frame_start + pos as u64 * stream_bytes / 256,
+                )
+            })
+            .collect(),
+        _ => vec![(0, frame_start)],
+    };
+    points.push((total_samples, frame_start + stream_bytes));
+    points
+}
+
+/// Reader of an MPEG audio stream that, when resynchronizing, only gives whole frames that follow
+/// each other, and counts the frames in the data it skips between them.
+///
+/// Otherwise the data is given as it is.
+pub struct FrameReader<R> {
+    data: R,
+    resync: Resync,
+    // The frame being read, and the position in it of the next byte to give.
+    frame: Vec<u8>,
+    frame_pos: usize,
+    // Length of the previous frame, unless the data is not at the end of a frame, such as at the
+    // start or after seeking.
+    last_frame_len: Option<usize>,
+}
+
+impl<R> FrameReader<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(data: R, resync: Resync) -> FrameReader<R> {
+        FrameReader {
+            data,
+            resync,
+            frame: Vec::new(),
+            frame_pos: 0,
+            last_frame_len: None,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.data
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.data
+    }
+
+    /// Reads the next frame into `frame`, skipping the data before it. Returns false at the end
+    /// of the stream.
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let start = self.data.stream_position()?;
+        let mut bytes = [0; 4];
+        let header = match read_up_to(self.data.by_ref(), &mut bytes)? {
+            4 => FrameHeader::parse(bytes),
+            _ => None,
+        };
+
+        let header = match header {
+            Some(header) => header,
+            None => {
+                self.data.seek(SeekFrom::Start(start))?;
+                let (offset, header) = match self.find_frame()? {
+                    Some(frame) => frame,
+                    // Data after the last frame, such as an ID3v1 tag, is not damage.
+                    None => return Ok(false),
+                };
+                if let Some(len) = self.last_frame_len {
+                    let skipped = (offset - start) as usize;
+                    self.resync.skip(((skipped + len / 2) / len).max(1) as u64);
+                }
+                self.data.seek(SeekFrom::Start(offset))?;
+                read_up_to(self.data.by_ref(), &mut bytes)?;
+                header
+            }
+        };
+
+        self.frame.clear();
+        self.frame.extend_from_slice(&bytes);
+        self.data
+            .by_ref()
+            .take(header.frame_len() as u64 - 4)
+            .read_to_end(&mut self.frame)?;
+        self.frame_pos = 0;
+        self.last_frame_len = Some(header.frame_len());
+        Ok(true)
+    }
+
+    /// Finds the next frame, however far it is.
+    fn find_frame(&mut self) -> io::Result<Option<(u64, FrameHeader)>> {
+        loop {
+            let start = self.data.stream_position()?;
+            if let Some(frame) = find_first_frame(self.data.by_ref())? {
+                return Ok(Some(frame));
+            }
+            let end = self.data.stream_position()?;
+            if end < start + MAX_SYNC_SEARCH as u64 {
+                return Ok(None);
+            }
+            // The last bytes may be the start of a header.
+            self.data.seek(SeekFrom::Start(end - 4))?;
+        }
+    }
+}
+
+impl<R> Read for FrameReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.resync.enabled {
+            return self.data.read(buf);
+        }
+
+        if self.frame_pos == self.frame.len() && !self.read_frame()? {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.frame.len() - self.frame_pos);
+        buf[..len].copy_from_slice(&self.frame[self.frame_pos..self.frame_pos + len]);
+        self.frame_pos += len;
+        Ok(len)
+    }
+}
+
+/// Finds the first frame whose header is followed by another valid header.
+fn find_first_frame<R>(mut data: R) -> io::Result<Option<(u64, FrameHeader)>>
+where
+    R: Read + Seek,
+{
+    let start = data.stream_position()?;
+    let mut buffer = vec![0; MAX_SYNC_SEARCH];
+    let len = read_up_to(data.by_ref(), &mut buffer)?;
+    buffer.truncate(len);
+
+    for offset in 0..buffer.len().saturating_sub(4) {
+        let header = match header_at(&buffer, offset) {
+            Some(header) => header,
+            None => continue,
+        };
+        // A single header can be found by chance, so we check the one of the next frame too.
+        let next = offset + header.frame_len();
+        if next + 4 > buffer.len() || header_at(&buffer, next).is_some() {
+            return Ok(Some((start + offset as u64, header)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the Xing, Info or VBRI header of the first frame, if it has one.
+fn read_info_frame(header: &FrameHeader, frame: &[u8]) -> Option<InfoFrame> {
+    let read_u32 = |offset: usize| {
+        frame
+            .get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    let xing = header.xing_offset();
+    match frame.get(xing..xing + 4) {
+        Some(b"Xing") | Some(b"Info") => {
+            let flags = read_u32(xing + 4)?;
+            // Each flag tells whether the frame count, the byte count, the table of contents and
+            // the quality are present, in that order.
+            let mut offsets = [0; 4];
+            let mut lame = xing + 8;
+            for (i, &(flag, len)) in [(1, 4), (2, 4), (4, 100), (8, 4)].iter().enumerate() {
+                if flags & flag != 0 {
+                    offsets[i] = lame;
+                    lame += len;
+                }
+            }
+            let frames = if flags & 1 != 0 {
+                read_u32(offsets[0])
+            } else {
+                None
+            };
+            let bytes = if flags & 2 != 0 {
+                read_u32(offsets[1])
+            } else {
+                None
+            };
+            let toc = if flags & 4 != 0 {
+                frame
+                    .get(offsets[2]..offsets[2] + 100)
+                    .map(|toc| Toc::Xing(toc.to_vec()))
+            } else {
+                None
+            };
+
+            // The LAME extension stores the delay and padding as two 12 bits numbers, after 21
+            // bytes of information about the encoding.
+            let gapless = match frame.get(lame..lame + 24) {
+                Some(tag) if tag[..4].iter().all(u8::is_ascii_alphanumeric) => {
+                    let delay = ((tag[21] as u32) << 4) | (tag[22] as u32 >> 4);
+                    let padding = ((tag[22] as u32 & 0x0f) << 8) | tag[23] as u32;
+                    Some((delay, padding))
+                }
+                _ => None,
+            };
+            return Some(InfoFrame {
+                frames,
+                bytes,
+                toc,
+                gapless,
+            });
+        }
+        _ => (),
+    }
+
+    // The VBRI header always comes right after 32 bytes of side information.
+    match frame.get(36..40) {
+        Some(b"VBRI") => Some(InfoFrame {
+            frames: read_u32(36 + 14),
+            bytes: read_u32(36 + 10),
+            toc: read_vbri_toc(&frame[36..]),
+            gapless: None,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads the table of contents of a VBRI header, which holds the lengths of the parts of the
+/// stream in entries of 1 to 4 bytes, to multiply by a scale factor.
+fn read_vbri_toc(vbri: &[u8]) -> Option<Toc> {
+    let read_u16 = |offset: usize| {
+        vbri.get(offset..offset + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+    };
+    let entries = read_u16(18)? as usize;
+    let scale = read_u16(20)?;
+    let entry_size = read_u16(22)? as usize;
+    let frames_per_entry = read_u16(24)?;
+    if !(1..=4).contains(&entry_size) || frames_per_entry == 0 {
+        return None;
+    }
+
+    let lengths = vbri
+        .get(26..26 + entries * entry_size)?
+        .chunks(entry_size)
+        .map(|entry| {
+            let len = entry.iter().fold(0, |len, &byte| (len << 8) | byte as u32);
+            len.saturating_mul(scale)
+        })
+        .collect();
+    Some(Toc::Vbri {
+        frames_per_entry,
+        lengths,
+    })
+}
+
+/// Counts the samples of all the frames, stopping at the first invalid header.
+fn scan_frames<R>(mut data: R) -> io::Result<u64>
+where
+    R: Read + Seek,
+{
+    let mut total_samples = 0;
+    let mut bytes = [0; 4];
+    loop {
+        if read_up_to(data.by_ref(), &mut bytes)? < bytes.len() {
+            return Ok(total_samples);
+        }
+        let header = match FrameHeader::parse(bytes) {
+            Some(header) => header,
+            None => return Ok(total_samples),
+        };
+        total_samples += header.samples() as u64;
+        data.seek(SeekFrom::Current(header.frame_len() as i64 - 4))?;
+    }
+}
+
+fn header_at(buffer: &[u8], offset: usize) -> Option<FrameHeader> {
+    let bytes = buffer.get(offset..offset + 4)?;
+    FrameHeader::parse([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Reads until the buffer is full or the end of the data is reached.
+fn read_up_to<R>(mut data: R, buffer: &mut [u8]) -> io::Result<usize>
+where
+    R: Read,
+{
+    let mut len = 0;
+    while len < buffer.len() {
+        match data.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_info_frame, seek_points, FrameHeader, StreamInfo, Toc};
+
+    #[test]
+    fn frame_header() {
+        // MPEG-1 layer III, 128 kbit/s, 44100 Hz, joint stereo.
+        let header = FrameHeader::parse([0xff, 0xfb, 0x90, 0x64]).unwrap();
+        assert_eq!(header.samples(), 1152);
+        assert_eq!(header.frame_len(), 417);
+
+        // MPEG-2 layer III, 64 kbit/s, 22050 Hz, padded.
+        let header = FrameHeader::parse([0xff, 0xf3, 0x82, 0xc4]).unwrap();
+        assert_eq!(header.samples(), 576);
+        assert_eq!(header.frame_len(), 209);
+
+        assert!(FrameHeader::parse([0xff, 0xfb, 0xf0, 0x64]).is_none());
+        assert!(FrameHeader::parse(*b"TAG ").is_none());
+    }
+
+    #[test]
+    fn vbri_seek_points() {
+        // MPEG-1 layer III, 128 kbit/s, 44100 Hz, joint stereo.
+        let header = FrameHeader::parse([0xff, 0xfb, 0x90, 0x64]).unwrap();
+        let mut frame = vec![0; 36];
+        frame.extend_from_slice(b"VBRI");
+        // Version, delay and quality.
+        frame.extend_from_slice(&[0, 1, 0, 0, 0, 0]);
+        frame.extend_from_slice(&3000u32.to_be_bytes());
+        frame.extend_from_slice(&30u32.to_be_bytes());
+        // 3 entries of 2 bytes, to multiply by 2, of 10 frames each.
+        for value in &[3u16, 2, 2, 10, 500, 400, 600] {
+            frame.extend_from_slice(&value.to_be_bytes());
+        }
+        frame.resize(header.frame_len(), 0);
+
+        let info_frame = read_info_frame(&header, &frame).unwrap();
+        assert_eq!(info_frame.frames, Some(30));
+        assert_eq!(
+            info_frame.toc,
+            Some(Toc::Vbri {
+                frames_per_entry: 10,
+                lengths: vec![1000, 800, 1200],
+            })
+        );
+
+        let info = StreamInfo {
+            seek_points: seek_points(info_frame.toc.as_ref(), 100, 1152, Some(34560), Some(3000)),
+            ..StreamInfo::default()
+        };
+        assert_eq!(info.byte_position(0), Some(100));
+        assert_eq!(info.byte_position(11520), Some(1100));
+        // Halfway through the second entry.
+        assert_eq!(info.byte_position(17280), Some(1500));
+        assert_eq!(info.byte_position(1_000_000), Some(3100));
+    }
+}
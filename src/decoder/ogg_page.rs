@@ -0,0 +1,54 @@
+//! Reading of the pages of Ogg streams, shared by the Vorbis and Opus decoders.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Returns the granule position of the last page of the stream, which is the number of
+/// inter-channel samples up to the end of the stream, then resets the stream to where it was.
+pub fn last_granule_position<R>(mut data: R) -> io::Result<Option<u64>>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    let granule_position = read_last_granule_position(data.by_ref(), stream_pos);
+    data.seek(SeekFrom::Start(stream_pos))?;
+    granule_position
+}
+
+fn read_last_granule_position<R>(mut data: R, stream_pos: u64) -> io::Result<Option<u64>>
+where
+    R: Read + Seek,
+{
+    // Only the pages of the same logical stream as the first page are relevant.
+    let mut first_page = [0; 27];
+    if data.read_exact(&mut first_page).is_err() || &first_page[0..4] != b"OggS" {
+        return Ok(None);
+    }
+    let serial = &first_page[14..18];
+
+    // A page is at most 65307 bytes long, so the last one starts within that distance from the
+    // end of the stream.
+    let end = match data.seek(SeekFrom::End(0)) {
+        Ok(end) => end,
+        // The end of streams that cannot seek is unknown.
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let start = end.saturating_sub(65307).max(stream_pos);
+    data.seek(SeekFrom::Start(start))?;
+    let mut buffer = Vec::new();
+    data.read_to_end(&mut buffer)?;
+
+    let granule_position = (0..buffer.len().saturating_sub(27))
+        .rev()
+        .filter(|&i| &buffer[i..i + 4] == b"OggS" && buffer[i + 4] == 0)
+        .filter(|&i| &buffer[i + 14..i + 18] == serial)
+        .map(|i| {
+            let mut granule_position = [0; 8];
+            granule_position.copy_from_slice(&buffer[i + 6..i + 14]);
+            u64::from_le_bytes(granule_position)
+        })
+        // Pages where no packet ends have a granule position of -1.
+        .find(|&granule_position| granule_position != u64::MAX);
+
+    Ok(granule_position)
+}
@@ -0,0 +1,405 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+use std::vec;
+
+use super::metadata::Metadata;
+use super::ogg_page::last_granule_position;
+use super::DecoderError;
+use crate::channel_layout::{ChannelLayout, Speaker};
+use crate::source::SeekError;
+use crate::Source;
+
+use ogg::{Packet, PacketReader};
+use opus_codec::MSDecoder;
+
+/// Opus streams are always decoded at 48 kHz, whatever the sample rate of the original audio.
+const SAMPLE_RATE: u32 = 48000;
+
+/// Number of samples per channel of the longest Opus packet, which lasts 120 ms.
+const MAX_PACKET_SAMPLES: usize = 5760;
+
+/// Number of samples per channel decoded before the target of a seek, so that the decoder has
+/// converged once it reaches it. RFC 7845 recommends 80 ms.
+const SEEK_PRE_ROLL: u64 = 3840;
+
+/// Decoder for an OGG file that contains Opus sound format.
+pub struct OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    packet_reader: PacketReader<R>,
+    decoder: MSDecoder,
+    serial: u32,
+    channels: u16,
+    channel_layout: ChannelLayout,
+    // Number of samples per channel at the start of the stream that are not part of the audio.
+    pre_skip: u64,
+    // Number of samples per channel still to be dropped from the next decoded packets.
+    skip: usize,
+    // Granule position of the end of the last decoded packet, if known.
+    granule: Option<u64>,
+    buffer: Vec<f32>,
+    current_data: vec::IntoIter<f32>,
+    // Length of the stream in inter-channel samples, if known.
+    total_samples: Option<u64>,
+    metadata: Metadata,
+    error: Option<DecoderError>,
+}
+
+impl<R> OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    /// Attempts to decode the data as ogg/opus.
+    pub fn new(mut data: R) -> Result<OpusDecoder<R>, DecoderError> {
+        let last_granule = last_granule_position(data.by_ref())?;
+        let mut packet_reader = PacketReader::new(data);
+
+        let packet = packet_reader.read_packet_expected()?;
+        let serial = packet.stream_serial();
+        let header = OpusHeader::parse(&packet.data)?;
+
+        let packet = packet_reader.read_packet_expected()?;
+        if !packet.data.starts_with(b"OpusTags") {
+            return Err(DecoderError::DecodeError(
+                "missing OpusTags header".to_owned(),
+            ));
+        }
+        let mut metadata = Metadata::default();
+        let comments = parse_comments(&packet.data[8..]);
+        metadata.push_vorbis_comments(comments.iter().map(|(n, v)| (n.as_str(), v.as_str())));
+
+        let mut decoder = MSDecoder::new(
+            SAMPLE_RATE,
+            header.streams,
+            header.coupled_streams,
+            &header.mapping,
+        )?;
+        // The gain is in dB in Q7.8 format, just like the output gain of the header.
+        decoder.set_gain(header.output_gain as i32)?;
+
+        let channels = header.mapping.len();
+        // Family 1 uses the Vorbis order, and other families do not assign speakers.
+        let channel_layout = match header.mapping_family {
+            0 | 1 => ChannelLayout::vorbis(channels as u16),
+            _ => ChannelLayout::new(vec![Speaker::Unknown; channels]),
+        };
+        let pre_skip = header.pre_skip as u64;
+        let mut decoder = OpusDecoder {
+            packet_reader,
+            decoder,
+            serial,
+            channels: channels as u16,
+            channel_layout,
+            pre_skip,
+            skip: header.pre_skip as usize,
+            granule: Some(0),
+            buffer: vec![0.0; MAX_PACKET_SAMPLES * channels],
+            current_data: Vec::new().into_iter(),
+            total_samples: last_granule.map(|granule| granule.saturating_sub(pre_skip)),
+            metadata,
+            error: None,
+        };
+
+        // Decoding the first packet reports invalid streams right away.
+        if let Some(data) = decoder.read_packet() {
+            decoder.current_data = data.into_iter();
+        }
+        match decoder.error.take() {
+            Some(error) => Err(error),
+            None => Ok(decoder),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.packet_reader.into_inner()
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn channel_layout(&self) -> &ChannelLayout {
+        &self.channel_layout
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        self.error.take()
+    }
+
+    /// Reads and decodes the next packet that has samples left after the pre-skip, keeping the
+    /// error if that fails.
+    fn read_packet(&mut self) -> Option<Vec<f32>> {
+        loop {
+            let packet = match self.packet_reader.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return None,
+                Err(error) => {
+                    self.error = Some(error.into());
+                    return None;
+                }
+            };
+            if packet.stream_serial() != self.serial {
+                continue;
+            }
+
+            let channels = self.channels as usize;
+            let skip = self.skip;
+            let (data, skipped) = match self.decode_packet(&packet) {
+                Ok(samples) => {
+                    let skipped = skip.min(samples.len() / channels);
+                    (samples[skipped * channels..].to_vec(), skipped)
+                }
+                Err(error) => {
+                    self.error = Some(error);
+                    return None;
+                }
+            };
+            self.skip -= skipped;
+            if !data.is_empty() {
+                return Some(data);
+            }
+        }
+    }
+
+    /// Decodes a packet and returns its samples, without those past the end of the stream.
+    fn decode_packet(&mut self, packet: &Packet) -> Result<&[f32], DecoderError> {
+        let len = self
+            .decoder
+            .decode_float(&packet.data, &mut self.buffer, false)? as u64;
+
+        let mut end = len;
+        if let Some(granule) = self.granule {
+            // The last page of the stream can end before the end of its last packet, in which
+            // case the remaining samples are padding.
+            if packet.last_in_stream() && granule + len > packet.absgp_page() {
+                end = packet.absgp_page().saturating_sub(granule);
+            }
+            self.granule = Some(granule + end);
+        }
+        if packet.last_in_page() {
+            self.granule = Some(packet.absgp_page());
+        }
+
+        Ok(&self.buffer[..end as usize * self.channels as usize])
+    }
+}
+
+impl<R> Source for OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.current_data.len())
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_samples
+            .map(|s| Duration::from_micros(s * 1_000_000 / SAMPLE_RATE as u64))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = self.channels as usize;
+        // Granule positions count the pre-skip samples as well.
+        let target = (pos.as_secs_f64() * SAMPLE_RATE as f64) as u64 + self.pre_skip;
+
+        // Seeking only has a page granularity and lands at or before the pre-roll. The exact
+        // position is only known once a page boundary has been decoded.
+        let found = self
+            .packet_reader
+            .seek_absgp(Some(self.serial), target.saturating_sub(SEEK_PRE_ROLL))
+            .map_err(|err| SeekError::OpusDecoder(err.into()))?;
+        if !found {
+            // The target is past the end of the stream.
+            self.packet_reader
+                .seek_bytes(SeekFrom::End(0))
+                .map_err(SeekError::Io)?;
+        }
+        self.decoder
+            .reset_state()
+            .map_err(|err| SeekError::OpusDecoder(err.into()))?;
+        self.granule = None;
+        self.skip = 0;
+
+        let mut data = Vec::new();
+        loop {
+            let packet = match self.packet_reader.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => break,
+                Err(err) => return Err(SeekError::OpusDecoder(err.into())),
+            };
+            // Seeking before the first audio page lands on the headers, which we skip.
+            if packet.stream_serial() != self.serial || is_header(&packet.data) {
+                continue;
+            }
+            let samples = self
+                .decode_packet(&packet)
+                .map_err(SeekError::OpusDecoder)?;
+            data.extend_from_slice(samples);
+
+            if let Some(end) = self.granule {
+                if end > target {
+                    let start = end.saturating_sub((data.len() / channels) as u64);
+                    let skip = target.saturating_sub(start) as usize * channels;
+                    data.drain(..skip.min(data.len()));
+                    break;
+                }
+                data.clear();
+            }
+        }
+
+        self.current_data = data.into_iter();
+        Ok(())
+    }
+}
+
+impl<R> Iterator for OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if let Some(sample) = self.current_data.next() {
+            if self.current_data.len() == 0 {
+                if let Some(data) = self.read_packet() {
+                    self.current_data = data.into_iter();
+                }
+            }
+            Some(sample)
+        } else {
+            if let Some(data) = self.read_packet() {
+                self.current_data = data.into_iter();
+            }
+            self.current_data.next()
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.current_data.size_hint().0, None)
+    }
+}
+
+/// Content of the `OpusHead` identification header.
+struct OpusHeader {
+    pre_skip: u16,
+    /// Gain to apply to the decoded samples, in dB in Q7.8 format.
+    output_gain: i16,
+    streams: u8,
+    coupled_streams: u8,
+    mapping_family: u8,
+    /// Index of the decoded stream channel of each output channel.
+    mapping: Vec<u8>,
+}
+
+impl OpusHeader {
+    fn parse(data: &[u8]) -> Result<OpusHeader, DecoderError> {
+        if data.len() < 19 || !data.starts_with(b"OpusHead") {
+            return Err(DecoderError::DecodeError(
+                "missing OpusHead header".to_owned(),
+            ));
+        }
+        // Versions with the same major version are backwards compatible.
+        if data[8] >> 4 != 0 {
+            return Err(DecoderError::UnsupportedEncoding(format!(
+                "Opus version {}",
+                data[8]
+            )));
+        }
+
+        let channels = data[9];
+        let pre_skip = u16::from_le_bytes([data[10], data[11]]);
+        let output_gain = i16::from_le_bytes([data[16], data[17]]);
+        let (streams, coupled_streams, mapping) = match data[18] {
+            // Mono or stereo in a single stream.
+            0 if channels == 1 || channels == 2 => (1, channels - 1, (0..channels).collect()),
+            0 => {
+                return Err(DecoderError::DecodeError(format!(
+                    "{} channels without a channel mapping",
+                    channels
+                )))
+            }
+            _ => match data.get(19..21 + channels as usize) {
+                Some(table) if channels > 0 => (table[0], table[1], table[2..].to_vec()),
+                _ => {
+                    return Err(DecoderError::DecodeError(
+                        "invalid channel mapping table".to_owned(),
+                    ))
+                }
+            },
+        };
+
+        Ok(OpusHeader {
+            pre_skip,
+            output_gain,
+            streams,
+            coupled_streams,
+            mapping_family: data[18],
+            mapping,
+        })
+    }
+}
+
+/// Parses the comments of an `OpusTags` header, which follow the Vorbis comment format.
+fn parse_comments(mut data: &[u8]) -> Vec<(String, String)> {
+    fn read_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = data.get(0..4)?;
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let value = data.get(4..4 + len)?;
+        *data = &data[4 + len..];
+        Some(value)
+    }
+
+    let mut comments = Vec::new();
+    // The vendor string is not a comment.
+    if read_bytes(&mut data).is_none() || data.len() < 4 {
+        return comments;
+    }
+    let count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    data = &data[4..];
+
+    for _ in 0..count {
+        let comment = match read_bytes(&mut data) {
+            Some(comment) => String::from_utf8_lossy(comment),
+            None => break,
+        };
+        if let Some(separator) = comment.find('=') {
+            let (name, value) = comment.split_at(separator);
+            comments.push((name.to_owned(), value[1..].to_owned()));
+        }
+    }
+    comments
+}
+
+fn is_header(packet: &[u8]) -> bool {
+    packet.starts_with(b"OpusHead") || packet.starts_with(b"OpusTags")
+}
+
+/// Returns true if the stream contains Opus data, then resets it to where it was.
+pub fn is_opus<R>(mut data: R) -> io::Result<bool>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    let is_opus = match PacketReader::new(data.by_ref()).read_packet() {
+        Ok(Some(packet)) => packet.data.starts_with(b"OpusHead"),
+        _ => false,
+    };
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(is_opus)
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Number of bytes read at once from the underlying reader.
+const CHUNK_LEN: usize = 8 * 1024;
+
+/// Adapts a reader that cannot seek, such as a pipe, to the decoders.
+///
+/// The last bytes read are kept in a bounded look-ahead buffer, which lets the decoders rewind
+/// the data after probing its format. Seeking before the start of that buffer, to the end of the
+/// data or further ahead than the size of the buffer fails with `io::ErrorKind::Unsupported`.
+///
+/// Built by [`DecoderBuilder::build_from_read`](super::DecoderBuilder::build_from_read).
+pub struct Unseekable<R> {
+    inner: R,
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    // Position in the stream of the first byte of the buffer.
+    buffer_start: u64,
+    // Position in the stream of the next byte to read.
+    pos: u64,
+}
+
+impl<R> Unseekable<R>
+where
+    R: Read,
+{
+    /// Wraps a reader, keeping up to `capacity` bytes to rewind to.
+    #[inline]
+    pub(crate) fn new(inner: R, capacity: usize) -> Unseekable<R> {
+        Unseekable {
+            inner,
+            buffer: VecDeque::new(),
+            capacity: capacity.max(CHUNK_LEN),
+            buffer_start: 0,
+            pos: 0,
+        }
+    }
+
+    /// Destroys this adapter and returns the underlying reader.
+    ///
+    /// The bytes already read from it but not consumed by the decoder are lost.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    #[inline]
+    fn buffer_end(&self) -> u64 {
+        self.buffer_start + self.buffer.len() as u64
+    }
+
+    /// Reads the next chunk of the underlying reader into the buffer. Returns false at the end
+    /// of the data.
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; CHUNK_LEN];
+        let len = loop {
+            match self.inner.read(&mut chunk) {
+                Ok(len) => break len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        self.buffer.extend(&chunk[..len]);
+
+        // Only the bytes before the current position can be dropped.
+        let excess = (self.buffer.len().saturating_sub(self.capacity) as u64)
+            .min(self.pos - self.buffer_start);
+        self.buffer.drain(..excess as usize);
+        self.buffer_start += excess;
+        Ok(len > 0)
+    }
+}
+
+impl<R> Read for Unseekable<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buffer_end() {
+            if !self.fill_buffer()? {
+                return Ok(0);
+            }
+        }
+
+        let offset = (self.pos - self.buffer_start) as usize;
+        let (front, back) = self.buffer.as_slices();
+        let available = match front.get(offset..) {
+            Some(available) if !available.is_empty() => available,
+            _ => &back[offset - front.len()..],
+        };
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R> Seek for Unseekable<R>
+where
+    R: Read,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => {
+                if offset >= 0 {
+                    self.pos.checked_add(offset as u64)
+                } else {
+                    self.pos.checked_sub(offset.unsigned_abs())
+                }
+            }
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the end of the stream is unknown",
+                ))
+            }
+        };
+        let target = target
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+
+        if target < self.buffer_start {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the stream cannot be rewound further than its look-ahead buffer",
+            ));
+        }
+        // Seeking forward reads the data in between, which is only worth it for short distances.
+        if target > self.pos.max(self.buffer_end()) + self.capacity as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the stream cannot skip more than its look-ahead buffer",
+            ));
+        }
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewinds_within_the_buffer() {
+        let data: Vec<u8> = (0..40000u32).map(|i| i as u8).collect();
+        let mut reader = Unseekable::new(&data[..], 16 * 1024);
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        reader.seek(SeekFrom::Start(1)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+
+        reader.seek(SeekFrom::Current(20000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[20005..20009]);
+        assert!(reader.seek(SeekFrom::Start(0)).is_err());
+        assert!(reader.seek(SeekFrom::End(0)).is_err());
+        assert!(reader.seek(SeekFrom::Current(-10000)).is_ok());
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, data[10009..]);
+    }
+}
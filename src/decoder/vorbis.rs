@@ -1,10 +1,17 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::time::Duration;
 use std::vec;
 
+use super::metadata::Metadata;
+use super::ogg_page::last_granule_position;
+use super::{DecoderError, Resync};
+use crate::source::SeekError;
 use crate::Source;
 
+use lewton::audio::AudioReadError;
 use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::InterleavedSamples;
+use lewton::{OggReadError, VorbisError};
 
 /// Decoder for an OGG file that contains Vorbis sound format.
 pub struct VorbisDecoder<R>
@@ -12,7 +19,14 @@ where
     R: Read + Seek,
 {
     stream_reader: OggStreamReader<R>,
-    current_data: vec::IntoIter<i16>,
+    current_data: vec::IntoIter<f32>,
+    // Granule position of the first sample of the stream.
+    start_granule: u64,
+    // Length of the stream in inter-channel samples, if known.
+    total_samples: Option<u64>,
+    metadata: Metadata,
+    resync: Resync,
+    error: Option<DecoderError>,
 }
 
 impl<R> VorbisDecoder<R>
@@ -20,34 +34,98 @@ where
     R: Read + Seek,
 {
     /// Attempts to decode the data as ogg/vorbis.
-    pub fn new(mut data: R) -> Result<VorbisDecoder<R>, R> {
-        if !is_vorbis(data.by_ref()) {
-            return Err(data);
+    pub fn new(mut data: R, resync: Resync) -> Result<VorbisDecoder<R>, DecoderError> {
+        let total_samples = last_granule_position(data.by_ref())?;
+        let stream_reader = OggStreamReader::new(data)?;
+        let mut decoder = Self::from_stream_reader(stream_reader, resync);
+        decoder.total_samples = total_samples.map(|s| s.saturating_sub(decoder.start_granule));
+        match decoder.error.take() {
+            Some(error) => Err(error),
+            None => Ok(decoder),
         }
-
-        let stream_reader = OggStreamReader::new(data).unwrap();
-        Ok(Self::from_stream_reader(stream_reader))
     }
-    pub fn from_stream_reader(mut stream_reader: OggStreamReader<R>) -> Self {
-        let mut data = match stream_reader.read_dec_packet_itl() {
-            Ok(Some(d)) => d,
-            _ => Vec::new(),
-        };
+    pub fn from_stream_reader(mut stream_reader: OggStreamReader<R>, resync: Resync) -> Self {
+        let channels = stream_reader.ident_hdr.audio_channels as usize;
+        let mut error = None;
 
-        // The first packet is always empty, therefore
-        // we need to read the second frame to get some data
-        if let Ok(Some(mut d)) = stream_reader.read_dec_packet_itl() {
-            data.append(&mut d);
+        // The whole first audio page is decoded, as its granule position tells where the stream
+        // starts. The first packet is always empty.
+        let mut data = Vec::new();
+        while stream_reader.get_last_absgp().is_none() {
+            match read_dec_packet_f32(&mut stream_reader, &resync) {
+                Ok(Some(mut d)) => data.append(&mut d),
+                Ok(None) => break,
+                Err(err) => {
+                    error = Some(err.into());
+                    break;
+                }
+            }
         }
 
+        // A granule position lower than the number of decoded samples means that samples at the
+        // start must be dropped, for example the priming samples of the encoder. A higher one
+        // means that the stream starts later than at zero, which happens with streams that have
+        // been cut from a longer one.
+        let decoded = (data.len() / channels) as u64;
+        let start_granule = match stream_reader.get_last_absgp() {
+            Some(granule) if granule < decoded => {
+                data.drain(..(decoded - granule) as usize * channels);
+                0
+            }
+            Some(granule) => granule - decoded,
+            None => 0,
+        };
+
+        let mut metadata = Metadata::default();
+        metadata.push_vorbis_comments(
+            stream_reader
+                .comment_hdr
+                .comment_list
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+
         VorbisDecoder {
             stream_reader,
             current_data: data.into_iter(),
+            start_granule,
+            total_samples: None,
+            metadata,
+            resync,
+            error,
         }
     }
     pub fn into_inner(self) -> OggStreamReader<R> {
         self.stream_reader
     }
+
+    pub fn resync(&self) -> &Resync {
+        &self.resync
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        self.error.take()
+    }
+
+    /// Reads the next packet, keeping the error if that fails.
+    fn read_packet(&mut self) -> Option<Vec<f32>> {
+        // The packets that follow an error are not played.
+        if self.error.is_some() {
+            return None;
+        }
+        match read_dec_packet_f32(&mut self.stream_reader, &self.resync) {
+            Ok(packet) => packet,
+            Err(error) => {
+                self.error = Some(error.into());
+                None
+            }
+        }
+    }
 }
 
 impl<R> Source for VorbisDecoder<R>
@@ -71,7 +149,46 @@ where
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        None
+        let sample_rate = self.sample_rate() as u64;
+        self.total_samples
+            .map(|s| Duration::from_micros(s * 1_000_000 / sample_rate))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = self.channels() as usize;
+        let target = (pos.as_secs_f64() * self.sample_rate() as f64) as u64 + self.start_granule;
+
+        // Seeking only has a page granularity and lands at or before `target`. The exact position
+        // is only known once a page boundary has been decoded, after which lewton keeps track of
+        // the absolute granule position of every packet.
+        self.stream_reader
+            .seek_absgp_pg(target)
+            .map_err(SeekError::LewtonDecoder)?;
+
+        let mut data = Vec::new();
+        loop {
+            match read_dec_packet_f32(&mut self.stream_reader, &self.resync) {
+                Ok(Some(mut packet)) => data.append(&mut packet),
+                Ok(None) => break,
+                // Seeking before the first audio page lands on the headers, which we skip.
+                Err(VorbisError::BadAudio(AudioReadError::AudioIsHeader)) => continue,
+                Err(err) => return Err(SeekError::LewtonDecoder(err)),
+            }
+
+            if let Some(end) = self.stream_reader.get_last_absgp() {
+                if end > target {
+                    // The first page may start before zero, when its first samples are dropped.
+                    let start = end as i64 - (data.len() / channels) as i64;
+                    let skip = (target as i64 - start).max(0) as usize * channels;
+                    data.drain(..skip.min(data.len()));
+                    break;
+                }
+                data.clear();
+            }
+        }
+
+        self.current_data = data.into_iter();
+        Ok(())
     }
 }
 
@@ -79,19 +196,19 @@ impl<R> Iterator for VorbisDecoder<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
         if let Some(sample) = self.current_data.next() {
             if self.current_data.len() == 0 {
-                if let Ok(Some(data)) = self.stream_reader.read_dec_packet_itl() {
+                if let Some(data) = self.read_packet() {
                     self.current_data = data.into_iter();
                 }
             }
             Some(sample)
         } else {
-            if let Ok(Some(data)) = self.stream_reader.read_dec_packet_itl() {
+            if let Some(data) = self.read_packet() {
                 self.current_data = data.into_iter();
             }
             self.current_data.next()
@@ -104,18 +221,37 @@ where
     }
 }
 
-/// Returns true if the stream contains Vorbis data, then resets it to where it was.
-fn is_vorbis<R>(mut data: R) -> bool
+/// Reads and decodes the next audio packet as interleaved `f32` samples.
+///
+/// When resynchronizing, packets that cannot be decoded are skipped. The Ogg reader then searches
+/// for the next valid page by itself.
+fn read_dec_packet_f32<R>(
+    stream_reader: &mut OggStreamReader<R>,
+    resync: &Resync,
+) -> Result<Option<Vec<f32>>, VorbisError>
 where
     R: Read + Seek,
 {
-    let stream_pos = data.seek(SeekFrom::Current(0)).unwrap();
-
-    if OggStreamReader::new(data.by_ref()).is_err() {
-        data.seek(SeekFrom::Start(stream_pos)).unwrap();
-        return false;
+    loop {
+        match stream_reader.read_dec_packet_generic::<InterleavedSamples<f32>>() {
+            Ok(packet) => return Ok(packet.map(|packet| packet.samples)),
+            // Failing to read the data is not something that skipping can fix, and headers found
+            // after seeking before the first audio page are not damaged.
+            Err(err @ VorbisError::OggError(OggReadError::ReadError(_)))
+            | Err(err @ VorbisError::BadAudio(AudioReadError::AudioIsHeader)) => return Err(err),
+            Err(_) if resync.enabled => resync.skip(1),
+            Err(err) => return Err(err),
+        }
     }
+}
 
-    data.seek(SeekFrom::Start(stream_pos)).unwrap();
-    true
+/// Returns true if the stream contains Vorbis data, then resets it to where it was.
+pub fn is_vorbis<R>(mut data: R) -> io::Result<bool>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    let is_vorbis = OggStreamReader::new(data.by_ref()).is_ok();
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(is_vorbis)
 }
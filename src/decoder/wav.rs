@@ -1,6 +1,12 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::time::Duration;
 
+use super::metadata::{Marker, Metadata};
+use super::wav_codec::{self, CompressedSamples};
+use super::DecoderError;
+use crate::channel_layout::ChannelLayout;
+use crate::conversions::sample::int_to_f32;
+use crate::source::SeekError;
 use crate::Source;
 
 use hound::{SampleFormat, WavReader};
@@ -10,9 +16,11 @@ pub struct WavDecoder<R>
 where
     R: Read + Seek,
 {
-    reader: SamplesIterator<R>,
+    reader: Samples<R>,
     sample_rate: u32,
     channels: u16,
+    channel_layout: ChannelLayout,
+    metadata: Metadata,
 }
 
 impl<R> WavDecoder<R>
@@ -20,67 +28,120 @@ where
     R: Read + Seek,
 {
     /// Attempts to decode the data as WAV.
-    pub fn new(mut data: R) -> Result<WavDecoder<R>, R> {
-        if !is_wave(data.by_ref()) {
-            return Err(data);
+    pub fn new(mut data: R) -> Result<WavDecoder<R>, DecoderError> {
+        let (metadata, channel_mask) = read_info(data.by_ref())?;
+
+        // Hound only supports PCM and floating point samples.
+        if let Some(header) = wav_codec::read_header(data.by_ref())? {
+            return Ok(WavDecoder {
+                sample_rate: header.sample_rate,
+                channels: header.channels,
+                channel_layout: ChannelLayout::default_for(header.channels),
+                reader: Samples::Compressed(CompressedSamples::new(data, header)?),
+                metadata,
+            });
         }
 
-        let reader = WavReader::new(data).unwrap();
+        let reader = WavReader::new(EofReader(data))?;
         let spec = reader.spec();
+        match (spec.sample_format, spec.bits_per_sample) {
+            (SampleFormat::Float, 32) | (SampleFormat::Int, 8..=32) => (),
+            (sample_format, bits_per_sample) => {
+                return Err(DecoderError::UnsupportedEncoding(format!(
+                    "{} bits {:?} WAV samples",
+                    bits_per_sample, sample_format
+                )))
+            }
+        }
         let reader = SamplesIterator {
             reader,
             samples_read: 0,
+            error: None,
         };
 
         Ok(WavDecoder {
-            reader,
+            reader: Samples::Pcm(reader),
             sample_rate: spec.sample_rate,
             channels: spec.channels,
+            channel_layout: match channel_mask {
+                0 => ChannelLayout::default_for(spec.channels),
+                mask => ChannelLayout::from_wav_mask(mask, spec.channels),
+            },
+            metadata,
         })
     }
     pub fn into_inner(self) -> R {
-        self.reader.reader.into_inner()
+        match self.reader {
+            Samples::Pcm(reader) => reader.reader.into_inner().0,
+            Samples::Compressed(reader) => reader.into_inner(),
+        }
     }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn channel_layout(&self) -> &ChannelLayout {
+        &self.channel_layout
+    }
+
+    /// Returns the error that made the decoder stop, if any.
+    pub fn take_error(&mut self) -> Option<DecoderError> {
+        match &mut self.reader {
+            Samples::Pcm(reader) => reader.error.take(),
+            Samples::Compressed(reader) => reader.error.take(),
+        }
+    }
+}
+
+/// The samples of a WAV file, decoded by hound or, for the encodings it does not support, by
+/// `wav_codec`.
+enum Samples<R>
+where
+    R: Read + Seek,
+{
+    Pcm(SamplesIterator<R>),
+    Compressed(CompressedSamples<R>),
 }
 
 struct SamplesIterator<R>
 where
     R: Read + Seek,
 {
-    reader: WavReader<R>,
+    reader: WavReader<EofReader<R>>,
     samples_read: u32,
+    error: Option<DecoderError>,
 }
 
 impl<R> Iterator for SamplesIterator<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
+    fn next(&mut self) -> Option<f32> {
         let spec = self.reader.spec();
-        match (spec.sample_format, spec.bits_per_sample) {
-            (SampleFormat::Float, 32) => self.reader.samples().next().map(|value| {
-                self.samples_read += 1;
-                f32_to_i16(value.unwrap_or(0.0))
-            }),
-            (SampleFormat::Int, 16) => self.reader.samples().next().map(|value| {
-                self.samples_read += 1;
-                value.unwrap_or(0)
-            }),
-            (SampleFormat::Int, 24) => self.reader.samples().next().map(|value| {
-                self.samples_read += 1;
-                i24_to_i16(value.unwrap_or(0))
-            }),
-            (SampleFormat::Int, 8) => self.reader.samples().next().map(|value| {
+        let sample = match (spec.sample_format, spec.bits_per_sample) {
+            (SampleFormat::Float, 32) => self.reader.samples().next()?,
+            (SampleFormat::Int, bits_per_sample) => self
+                .reader
+                .samples()
+                .next()?
+                .map(|value| int_to_f32(value, bits_per_sample as u32)),
+            // Other formats are rejected by `WavDecoder::new`.
+            _ => return None,
+        };
+
+        match sample {
+            Ok(sample) => {
                 self.samples_read += 1;
-                i8_to_i16(value.unwrap_or(0))
-            }),
-            (sample_format, bits_per_sample) => panic!(
-                "Unimplemented wav spec: {:?}, {}",
-                sample_format, bits_per_sample
-            ),
+                Some(sample)
+            }
+            Err(error) => {
+                self.error = Some(error.into());
+                None
+            }
         }
     }
 
@@ -93,6 +154,33 @@ where
 
 impl<R> ExactSizeIterator for SamplesIterator<R> where R: Read + Seek {}
 
+/// Reader that reports the end of the data as an error of kind `UnexpectedEof`.
+///
+/// Hound only reads data that it expects to find, but reports missing data as an error of kind
+/// `Other`, which could not be told apart from other errors.
+struct EofReader<R>(R);
+
+impl<R> Read for EofReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf)? {
+            0 if !buf.is_empty() => Err(io::ErrorKind::UnexpectedEof.into()),
+            len => Ok(len),
+        }
+    }
+}
+
+impl<R> Seek for EofReader<R>
+where
+    R: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
 impl<R> Source for WavDecoder<R>
 where
     R: Read + Seek,
@@ -114,65 +202,262 @@ where
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        let ms = self.len() as u64 * 1000 / (self.channels as u64 * self.sample_rate as u64);
+        // The duration of the whole file, however much of it has already been read.
+        let frames = match &self.reader {
+            Samples::Pcm(reader) => reader.reader.duration() as u64,
+            Samples::Compressed(reader) => reader.frames(),
+        };
+        let ms = frames * 1000 / self.sample_rate as u64;
         Some(Duration::from_millis(ms))
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let new_pos = (pos.as_secs_f64() * self.sample_rate as f64) as u64;
+        let reader = match &mut self.reader {
+            Samples::Pcm(reader) => reader,
+            Samples::Compressed(reader) => return reader.seek(new_pos).map_err(SeekError::Io),
+        };
+
+        // `WavReader::seek` works in inter-channel samples and saturates at the end of the file.
+        let file_len = reader.reader.duration();
+        let new_pos = new_pos.min(file_len as u64) as u32;
+
+        reader
+            .reader
+            .seek(new_pos)
+            .map_err(SeekError::HoundDecoder)?;
+        reader.samples_read = new_pos * self.channels as u32;
+        Ok(())
+    }
 }
 
 impl<R> Iterator for WavDecoder<R>
 where
     R: Read + Seek,
 {
-    type Item = i16;
+    type Item = f32;
 
     #[inline]
-    fn next(&mut self) -> Option<i16> {
-        self.reader.next()
+    fn next(&mut self) -> Option<f32> {
+        match &mut self.reader {
+            Samples::Pcm(reader) => reader.next(),
+            Samples::Compressed(reader) => reader.next(),
+        }
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.reader.size_hint()
+        match &self.reader {
+            Samples::Pcm(reader) => reader.size_hint(),
+            Samples::Compressed(reader) => reader.size_hint(),
+        }
     }
 }
 
 impl<R> ExactSizeIterator for WavDecoder<R> where R: Read + Seek {}
 
-/// Returns true if the stream contains WAV data, then resets it to where it was.
-fn is_wave<R>(mut data: R) -> bool
+/// Returns true if the stream starts with a RIFF WAVE header, then resets it to where it was.
+pub fn is_wave<R>(mut data: R) -> io::Result<bool>
 where
     R: Read + Seek,
 {
-    let stream_pos = data.seek(SeekFrom::Current(0)).unwrap();
+    let stream_pos = data.stream_position()?;
+
+    let mut header = [0; 12];
+    let is_wave = match data.read_exact(&mut header) {
+        Ok(()) => &header[0..4] == b"RIFF" && &header[8..12] == b"WAVE",
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err),
+    };
+
+    data.seek(SeekFrom::Start(stream_pos))?;
+    Ok(is_wave)
+}
+
+/// Reads the tags of the `LIST` chunks of type `INFO`, the loop of the `smpl` chunk, the markers
+/// of the `cue ` chunk and the channel mask of `WAVE_FORMAT_EXTENSIBLE` files, which hound skips.
+/// The mask is zero if there is none.
+fn read_info<R>(mut data: R) -> io::Result<(Metadata, u32)>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+
+    let mut info = Info::default();
+    let _ = read_info_chunks(data.by_ref(), &mut info);
+
+    data.seek(SeekFrom::Start(stream_pos))?;
+
+    // Cue points and their labels can come in any order.
+    let mut metadata = info.metadata;
+    for (id, position) in info.cue_points {
+        let label = info
+            .labels
+            .iter()
+            .find(|(label_id, _)| *label_id == id)
+            .map(|(_, label)| label.clone())
+            .unwrap_or_default();
+        metadata.push_marker(Marker { position, label });
+    }
+    Ok((metadata, info.channel_mask))
+}
+
+/// What `read_info_chunks` finds in the chunks that hound skips.
+#[derive(Default)]
+struct Info {
+    metadata: Metadata,
+    channel_mask: u32,
+    // Identifier and position of each cue point.
+    cue_points: Vec<(u32, u64)>,
+    // Identifier of the cue point and text of each label.
+    labels: Vec<(u32, String)>,
+}
+
+fn read_info_chunks<R>(mut data: R, info: &mut Info) -> io::Result<()>
+where
+    R: Read + Seek,
+{
+    let mut header = [0; 12];
+    data.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Ok(());
+    }
 
-    if WavReader::new(data.by_ref()).is_err() {
-        data.seek(SeekFrom::Start(stream_pos)).unwrap();
-        return false;
+    // The chunks are read until the end of the data is reached.
+    let mut chunk_header = [0; 8];
+    loop {
+        data.read_exact(&mut chunk_header)?;
+        let len = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as usize;
+        // Chunks are padded to an even length.
+        let padding = len % 2;
+
+        if &chunk_header[0..4] == b"LIST" && len >= 4 {
+            let list = read_chunk(&mut data, len)?;
+            match &list[0..4] {
+                b"INFO" => read_info_list(&list[4..], &mut info.metadata),
+                b"adtl" => read_labels(&list[4..], &mut info.labels),
+                _ => (),
+            }
+            data.seek(SeekFrom::Current(padding as i64))?;
+        } else if &chunk_header[0..4] == b"smpl" && len >= 36 {
+            let sampler = read_chunk(&mut data, len)?;
+            read_sampler_loop(&sampler, &mut info.metadata);
+            data.seek(SeekFrom::Current(padding as i64))?;
+        } else if &chunk_header[0..4] == b"cue " && len >= 4 {
+            let cue = read_chunk(&mut data, len)?;
+            read_cue_points(&cue, &mut info.cue_points);
+            data.seek(SeekFrom::Current(padding as i64))?;
+        } else if &chunk_header[0..4] == b"fmt " && len >= 24 {
+            let format = read_chunk(&mut data, len)?;
+            // The mask follows the extension size and the valid bits per sample.
+            let format_tag = u16::from_le_bytes([format[0], format[1]]);
+            if format_tag == 0xfffe {
+                info.channel_mask =
+                    u32::from_le_bytes([format[20], format[21], format[22], format[23]]);
+            }
+            data.seek(SeekFrom::Current(padding as i64))?;
+        } else {
+            data.seek(SeekFrom::Current((len + padding) as i64))?;
+        }
     }
+}
 
-    data.seek(SeekFrom::Start(stream_pos)).unwrap();
-    true
+/// Reads the content of a chunk of `len` bytes.
+///
+/// The buffer grows as the bytes are read, since the length comes from the file and may be far
+/// larger than the file itself.
+fn read_chunk<R>(data: &mut R, len: usize) -> io::Result<Vec<u8>>
+where
+    R: Read,
+{
+    let mut chunk = Vec::new();
+    data.take(len as u64).read_to_end(&mut chunk)?;
+    if chunk.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(chunk)
 }
 
-/// Returns a 32 bit WAV float as an i16. WAV floats are typically in the range of
-/// [-1.0, 1.0] while i16s are in the range [-32768, 32767]. Note that this
-/// function definitely causes precision loss but hopefully this isn't too
-/// audiable when actually playing?
-fn f32_to_i16(f: f32) -> i16 {
-    // prefer to clip the input rather than be excessively loud.
-    (f.max(-1.0).min(1.0) * i16::max_value() as f32) as i16
+/// Reads the identifier and position of the points of a `cue ` chunk.
+fn read_cue_points(cue: &[u8], cue_points: &mut Vec<(u32, u64)>) {
+    // Each point takes 24 bytes after the count: an identifier, a position in the playlist, the
+    // chunk holding the sample, the offsets of that chunk and of its block, and the position of
+    // the sample within the data.
+    let count = u32::from_le_bytes([cue[0], cue[1], cue[2], cue[3]]) as usize;
+    for point in cue[4..].chunks_exact(24).take(count) {
+        let id = u32::from_le_bytes([point[0], point[1], point[2], point[3]]);
+        let position = u32::from_le_bytes([point[20], point[21], point[22], point[23]]);
+        cue_points.push((id, position as u64));
+    }
 }
 
-/// Returns a 24 bit WAV int as an i16. Note that this is a 24 bit integer, not a
-/// 32 bit one. 24 bit ints are in the range [−8,388,608, 8,388,607] while i16s
-/// are in the range [-32768, 32767]. Note that this function definitely causes
-/// precision loss but hopefully this isn't too audiable when actually playing?
-fn i24_to_i16(i: i32) -> i16 {
-    (i >> 8) as i16
+/// Reads the `labl` chunks of a `LIST` chunk of type `adtl`, which name the cue points.
+fn read_labels(mut list: &[u8], labels: &mut Vec<(u32, String)>) {
+    while list.len() >= 8 {
+        let len = u32::from_le_bytes([list[4], list[5], list[6], list[7]]) as usize;
+        let content = match list.get(8..8 + len) {
+            Some(content) => content,
+            None => return,
+        };
+        if &list[0..4] == b"labl" && len >= 4 {
+            let id = u32::from_le_bytes([content[0], content[1], content[2], content[3]]);
+            let text = String::from_utf8_lossy(&content[4..]);
+            labels.push((id, text.trim_end_matches('\0').to_owned()));
+        }
+
+        list = list.get(8 + len + len % 2..).unwrap_or(&[]);
+    }
 }
 
-/// Returns an 8-bit WAV int as an i16. This scales the sample value by a factor
-/// of 256.
-fn i8_to_i16(i: i8) -> i16 {
-    i as i16 * 256
+/// Translates the first loop of a `smpl` chunk to the `LOOPSTART` and `LOOPLENGTH` tags.
+fn read_sampler_loop(sampler: &[u8], metadata: &mut Metadata) {
+    let read_u32 = |offset: usize| {
+        sampler
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    // Each loop takes 24 bytes after the 36 bytes of the header: an identifier, a type, the
+    // positions of its first and last samples, a fraction and a play count.
+    if read_u32(28).unwrap_or(0) == 0 {
+        return;
+    }
+    if let (Some(start), Some(end)) = (read_u32(36 + 8), read_u32(36 + 12)) {
+        if end >= start {
+            metadata.push_tag("LOOPSTART", &start.to_string());
+            metadata.push_tag("LOOPLENGTH", &(end - start + 1).to_string());
+        }
+    }
+}
+
+fn read_info_list(mut list: &[u8], metadata: &mut Metadata) {
+    while list.len() >= 8 {
+        let len = u32::from_le_bytes([list[4], list[5], list[6], list[7]]) as usize;
+        let value = match list.get(8..8 + len) {
+            Some(value) => String::from_utf8_lossy(value),
+            None => return,
+        };
+        let id = String::from_utf8_lossy(&list[0..4]);
+        let name = match &*id {
+            "INAM" => "TITLE",
+            "IART" => "ARTIST",
+            "IPRD" => "ALBUM",
+            "ITRK" | "IPRT" => "TRACKNUMBER",
+            "ICRD" => "DATE",
+            "IGNR" => "GENRE",
+            "ICMT" => "COMMENT",
+            "ICOP" => "COPYRIGHT",
+            "ISFT" => "ENCODER",
+            other => other,
+        };
+        metadata.push_tag(name, &value);
+
+        list = list.get(8 + len + len % 2..).unwrap_or(&[]);
+    }
 }
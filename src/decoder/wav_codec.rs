@@ -0,0 +1,552 @@
+//! Decoding of the compressed encodings of WAV files that hound does not support: G.711 μ-law
+//! and A-law, IMA ADPCM and Microsoft ADPCM.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::iter;
+
+use super::DecoderError;
+use crate::conversions::sample::int_to_f32;
+
+/// Number of bytes read from the data at once, rounded down to whole blocks.
+const BUFFER_LEN: usize = 16 * 1024;
+
+const WAVE_FORMAT_ADPCM: u16 = 0x0002;
+const WAVE_FORMAT_ALAW: u16 = 0x0006;
+const WAVE_FORMAT_MULAW: u16 = 0x0007;
+const WAVE_FORMAT_IMA_ADPCM: u16 = 0x0011;
+
+/// Coefficients of the Microsoft ADPCM predictors, used when the `fmt ` chunk omits them.
+const MS_ADPCM_COEFFICIENTS: [(i32, i32); 7] = [
+    (256, 0),
+    (512, -256),
+    (0, 0),
+    (192, 64),
+    (240, 0),
+    (460, -208),
+    (392, -232),
+];
+
+const MS_ADPCM_ADAPTATION: [i32; 16] = [
+    230, 230, 230, 230, 307, 409, 512, 614, 768, 614, 512, 409, 307, 230, 230, 230,
+];
+
+const IMA_ADPCM_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+const IMA_ADPCM_INDEX_CHANGES: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+/// An encoding of the samples of a WAV file that hound does not support.
+#[derive(Clone, Debug, PartialEq)]
+enum Codec {
+    ALaw,
+    MuLaw,
+    ImaAdpcm,
+    MsAdpcm(Vec<(i32, i32)>),
+}
+
+impl Codec {
+    /// Returns the number of inter-channel samples of a block of `len` bytes, which may be the
+    /// truncated last block.
+    fn frames_in_block(&self, len: usize, channels: usize) -> usize {
+        match self {
+            Codec::ALaw | Codec::MuLaw => len / channels,
+            Codec::ImaAdpcm => match len.checked_sub(4 * channels) {
+                Some(data_len) => 1 + data_len / (4 * channels) * 8,
+                None => 0,
+            },
+            Codec::MsAdpcm(_) => match len.checked_sub(7 * channels) {
+                Some(data_len) => 2 + data_len * 2 / channels,
+                None => 0,
+            },
+        }
+    }
+
+    /// Decodes a block and appends its samples to `output`.
+    fn decode_block(
+        &self,
+        block: &[u8],
+        channels: usize,
+        output: &mut Vec<i16>,
+    ) -> Result<(), DecoderError> {
+        match self {
+            Codec::ALaw => output.extend(block.iter().map(|&byte| alaw_to_i16(byte))),
+            Codec::MuLaw => output.extend(block.iter().map(|&byte| mulaw_to_i16(byte))),
+            Codec::ImaAdpcm => decode_ima_adpcm(block, channels, output),
+            Codec::MsAdpcm(coefficients) => decode_ms_adpcm(block, channels, coefficients, output)?,
+        }
+        Ok(())
+    }
+}
+
+/// Description of the samples of a WAV file with a compressed encoding.
+pub struct Header {
+    codec: Codec,
+    pub channels: u16,
+    pub sample_rate: u32,
+    block_align: usize,
+    frames_per_block: usize,
+    data_len: u64,
+    frames: u64,
+}
+
+/// Reads the chunks of a WAV file up to its `data` chunk.
+///
+/// If its samples use one of the encodings of this module, returns their description and leaves
+/// the data positioned at the start of the samples. Otherwise resets the data to where it was and
+/// returns `None`.
+pub fn read_header<R>(mut data: R) -> Result<Option<Header>, DecoderError>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position()?;
+    let header = read_chunks(data.by_ref());
+    if !matches!(header, Ok(Some(_))) {
+        data.seek(SeekFrom::Start(stream_pos))?;
+    }
+    header
+}
+
+fn read_chunks<R>(mut data: R) -> Result<Option<Header>, DecoderError>
+where
+    R: Read + Seek,
+{
+    let mut riff = [0; 12];
+    data.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Ok(None);
+    }
+
+    let mut format = None;
+    let mut fact_frames = None;
+    let mut chunk_header = [0; 8];
+    loop {
+        // Files that end without a `data` chunk are left to hound, which reports the error.
+        if data.read_exact(&mut chunk_header).is_err() {
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as u64;
+
+        match &chunk_header[0..4] {
+            b"fmt " => {
+                let mut chunk = vec![0; len.min(1024) as usize];
+                data.read_exact(&mut chunk)?;
+                format = Some(chunk);
+                data.seek(SeekFrom::Current((len - len.min(1024) + len % 2) as i64))?;
+            }
+            b"fact" if len >= 4 => {
+                let mut frames = [0; 4];
+                data.read_exact(&mut frames)?;
+                fact_frames = Some(u32::from_le_bytes(frames) as u64);
+                data.seek(SeekFrom::Current((len - 4 + len % 2) as i64))?;
+            }
+            b"data" => {
+                return match format {
+                    Some(format) => parse_format(&format, len, fact_frames),
+                    None => Ok(None),
+                };
+            }
+            _ => {
+                data.seek(SeekFrom::Current((len + len % 2) as i64))?;
+            }
+        }
+    }
+}
+
+/// Parses the `fmt ` chunk, given the length of the `data` chunk and the number of inter-channel
+/// samples of the `fact` chunk.
+fn parse_format(
+    format: &[u8],
+    data_len: u64,
+    fact_frames: Option<u64>,
+) -> Result<Option<Header>, DecoderError> {
+    let read_u16 = |offset: usize| {
+        format
+            .get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+    let invalid = || DecoderError::DecodeError("invalid WAV fmt chunk".to_owned());
+
+    let format_tag = read_u16(0).ok_or_else(invalid)?;
+    let codec = match format_tag {
+        WAVE_FORMAT_ALAW => Codec::ALaw,
+        WAVE_FORMAT_MULAW => Codec::MuLaw,
+        WAVE_FORMAT_IMA_ADPCM => Codec::ImaAdpcm,
+        WAVE_FORMAT_ADPCM => {
+            let count = read_u16(20).unwrap_or(0) as usize;
+            let coefficients: Vec<_> = (0..count)
+                .map_while(|i| {
+                    let c1 = read_u16(22 + i * 4)? as i16 as i32;
+                    let c2 = read_u16(24 + i * 4)? as i16 as i32;
+                    Some((c1, c2))
+                })
+                .collect();
+            if coefficients.len() == count && count > 0 {
+                Codec::MsAdpcm(coefficients)
+            } else {
+                Codec::MsAdpcm(MS_ADPCM_COEFFICIENTS.to_vec())
+            }
+        }
+        _ => return Ok(None),
+    };
+    if format.len() < 16 {
+        return Err(invalid());
+    }
+
+    let channels = read_u16(2).ok_or_else(invalid)?;
+    let sample_rate = u32::from_le_bytes([format[4], format[5], format[6], format[7]]);
+    let bits_per_sample = read_u16(14).ok_or_else(invalid)?;
+    let block_align = read_u16(12).ok_or_else(invalid)? as usize;
+    if channels == 0 || sample_rate == 0 {
+        return Err(invalid());
+    }
+    match (&codec, bits_per_sample) {
+        (Codec::ALaw, 8) | (Codec::MuLaw, 8) | (Codec::ImaAdpcm, 4) | (Codec::MsAdpcm(_), 4) => (),
+        (_, bits_per_sample) => {
+            return Err(DecoderError::UnsupportedEncoding(format!(
+                "{} bits WAV samples with format tag {:#06x}",
+                bits_per_sample, format_tag
+            )))
+        }
+    }
+
+    // G.711 samples take one byte each, so several frames are decoded at once.
+    let block_align = match codec {
+        Codec::ALaw | Codec::MuLaw => channels as usize,
+        _ => block_align,
+    };
+    let frames_per_block = codec.frames_in_block(block_align, channels as usize);
+    if frames_per_block == 0 {
+        return Err(invalid());
+    }
+
+    let full_blocks = data_len / block_align as u64;
+    let last_block = (data_len % block_align as u64) as usize;
+    let frames = full_blocks * frames_per_block as u64
+        + codec.frames_in_block(last_block, channels as usize) as u64;
+    // The last block is padded, so the `fact` chunk tells the actual length.
+    let frames = fact_frames.map_or(frames, |fact_frames| fact_frames.min(frames));
+
+    Ok(Some(Header {
+        codec,
+        channels,
+        sample_rate,
+        block_align,
+        frames_per_block,
+        data_len,
+        frames,
+    }))
+}
+
+/// Iterator over the samples of a WAV file with a compressed encoding.
+pub struct CompressedSamples<R>
+where
+    R: Read + Seek,
+{
+    data: R,
+    header: Header,
+    // Position of the first byte of the samples.
+    data_start: u64,
+    // Number of bytes of the samples read so far.
+    data_read: u64,
+    samples_read: u64,
+    bytes: Vec<u8>,
+    buffer: Vec<i16>,
+    buffer_pos: usize,
+    pub error: Option<DecoderError>,
+}
+
+impl<R> CompressedSamples<R>
+where
+    R: Read + Seek,
+{
+    /// Decodes the samples that start at the current position of the data.
+    pub fn new(mut data: R, header: Header) -> Result<CompressedSamples<R>, DecoderError> {
+        let data_start = data.stream_position()?;
+        Ok(CompressedSamples {
+            data,
+            header,
+            data_start,
+            data_read: 0,
+            samples_read: 0,
+            bytes: Vec::new(),
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            error: None,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.data
+    }
+
+    /// Returns the number of inter-channel samples of the whole stream.
+    #[inline]
+    pub fn frames(&self) -> u64 {
+        self.header.frames
+    }
+
+    /// Returns the number of samples, counting every channel.
+    #[inline]
+    pub fn samples_len(&self) -> u64 {
+        self.header.frames * self.header.channels as u64
+    }
+
+    /// Moves to the given inter-channel sample. Positions past the end saturate at the end.
+    pub fn seek(&mut self, frame: u64) -> io::Result<()> {
+        let frame = frame.min(self.header.frames);
+        let block = frame / self.header.frames_per_block as u64;
+        let data_read = block * self.header.block_align as u64;
+        self.data
+            .seek(SeekFrom::Start(self.data_start + data_read))?;
+        self.data_read = data_read;
+        self.samples_read =
+            block * self.header.frames_per_block as u64 * self.header.channels as u64;
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        self.error = None;
+
+        // Skip the samples of the block before the target.
+        for _ in 0..(frame * self.header.channels as u64 - self.samples_read) {
+            if self.next().is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and decodes the next blocks into the buffer.
+    ///
+    /// If the data ends early, the samples that could be read are kept and the error is only
+    /// returned once they have all been used.
+    fn fill_buffer(&mut self) -> Result<(), DecoderError> {
+        let block_align = self.header.block_align;
+        let remaining = (self.header.data_len - self.data_read) as usize;
+        let len = remaining.min((BUFFER_LEN / block_align).max(1) * block_align);
+        self.bytes.resize(len, 0);
+
+        let mut read = 0;
+        while read < len {
+            match self.data.read(&mut self.bytes[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.data_read += read as u64;
+
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        let channels = self.header.channels as usize;
+        for block in self.bytes[..read].chunks(block_align) {
+            self.header
+                .codec
+                .decode_block(block, channels, &mut self.buffer)?;
+        }
+        if self.buffer.is_empty() {
+            return Err(DecoderError::Truncated);
+        }
+        Ok(())
+    }
+}
+
+impl<R> Iterator for CompressedSamples<R>
+where
+    R: Read + Seek,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.samples_read == self.samples_len() || self.error.is_some() {
+            return None;
+        }
+        if self.buffer_pos == self.buffer.len() {
+            if let Err(error) = self.fill_buffer() {
+                self.error = Some(error);
+                return None;
+            }
+        }
+
+        let sample = self.buffer[self.buffer_pos];
+        self.buffer_pos += 1;
+        self.samples_read += 1;
+        Some(int_to_f32(sample as i32, 16))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.samples_len() - self.samples_read) as usize;
+        (len, Some(len))
+    }
+}
+
+fn alaw_to_i16(byte: u8) -> i16 {
+    let byte = byte ^ 0x55;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = (byte & 0x0f) as i16;
+    let magnitude = match exponent {
+        0 => (mantissa << 4) + 8,
+        _ => ((mantissa << 4) + 0x108) << (exponent - 1),
+    };
+    // Unlike μ-law, the sign bit is set for positive values.
+    if byte & 0x80 != 0 {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+fn mulaw_to_i16(byte: u8) -> i16 {
+    let byte = !byte;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = (byte & 0x0f) as i16;
+    let magnitude = (((mantissa << 3) + 0x84) << exponent) - 0x84;
+    if byte & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Decodes an IMA ADPCM block, which starts with the first sample and the step index of each
+/// channel, followed by groups of 8 samples of each channel in turn.
+fn decode_ima_adpcm(block: &[u8], channels: usize, output: &mut Vec<i16>) {
+    let frames = Codec::ImaAdpcm.frames_in_block(block.len(), channels);
+    if frames == 0 {
+        return;
+    }
+    let start = output.len();
+    output.resize(start + frames * channels, 0);
+    let output = &mut output[start..];
+
+    let mut states: Vec<(i32, i32)> = block[..4 * channels]
+        .chunks(4)
+        .map(|header| {
+            let sample = i16::from_le_bytes([header[0], header[1]]) as i32;
+            (sample, (header[2] as i32).min(88))
+        })
+        .collect();
+    for (channel, &(sample, _)) in states.iter().enumerate() {
+        output[channel] = sample as i16;
+    }
+
+    let groups = (frames - 1) / 8;
+    let words = block[4 * channels..].chunks(4).take(groups * channels);
+    for (i, word) in words.enumerate() {
+        let channel = i % channels;
+        let first_frame = 1 + i / channels * 8;
+        let (sample, index) = &mut states[channel];
+        let nibbles = word
+            .iter()
+            .flat_map(|byte| iter::once(byte & 0x0f).chain(iter::once(byte >> 4)));
+        for (frame, nibble) in (first_frame..).zip(nibbles) {
+            let step = IMA_ADPCM_STEPS[*index as usize];
+            let mut diff = step >> 3;
+            if nibble & 1 != 0 {
+                diff += step >> 2;
+            }
+            if nibble & 2 != 0 {
+                diff += step >> 1;
+            }
+            if nibble & 4 != 0 {
+                diff += step;
+            }
+            if nibble & 8 != 0 {
+                diff = -diff;
+            }
+            *sample = (*sample + diff).clamp(i16::MIN as i32, i16::MAX as i32);
+            *index = (*index + IMA_ADPCM_INDEX_CHANGES[(nibble & 7) as usize]).clamp(0, 88);
+            output[frame * channels + channel] = *sample as i16;
+        }
+    }
+}
+
+/// Decodes a Microsoft ADPCM block, which starts with the predictor, the step and the first two
+/// samples of each channel, followed by interleaved samples of 4 bits.
+fn decode_ms_adpcm(
+    block: &[u8],
+    channels: usize,
+    coefficients: &[(i32, i32)],
+    output: &mut Vec<i16>,
+) -> Result<(), DecoderError> {
+    let frames = Codec::MsAdpcm(Vec::new()).frames_in_block(block.len(), channels);
+    if frames == 0 {
+        return Ok(());
+    }
+
+    let read_i16 = |offset: usize| i16::from_le_bytes([block[offset], block[offset + 1]]) as i32;
+    let mut states = Vec::with_capacity(channels);
+    for (channel, &predictor) in block[..channels].iter().enumerate() {
+        let coefficients = *coefficients
+            .get(predictor as usize)
+            .ok_or_else(|| DecoderError::DecodeError("invalid MS ADPCM predictor".to_owned()))?;
+        let delta = read_i16(channels + channel * 2);
+        let sample1 = read_i16(3 * channels + channel * 2);
+        let sample2 = read_i16(5 * channels + channel * 2);
+        states.push((coefficients, delta, sample1, sample2));
+    }
+
+    // The older sample comes first.
+    output.extend(states.iter().map(|&(_, _, _, sample2)| sample2 as i16));
+    output.extend(states.iter().map(|&(_, _, sample1, _)| sample1 as i16));
+
+    let nibbles = block[7 * channels..]
+        .iter()
+        .flat_map(|byte| iter::once(byte >> 4).chain(iter::once(byte & 0x0f)));
+    for (i, nibble) in nibbles.take((frames - 2) * channels).enumerate() {
+        let ((c1, c2), delta, sample1, sample2) = &mut states[i % channels];
+        // The coefficients come from the file and may be as large as the samples.
+        let predicted = (*sample1 as i64 * *c1 as i64 + *sample2 as i64 * *c2 as i64) >> 8;
+        // The nibble is a signed 4 bits number.
+        let signed = ((nibble as i64) << 60) >> 60;
+        let sample = (predicted + signed * *delta as i64).clamp(i16::MIN as i64, i16::MAX as i64);
+        *sample2 = *sample1;
+        *sample1 = sample as i32;
+        // The step keeps growing on loud sounds, so it is bounded like other decoders do.
+        *delta = ((MS_ADPCM_ADAPTATION[nibble as usize] * *delta) >> 8).clamp(16, i32::MAX / 768);
+        output.push(sample as i16);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alaw_to_i16, decode_ms_adpcm, mulaw_to_i16};
+
+    #[test]
+    fn g711() {
+        assert_eq!(mulaw_to_i16(0xff), 0);
+        assert_eq!(mulaw_to_i16(0x80), 32124);
+        assert_eq!(mulaw_to_i16(0x00), -32124);
+        assert_eq!(alaw_to_i16(0xd5), 8);
+        assert_eq!(alaw_to_i16(0x55), -8);
+        assert_eq!(alaw_to_i16(0xaa), 32256);
+        assert_eq!(alaw_to_i16(0x2a), -32256);
+    }
+
+    #[test]
+    fn ms_adpcm_hostile_header() {
+        // The largest coefficients and samples, and a step that triples on every nibble.
+        let mut block = vec![0];
+        block.extend_from_slice(&i16::MAX.to_le_bytes());
+        block.extend_from_slice(&i16::MIN.to_le_bytes());
+        block.extend_from_slice(&i16::MIN.to_le_bytes());
+        block.extend(vec![0x88; 64]);
+        let coefficients = [(i16::MIN as i32, i16::MIN as i32)];
+
+        let mut output = Vec::new();
+        decode_ms_adpcm(&block, 1, &coefficients, &mut output).unwrap();
+        assert_eq!(output.len(), 2 + 128);
+    }
+}
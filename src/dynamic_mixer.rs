@@ -1,11 +1,11 @@
 //! Mixer that plays multiple sounds at the same time.
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::source::{Source, UniformSourceIterator};
-use crate::Sample;
+use crate::{ResampleQuality, Sample};
 
 /// Builds a new mixer.
 ///
@@ -23,6 +23,7 @@ where
     let input = Arc::new(DynamicMixerController {
         has_pending: AtomicBool::new(false),
         pending_sources: Mutex::new(Vec::new()),
+        resample_quality: AtomicU8::new(ResampleQuality::Linear.to_u8()),
         channels,
         sample_rate,
     });
@@ -39,6 +40,7 @@ where
 pub struct DynamicMixerController<S> {
     has_pending: AtomicBool,
     pending_sources: Mutex<Vec<Box<dyn Source<Item = S> + Send>>>,
+    resample_quality: AtomicU8,
     channels: u16,
     sample_rate: u32,
 }
@@ -48,18 +50,37 @@ where
     S: Sample + Send + 'static,
 {
     /// Adds a new source to mix to the existing ones.
+    ///
+    /// Its sample rate is converted with the algorithm set by
+    /// [`set_resample_quality`](DynamicMixerController::set_resample_quality).
     #[inline]
     pub fn add<T>(&self, source: T)
     where
         T: Source<Item = S> + Send + 'static,
     {
-        let uniform_source = UniformSourceIterator::new(source, self.channels, self.sample_rate);
+        let quality = self.resample_quality();
+        let uniform_source =
+            UniformSourceIterator::with_quality(source, self.channels, self.sample_rate, quality);
         self.pending_sources
             .lock()
             .unwrap()
             .push(Box::new(uniform_source) as Box<_>);
         self.has_pending.store(true, Ordering::SeqCst); // TODO: can we relax this ordering?
     }
+
+    /// Sets the algorithm that converts the sample rate of the sources added from now on.
+    /// Defaults to `ResampleQuality::Linear`.
+    #[inline]
+    pub fn set_resample_quality(&self, quality: ResampleQuality) {
+        self.resample_quality
+            .store(quality.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Returns the algorithm that converts the sample rate of the sources added from now on.
+    #[inline]
+    pub fn resample_quality(&self) -> ResampleQuality {
+        ResampleQuality::from_u8(self.resample_quality.load(Ordering::Relaxed))
+    }
 }
 
 /// The output of the mixer. Implements `Source`.
@@ -148,6 +169,7 @@ mod tests {
     use crate::buffer::SamplesBuffer;
     use crate::dynamic_mixer;
     use crate::source::Source;
+    use crate::ResampleQuality;
 
     #[test]
     fn basic() {
@@ -204,6 +226,21 @@ mod tests {
         assert_eq!(rx.next(), None);
     }
 
+    #[test]
+    fn resample_quality() {
+        let (tx, _) = dynamic_mixer::mixer::<f32>(1, 48000);
+        assert_eq!(tx.resample_quality(), ResampleQuality::Linear);
+        for &quality in &[
+            ResampleQuality::Low,
+            ResampleQuality::Medium,
+            ResampleQuality::High,
+            ResampleQuality::Linear,
+        ] {
+            tx.set_resample_quality(quality);
+            assert_eq!(tx.resample_quality(), quality);
+        }
+    }
+
     #[test]
     fn start_afterwards() {
         let (tx, mut rx) = dynamic_mixer::mixer(1, 48000);
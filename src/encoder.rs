@@ -0,0 +1,75 @@
+//! Encodes the samples of a source into an audio file.
+//!
+//! This is useful to render sounds offline, for example to save the output of a chain of
+//! filters:
+//!
+//! ```
+//! use std::io::Cursor;
+//! use std::time::Duration;
+//! use rodio::encoder::{self, WavSampleFormat};
+//! use rodio::source::{SineWave, Source};
+//!
+//! let source = SineWave::new(440).take_duration(Duration::from_millis(250)).amplify(0.5);
+//! let mut file = Cursor::new(Vec::new());
+//! encoder::write_wav(source, &mut file, WavSampleFormat::Int16).unwrap();
+//! ```
+
+use std::io::{Seek, Write};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::conversions::sample::f32_to_int;
+use crate::source::UniformSourceIterator;
+use crate::{Sample, Source};
+
+/// Format of the samples of a WAV file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    /// 16 bits signed integers.
+    Int16,
+    /// 24 bits signed integers.
+    Int24,
+    /// 32 bits floating point numbers.
+    Float32,
+}
+
+/// Writes all the samples of a source to a WAV file.
+///
+/// The channels count and sample rate of the file are those of the source when this function is
+/// called. If they change later on, the samples are converted to match them.
+///
+/// This function only returns once the source is exhausted, which never happens for infinite
+/// sources. Use [`take_duration`](Source::take_duration) to limit their length.
+pub fn write_wav<S, W>(source: S, writer: W, format: WavSampleFormat) -> Result<(), hound::Error>
+where
+    S: Source,
+    S::Item: Sample,
+    W: Write + Seek,
+{
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+    let samples: UniformSourceIterator<S, f32> =
+        UniformSourceIterator::new(source, channels, sample_rate);
+
+    let (bits_per_sample, sample_format) = match format {
+        WavSampleFormat::Int16 => (16, SampleFormat::Int),
+        WavSampleFormat::Int24 => (24, SampleFormat::Int),
+        WavSampleFormat::Float32 => (32, SampleFormat::Float),
+    };
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample,
+        sample_format,
+    };
+
+    let mut writer = WavWriter::new(writer, spec)?;
+    for sample in samples {
+        match format {
+            WavSampleFormat::Int16 => writer.write_sample(f32_to_int(sample, 16) as i16)?,
+            WavSampleFormat::Int24 => writer.write_sample(f32_to_int(sample, 24))?,
+            WavSampleFormat::Float32 => writer.write_sample(sample)?,
+        }
+    }
+    writer.finalize()
+}
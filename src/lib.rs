@@ -107,13 +107,17 @@ mod spatial_sink;
 mod stream;
 
 pub mod buffer;
+pub mod channel_layout;
 pub mod decoder;
 pub mod dynamic_mixer;
+#[cfg(feature = "wav")]
+pub mod encoder;
 pub mod queue;
 pub mod source;
 pub mod static_buffer;
 
-pub use crate::conversions::Sample;
+pub use crate::channel_layout::{ChannelLayout, Speaker};
+pub use crate::conversions::{ResampleQuality, Sample};
 pub use crate::decoder::Decoder;
 pub use crate::sink::Sink;
 pub use crate::source::Source;
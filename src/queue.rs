@@ -5,7 +5,7 @@ use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
 
-use crate::source::{Empty, Source, Zero};
+use crate::source::{Empty, SeekError, Source, Zero};
 use crate::Sample;
 
 /// Builds a new queue. It consists of an input and an output.
@@ -151,6 +151,12 @@ where
     fn total_duration(&self) -> Option<Duration> {
         None
     }
+
+    /// Only seeks within the sound that is currently playing.
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.current.try_seek(pos)
+    }
 }
 
 impl<S> Iterator for SourcesQueueOutput<S>
@@ -1,10 +1,11 @@
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use crate::source::{Done, SeekError};
 use crate::stream::{OutputStreamHandle, PlayError};
-use crate::{queue, source::Done, Sample, Source};
+use crate::{queue, Sample, Source};
 
 /// Handle to an device that outputs sounds.
 ///
@@ -24,6 +25,95 @@ struct Controls {
     pause: AtomicBool,
     volume: Mutex<f32>,
     stopped: AtomicBool,
+    seek: Mutex<Option<SeekOrder>>,
+    // Number of seek orders given so far, used to tell them apart.
+    seek_count: AtomicUsize,
+}
+
+/// A pending request to seek the sound that is currently playing.
+struct SeekOrder {
+    id: usize,
+    pos: Duration,
+    feedback: Sender<Result<(), SeekError>>,
+}
+
+/// Longest time `Sink::try_seek` waits for the audio thread.
+const SEEK_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl SeekOrder {
+    fn new(id: usize, pos: Duration) -> (Self, Receiver<Result<(), SeekError>>) {
+        let (feedback, rx) = mpsc::channel();
+        (SeekOrder { id, pos, feedback }, rx)
+    }
+
+    fn attempt<S>(self, source: &mut S)
+    where
+        S: Source,
+        S::Item: Sample,
+    {
+        let result = source.try_seek(self.pos);
+        // The caller may have given up waiting, in which case nobody listens anymore.
+        let _ = self.feedback.send(result);
+    }
+}
+
+/// Drops the pending seek order when the inner sound ends, so that it does not seek the next one.
+struct DropSeekOnEnd<I> {
+    input: I,
+    controls: Arc<Controls>,
+}
+
+impl<I> Iterator for DropSeekOnEnd<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        let next = self.input.next();
+        if next.is_none() {
+            *self.controls.seek.lock().unwrap() = None;
+        }
+        next
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for DropSeekOnEnd<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
 }
 
 impl Sink {
@@ -47,6 +137,8 @@ impl Sink {
                 pause: AtomicBool::new(false),
                 volume: Mutex::new(1.0),
                 stopped: AtomicBool::new(false),
+                seek: Mutex::new(None),
+                seek_count: AtomicUsize::new(0),
             }),
             sound_count: Arc::new(AtomicUsize::new(0)),
             detached: false,
@@ -71,7 +163,11 @@ impl Sink {
             .periodic_access(Duration::from_millis(5), move |src| {
                 if controls.stopped.load(Ordering::SeqCst) {
                     src.stop();
+                    *controls.seek.lock().unwrap() = None;
                 } else {
+                    if let Some(seek) = controls.seek.lock().unwrap().take() {
+                        seek.attempt(src);
+                    }
                     src.inner_mut().set_factor(*controls.volume.lock().unwrap());
                     src.inner_mut()
                         .inner_mut()
@@ -79,6 +175,10 @@ impl Sink {
                 }
             })
             .convert_samples();
+        let source = DropSeekOnEnd {
+            input: source,
+            controls: self.controls.clone(),
+        };
         self.sound_count.fetch_add(1, Ordering::Relaxed);
         let source = Done::new(source, self.sound_count.clone());
         *self.sleep_until_end.lock().unwrap() = Some(self.queue_tx.append_with_signal(source));
@@ -127,10 +227,56 @@ impl Sink {
         self.controls.pause.load(Ordering::SeqCst)
     }
 
+    /// Attempts to seek to a given position in the sound that is currently playing.
+    ///
+    /// This blocks the current thread until the audio thread has performed the seek, which takes
+    /// up to about 5 milliseconds. If the sound is not being played at all, this gives up after a
+    /// second.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SeekError::NotSupported`] if one of the underlying sources does not support
+    /// seeking, or the error of the decoder if it failed to seek. Returns
+    /// [`SeekError::NotPlaying`] if the sink is empty or stopped, or if the sound ends before it
+    /// is seeked, [`SeekError::TimedOut`] if the sink is not being played, and
+    /// [`SeekError::Superseded`] if another seek is requested before this one is performed.
+    pub fn try_seek(&self, pos: Duration) -> Result<(), SeekError> {
+        if self.sound_count.load(Ordering::Relaxed) == 0
+            || self.controls.stopped.load(Ordering::SeqCst)
+        {
+            return Err(SeekError::NotPlaying);
+        }
+
+        let id = self.controls.seek_count.fetch_add(1, Ordering::Relaxed);
+        let (order, feedback) = SeekOrder::new(id, pos);
+        if let Some(previous) = self.controls.seek.lock().unwrap().replace(order) {
+            let _ = previous.feedback.send(Err(SeekError::Superseded));
+        }
+
+        match feedback.recv_timeout(SEEK_TIMEOUT) {
+            Ok(result) => result,
+            // The order was dropped along with the sound, or because the sink was stopped.
+            Err(RecvTimeoutError::Disconnected) => Err(SeekError::NotPlaying),
+            Err(RecvTimeoutError::Timeout) => {
+                // Nobody plays the sound, so the order is withdrawn.
+                let mut seek = self.controls.seek.lock().unwrap();
+                if seek.as_ref().map(|order| order.id) == Some(id) {
+                    *seek = None;
+                    return Err(SeekError::TimedOut);
+                }
+                drop(seek);
+                // The order has just been taken by the audio thread or replaced by another one,
+                // and its outcome follows.
+                feedback.recv().unwrap_or(Err(SeekError::NotPlaying))
+            }
+        }
+    }
+
     /// Stops the sink by emptying the queue.
     #[inline]
     pub fn stop(&self) {
         self.controls.stopped.store(true, Ordering::SeqCst);
+        *self.controls.seek.lock().unwrap() = None;
     }
 
     /// Destroys the sink without stopping the sounds that are still playing.
@@ -168,12 +314,19 @@ impl Drop for Sink {
         if !self.detached {
             self.controls.stopped.store(true, Ordering::Relaxed);
         }
+        *self.controls.seek.lock().unwrap() = None;
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
     use crate::buffer::SamplesBuffer;
+    use crate::source::SeekError;
     use crate::{Sink, Source};
 
     #[test]
@@ -204,7 +357,7 @@ mod tests {
 
         assert_eq!(queue_rx.next(), Some(0.0));
 
-        assert_eq!(sink.empty(), true);
+        assert!(sink.empty());
     }
 
     #[test]
@@ -224,4 +377,105 @@ mod tests {
             assert_eq!(queue_rx.next(), src.next());
         }
     }
+
+    /// Mono source whose samples are their own position, at 1000 samples per second.
+    struct Ramp(u64);
+
+    impl Iterator for Ramp {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            self.0 += 1;
+            Some((self.0 - 1) as f32)
+        }
+    }
+
+    impl Source for Ramp {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            1000
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+
+        fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+            self.0 = pos.as_millis() as u64;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_try_seek() {
+        let (sink, mut queue_rx) = Sink::new_idle();
+        assert!(matches!(
+            sink.try_seek(Duration::from_secs(1)),
+            Err(SeekError::NotPlaying)
+        ));
+
+        sink.append(Ramp(0));
+        let sink = Arc::new(sink);
+
+        // Nobody plays the sound, so the seeks give up and the last order is withdrawn. The first
+        // one is replaced by the second one.
+        let first = {
+            let sink = sink.clone();
+            thread::spawn(move || sink.try_seek(Duration::from_secs(1)))
+        };
+        while sink.controls.seek.lock().unwrap().is_none() {
+            thread::yield_now();
+        }
+        assert!(matches!(
+            sink.try_seek(Duration::from_secs(2)),
+            Err(SeekError::TimedOut)
+        ));
+        assert!(matches!(first.join().unwrap(), Err(SeekError::Superseded)));
+        assert!(sink.controls.seek.lock().unwrap().is_none());
+
+        // Records the samples that do not follow the previous one.
+        let polling = Arc::new(AtomicBool::new(true));
+        let jumps = Arc::new(Mutex::new(Vec::new()));
+        let poller = {
+            let polling = polling.clone();
+            let jumps = jumps.clone();
+            thread::spawn(move || {
+                let mut previous = -1.0;
+                while polling.load(Ordering::SeqCst) {
+                    let sample = queue_rx.next().unwrap();
+                    if sample != previous + 1.0 {
+                        jumps.lock().unwrap().push(sample);
+                    }
+                    previous = sample;
+                    thread::sleep(Duration::from_micros(10));
+                }
+            })
+        };
+
+        sink.try_seek(Duration::from_secs(30)).unwrap();
+        assert!(sink.controls.seek.lock().unwrap().is_none());
+        while jumps.lock().unwrap().is_empty() {
+            thread::yield_now();
+        }
+        assert_eq!(*jumps.lock().unwrap(), [30000.0]);
+
+        sink.stop();
+        let start = Instant::now();
+        assert!(matches!(
+            sink.try_seek(Duration::from_secs(10)),
+            Err(SeekError::NotPlaying)
+        ));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(sink.controls.seek.lock().unwrap().is_none());
+
+        polling.store(false, Ordering::SeqCst);
+        poller.join().unwrap();
+    }
 }
@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// Internal function that builds a `Amplify` object.
@@ -93,4 +94,9 @@ where
     fn total_duration(&self) -> Option<Duration> {
         self.input.total_duration()
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
 }
@@ -1,42 +1,164 @@
 use std::f32::consts::PI;
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::Source;
 
 // Implemented following http://www.musicdsp.org/files/Audio-EQ-Cookbook.txt
 
+/// Q of the low-pass and high-pass filters when it is not given.
+const DEFAULT_Q: f32 = 0.5;
+
 /// Internal function that builds a `BltFilter` object.
-pub fn low_pass<I>(input: I, freq: u32) -> BltFilter<I>
+pub fn blt_filter<I>(input: I, formula: BltFormula) -> BltFilter<I>
 where
     I: Source<Item = f32>,
 {
+    formula.check();
     BltFilter {
         input,
-        formula: BltFormula::LowPass { freq, q: 0.5 },
+        formula,
         applier: None,
-        x_n1: 0.0,
-        x_n2: 0.0,
-        y_n1: 0.0,
-        y_n2: 0.0,
+        states: Vec::new(),
+        channel: 0,
     }
 }
 
+/// Internal function that builds a low-pass `BltFilter` object.
+#[inline]
+pub fn low_pass<I>(input: I, freq: u32) -> BltFilter<I>
+where
+    I: Source<Item = f32>,
+{
+    blt_filter(
+        input,
+        BltFormula::LowPass {
+            freq: freq as f32,
+            q: DEFAULT_Q,
+        },
+    )
+}
+
+/// Internal function that builds a high-pass `BltFilter` object.
+#[inline]
+pub fn high_pass<I>(input: I, freq: u32) -> BltFilter<I>
+where
+    I: Source<Item = f32>,
+{
+    blt_filter(
+        input,
+        BltFormula::HighPass {
+            freq: freq as f32,
+            q: DEFAULT_Q,
+        },
+    )
+}
+
+/// A biquad filter, whose response is one of those of the Audio EQ Cookbook.
+///
+/// Each channel is filtered separately. Building or changing a filter with a Q that is not
+/// positive panics.
 #[derive(Clone, Debug)]
 pub struct BltFilter<I> {
     input: I,
     formula: BltFormula,
     applier: Option<BltApplier>,
-    x_n1: f32,
-    x_n2: f32,
-    y_n1: f32,
-    y_n2: f32,
+    // State of the filter of each channel.
+    states: Vec<BltState>,
+    // Channel of the next sample.
+    channel: usize,
 }
 
 impl<I> BltFilter<I> {
     /// Modifies this filter so that it becomes a low-pass filter.
+    #[inline]
     pub fn to_low_pass(&mut self, freq: u32) {
-        self.formula = BltFormula::LowPass { freq, q: 0.5 };
-        self.applier = None;
+        self.to_low_pass_with_q(freq, DEFAULT_Q);
+    }
+
+    /// Modifies this filter so that it becomes a low-pass filter with the given Q.
+    #[inline]
+    pub fn to_low_pass_with_q(&mut self, freq: u32, q: f32) {
+        self.set_formula(BltFormula::LowPass {
+            freq: freq as f32,
+            q,
+        });
+    }
+
+    /// Modifies this filter so that it becomes a high-pass filter.
+    #[inline]
+    pub fn to_high_pass(&mut self, freq: u32) {
+        self.to_high_pass_with_q(freq, DEFAULT_Q);
+    }
+
+    /// Modifies this filter so that it becomes a high-pass filter with the given Q.
+    #[inline]
+    pub fn to_high_pass_with_q(&mut self, freq: u32, q: f32) {
+        self.set_formula(BltFormula::HighPass {
+            freq: freq as f32,
+            q,
+        });
+    }
+
+    /// Modifies this filter so that it becomes a band-pass filter, with a gain of 0 dB at its
+    /// center frequency.
+    #[inline]
+    pub fn to_band_pass(&mut self, freq: u32, q: f32) {
+        self.set_formula(BltFormula::BandPass {
+            freq: freq as f32,
+            q,
+        });
+    }
+
+    /// Modifies this filter so that it becomes a notch filter.
+    #[inline]
+    pub fn to_notch(&mut self, freq: u32, q: f32) {
+        self.set_formula(BltFormula::Notch {
+            freq: freq as f32,
+            q,
+        });
+    }
+
+    /// Modifies this filter so that it becomes an all-pass filter.
+    #[inline]
+    pub fn to_all_pass(&mut self, freq: u32, q: f32) {
+        self.set_formula(BltFormula::AllPass {
+            freq: freq as f32,
+            q,
+        });
+    }
+
+    /// Modifies this filter so that it becomes a peaking filter, which changes the gain of the
+    /// frequencies around `freq` by `gain_db` decibels.
+    #[inline]
+    pub fn to_peaking(&mut self, freq: u32, q: f32, gain_db: f32) {
+        self.set_formula(BltFormula::Peaking {
+            freq: freq as f32,
+            q,
+            gain_db,
+        });
+    }
+
+    /// Modifies this filter so that it becomes a low-shelf filter, which changes the gain of the
+    /// frequencies below `freq` by `gain_db` decibels.
+    #[inline]
+    pub fn to_low_shelf(&mut self, freq: u32, q: f32, gain_db: f32) {
+        self.set_formula(BltFormula::LowShelf {
+            freq: freq as f32,
+            q,
+            gain_db,
+        });
+    }
+
+    /// Modifies this filter so that it becomes a high-shelf filter, which changes the gain of
+    /// the frequencies above `freq` by `gain_db` decibels.
+    #[inline]
+    pub fn to_high_shelf(&mut self, freq: u32, q: f32, gain_db: f32) {
+        self.set_formula(BltFormula::HighShelf {
+            freq: freq as f32,
+            q,
+            gain_db,
+        });
     }
 
     /// Returns a reference to the inner source.
@@ -56,6 +178,13 @@ impl<I> BltFilter<I> {
     pub fn into_inner(self) -> I {
         self.input
     }
+
+    #[inline]
+    fn set_formula(&mut self, formula: BltFormula) {
+        formula.check();
+        self.formula = formula;
+        self.applier = None;
+    }
 }
 
 impl<I> Iterator for BltFilter<I>
@@ -71,25 +200,27 @@ where
         if self.applier.is_none() {
             self.applier = Some(self.formula.to_applier(self.input.sample_rate()));
         }
+        // The channels count can only change at the start of a frame.
+        let channels = self.input.channels() as usize;
+        if self.states.len() != channels {
+            self.states.resize(channels, BltState::default());
+        }
 
         let sample = match self.input.next() {
             None => return None,
             Some(s) => s,
         };
 
-        let result = self
-            .applier
-            .as_ref()
-            .unwrap()
-            .apply(sample, self.x_n1, self.x_n2, self.y_n1, self.y_n2);
-
-        self.y_n2 = self.y_n1;
-        self.x_n2 = self.x_n1;
-        self.y_n1 = result;
-        self.x_n1 = sample;
+        let state = &mut self.states[self.channel];
+        let result = self.applier.as_ref().unwrap().apply(sample, state);
 
+        self.channel += 1;
+        if self.channel == channels {
+            self.channel = 0;
+        }
         if last_in_frame {
             self.applier = None;
+            self.channel = 0;
         }
 
         Some(result)
@@ -126,41 +257,142 @@ where
     fn total_duration(&self) -> Option<Duration> {
         self.input.total_duration()
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        // Seeking lands on the first channel.
+        self.channel = 0;
+        Ok(())
+    }
 }
 
+/// The response of a `BltFilter`. Frequencies are in Hz.
 #[derive(Clone, Debug)]
-enum BltFormula {
-    LowPass { freq: u32, q: f32 },
+pub enum BltFormula {
+    LowPass { freq: f32, q: f32 },
+    HighPass { freq: f32, q: f32 },
+    BandPass { freq: f32, q: f32 },
+    Notch { freq: f32, q: f32 },
+    AllPass { freq: f32, q: f32 },
+    Peaking { freq: f32, q: f32, gain_db: f32 },
+    LowShelf { freq: f32, q: f32, gain_db: f32 },
+    HighShelf { freq: f32, q: f32, gain_db: f32 },
 }
 
 impl BltFormula {
-    fn to_applier(&self, sampling_frequency: u32) -> BltApplier {
+    /// Returns the frequency, the Q and the gain in decibels.
+    #[inline]
+    fn parameters(&self) -> (f32, f32, f32) {
         match *self {
-            BltFormula::LowPass { freq, q } => {
-                let w0 = 2.0 * PI * freq as f32 / sampling_frequency as f32;
-
-                let alpha = w0.sin() / (2.0 * q);
-                let b1 = 1.0 - w0.cos();
-                let b0 = b1 / 2.0;
-                let b2 = b0;
-                let a0 = 1.0 + alpha;
-                let a1 = -2.0 * w0.cos();
-                let a2 = 1.0 - alpha;
-
-                BltApplier {
-                    b0: b0 / a0,
-                    b1: b1 / a0,
-                    b2: b2 / a0,
-                    a1: a1 / a0,
-                    a2: a2 / a0,
-                }
+            BltFormula::LowPass { freq, q }
+            | BltFormula::HighPass { freq, q }
+            | BltFormula::BandPass { freq, q }
+            | BltFormula::Notch { freq, q }
+            | BltFormula::AllPass { freq, q } => (freq, q, 0.0),
+            BltFormula::Peaking { freq, q, gain_db }
+            | BltFormula::LowShelf { freq, q, gain_db }
+            | BltFormula::HighShelf { freq, q, gain_db } => (freq, q, gain_db),
+        }
+    }
+
+    /// Panics if the Q is not positive, which makes the filter unstable.
+    #[inline]
+    fn check(&self) {
+        let (_, q, _) = self.parameters();
+        assert!(q > 0.0, "the Q of a filter must be positive, got {}", q);
+    }
+
+    pub fn to_applier(&self, sampling_frequency: u32) -> BltApplier {
+        let (freq, q, gain_db) = self.parameters();
+
+        let w0 = 2.0 * PI * freq / sampling_frequency as f32;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let a = 10f32.powf(gain_db / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match *self {
+            BltFormula::LowPass { .. } => {
+                let b1 = 1.0 - cos_w0;
+                (
+                    b1 / 2.0,
+                    b1,
+                    b1 / 2.0,
+                    1.0 + alpha,
+                    -2.0 * cos_w0,
+                    1.0 - alpha,
+                )
+            }
+            BltFormula::HighPass { .. } => {
+                let b1 = -(1.0 + cos_w0);
+                (
+                    -b1 / 2.0,
+                    b1,
+                    -b1 / 2.0,
+                    1.0 + alpha,
+                    -2.0 * cos_w0,
+                    1.0 - alpha,
+                )
+            }
+            BltFormula::BandPass { .. } => {
+                (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
             }
+            BltFormula::Notch { .. } => (
+                1.0,
+                -2.0 * cos_w0,
+                1.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            BltFormula::AllPass { .. } => (
+                1.0 - alpha,
+                -2.0 * cos_w0,
+                1.0 + alpha,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            BltFormula::Peaking { .. } => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            BltFormula::LowShelf { .. } => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - shelf),
+                (a + 1.0) + (a - 1.0) * cos_w0 + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - shelf,
+            ),
+            BltFormula::HighShelf { .. } => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - shelf),
+                (a + 1.0) - (a - 1.0) * cos_w0 + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - shelf,
+            ),
+        };
+
+        BltApplier {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
         }
     }
 }
 
+/// The coefficients of a biquad filter.
 #[derive(Clone, Debug)]
-struct BltApplier {
+pub struct BltApplier {
     b0: f32,
     b1: f32,
     b2: f32,
@@ -169,8 +401,102 @@ struct BltApplier {
 }
 
 impl BltApplier {
+    /// Filters the next sample of a channel, and updates the state of its filter.
     #[inline]
-    fn apply(&self, x_n: f32, x_n1: f32, x_n2: f32, y_n1: f32, y_n2: f32) -> f32 {
-        self.b0 * x_n + self.b1 * x_n1 + self.b2 * x_n2 - self.a1 * y_n1 - self.a2 * y_n2
+    pub fn apply(&self, x_n: f32, state: &mut BltState) -> f32 {
+        let y_n = self.b0 * x_n + self.b1 * state.x_n1 + self.b2 * state.x_n2
+            - self.a1 * state.y_n1
+            - self.a2 * state.y_n2;
+        *state = BltState {
+            x_n1: x_n,
+            x_n2: state.x_n1,
+            y_n1: y_n,
+            y_n2: state.y_n1,
+        };
+        y_n
+    }
+}
+
+/// The last two input and output samples of a channel.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BltState {
+    x_n1: f32,
+    x_n2: f32,
+    y_n1: f32,
+    y_n2: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use crate::buffer::SamplesBuffer;
+    use crate::source::{BltFilter, Source};
+
+    #[test]
+    fn filters_each_channel() {
+        // A constant on the left channel, silence on the right one.
+        let samples: Vec<f32> = (0..2000).map(|i| (i % 2 == 0) as u8 as f32).collect();
+
+        let low_pass: Vec<f32> = SamplesBuffer::new(2, 44100, samples.clone())
+            .low_pass(1000)
+            .collect();
+        assert!((low_pass[1998] - 1.0).abs() < 1e-3);
+        assert!(low_pass.iter().skip(1).step_by(2).all(|&s| s == 0.0));
+
+        let high_pass: Vec<f32> = SamplesBuffer::new(2, 44100, samples)
+            .high_pass(1000)
+            .collect();
+        assert!(high_pass[1998].abs() < 1e-3);
+        assert!(high_pass.iter().skip(1).step_by(2).all(|&s| s == 0.0));
+    }
+
+    /// Returns the gain in decibels of a filter at the given frequency, once it has settled.
+    fn gain_db<F>(freq: f32, filter: F) -> f32
+    where
+        F: FnOnce(SamplesBuffer<f32>) -> BltFilter<SamplesBuffer<f32>>,
+    {
+        let samples: Vec<f32> = (0..44100)
+            .map(|i| (2.0 * PI * freq * i as f32 / 44100.0).sin())
+            .collect();
+        let filtered: Vec<f32> = filter(SamplesBuffer::new(1, 44100, samples)).collect();
+        // The second half of the second holds a whole number of periods of the tested frequencies.
+        let tail = &filtered[22050..];
+        let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+        20.0 * (rms * 2f32.sqrt()).log10()
+    }
+
+    #[test]
+    fn band_pass_and_notch() {
+        assert!(gain_db(1000.0, |s| s.band_pass(1000, 1.0)).abs() < 0.1);
+        assert!(gain_db(100.0, |s| s.band_pass(1000, 1.0)) < -15.0);
+
+        assert!(gain_db(1000.0, |s| s.notch(1000, 1.0)) < -30.0);
+        assert!(gain_db(100.0, |s| s.notch(1000, 1.0)).abs() < 0.5);
+    }
+
+    #[test]
+    fn all_pass_keeps_level() {
+        for &freq in &[100.0, 1000.0, 10000.0] {
+            assert!(gain_db(freq, |s| s.all_pass(1000, 1.0)).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn peaking_and_shelves() {
+        assert!((gain_db(1000.0, |s| s.peaking(1000, 1.0, 6.0)) - 6.0).abs() < 0.1);
+        assert!(gain_db(50.0, |s| s.peaking(1000, 1.0, 6.0)).abs() < 0.5);
+
+        assert!((gain_db(30.0, |s| s.low_shelf(300, 0.707, 6.0)) - 6.0).abs() < 0.5);
+        assert!(gain_db(10000.0, |s| s.low_shelf(300, 0.707, 6.0)).abs() < 0.5);
+
+        assert!((gain_db(15000.0, |s| s.high_shelf(2000, 0.707, -6.0)) + 6.0).abs() < 0.5);
+        assert!(gain_db(50.0, |s| s.high_shelf(2000, 0.707, -6.0)).abs() < 0.5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_if_zero_q() {
+        SamplesBuffer::new(1, 44100, vec![0.0f32]).band_pass(1000, 0.0);
     }
 }
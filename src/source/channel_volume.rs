@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// Combines channels in input into a single mono source, then plays that mono sound
@@ -138,4 +139,9 @@ where
     fn total_duration(&self) -> Option<Duration> {
         self.input.total_duration()
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
 }
@@ -0,0 +1,276 @@
+use std::time::Duration;
+
+use cpal::Sample as CpalSample;
+
+use crate::source::SeekError;
+use crate::{Sample, Source};
+
+/// Internal function that builds a `Compressor` object.
+pub fn compressor<I>(
+    input: I,
+    threshold_db: f32,
+    ratio: f32,
+    attack: Duration,
+    release: Duration,
+    makeup_db: f32,
+) -> Compressor<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    assert!(
+        ratio >= 1.0,
+        "the ratio of a compressor must be at least 1, got {}",
+        ratio
+    );
+    let channels = input.channels();
+    let sample_rate = input.sample_rate();
+    Compressor {
+        input,
+        threshold_db,
+        ratio,
+        attack,
+        release,
+        makeup_db,
+        frame: Vec::with_capacity(channels as usize),
+        frame_pos: 0,
+        channels,
+        sample_rate,
+        reduction_db: 0.0,
+    }
+}
+
+/// Lowers the level of the sound by `ratio` above a threshold.
+///
+/// The level is the peak of all the channels, so that they all get the same gain and the stereo
+/// image is kept.
+#[derive(Clone, Debug)]
+pub struct Compressor<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    threshold_db: f32,
+    ratio: f32,
+    attack: Duration,
+    release: Duration,
+    makeup_db: f32,
+    // Samples of the current inter-channel frame, with the gain applied.
+    frame: Vec<I::Item>,
+    frame_pos: usize,
+    // Format of the current inter-channel frame.
+    channels: u16,
+    sample_rate: u32,
+    // Current gain reduction, in decibels.
+    reduction_db: f32,
+}
+
+impl<I> Compressor<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    /// Returns the current gain reduction in decibels, not counting the makeup gain.
+    #[inline]
+    pub fn gain_reduction(&self) -> f32 {
+        self.reduction_db
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Reads the next inter-channel frame and applies the gain to it. Returns false at the end of
+    /// the source.
+    fn next_frame(&mut self) -> bool {
+        self.channels = self.input.channels();
+        self.sample_rate = self.input.sample_rate();
+        self.frame.clear();
+        self.frame_pos = 0;
+        self.frame
+            .extend(self.input.by_ref().take(self.channels as usize));
+        if self.frame.is_empty() {
+            return false;
+        }
+
+        let peak = self
+            .frame
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.to_f32().abs()));
+        let level_db = 20.0 * peak.log10();
+        let target_db = (level_db - self.threshold_db).max(0.0) * (1.0 - 1.0 / self.ratio);
+
+        let time = if target_db > self.reduction_db {
+            self.attack
+        } else {
+            self.release
+        };
+        let coefficient = smoothing_coefficient(time, self.sample_rate);
+        self.reduction_db = target_db + (self.reduction_db - target_db) * coefficient;
+
+        let gain = 10f32.powf((self.makeup_db - self.reduction_db) / 20.0);
+        for sample in &mut self.frame {
+            *sample = sample.amplify(gain);
+        }
+        true
+    }
+
+    /// Returns true if the samples that follow the current frame have the same format.
+    #[inline]
+    fn same_format(&self) -> bool {
+        self.input.channels() == self.channels && self.input.sample_rate() == self.sample_rate
+    }
+}
+
+/// Returns the factor by which the distance to a target is multiplied at each inter-channel
+/// sample, so that about two thirds of the way are covered after `time`.
+pub fn smoothing_coefficient(time: Duration, sample_rate: u32) -> f32 {
+    let samples = time.as_secs_f32() * sample_rate as f32;
+    if samples > 0.0 {
+        (-1.0 / samples).exp()
+    } else {
+        0.0
+    }
+}
+
+impl<I> Iterator for Compressor<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        if self.frame_pos == self.frame.len() && !self.next_frame() {
+            return None;
+        }
+        let sample = self.frame[self.frame_pos];
+        self.frame_pos += 1;
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.frame.len() - self.frame_pos;
+        let (min, max) = self.input.size_hint();
+        (min + buffered, max.map(|max| max + buffered))
+    }
+}
+
+impl<I> ExactSizeIterator for Compressor<I>
+where
+    I: Source + ExactSizeIterator,
+    I::Item: Sample,
+{
+}
+
+impl<I> Source for Compressor<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        let buffered = self.frame.len() - self.frame_pos;
+        if buffered == 0 {
+            self.input.current_frame_len()
+        } else if self.same_format() {
+            self.input.current_frame_len().map(|len| len + buffered)
+        } else {
+            Some(buffered)
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        if self.frame_pos < self.frame.len() {
+            self.channels
+        } else {
+            self.input.channels()
+        }
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        if self.frame_pos < self.frame.len() {
+            self.sample_rate
+        } else {
+            self.input.sample_rate()
+        }
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.frame.clear();
+        self.frame_pos = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::buffer::SamplesBuffer;
+    use crate::source::Source;
+
+    #[test]
+    fn compresses_above_threshold() {
+        // A square wave at full scale on the left channel, and a quieter one on the right.
+        let samples: Vec<f32> = (0..20000)
+            .map(|i| {
+                let sign = if i / 100 % 2 == 0 { 1.0 } else { -1.0 };
+                sign * if i % 2 == 0 { 1.0 } else { 0.1 }
+            })
+            .collect();
+        let compressed: Vec<f32> = SamplesBuffer::new(2, 10000, samples)
+            .compressor(
+                -12.0,
+                4.0,
+                Duration::from_millis(1),
+                Duration::from_millis(50),
+                0.0,
+            )
+            .collect();
+        assert_eq!(compressed.len(), 20000);
+
+        // 12 dB above the threshold come out as 3 dB, and both channels get the same gain.
+        let expected = 10f32.powf(-9.0 / 20.0);
+        assert!((compressed[19998].abs() - expected).abs() < 0.01);
+        assert!((compressed[19999].abs() - expected / 10.0).abs() < 0.001);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_if_ratio_below_one() {
+        SamplesBuffer::new(1, 44100, vec![0.0f32]).compressor(
+            -18.0,
+            0.5,
+            Duration::from_millis(5),
+            Duration::from_millis(100),
+            0.0,
+        );
+    }
+}
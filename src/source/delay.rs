@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// Internal function that builds a `Delay` object.
@@ -105,4 +106,20 @@ where
             .total_duration()
             .map(|val| val + self.requested_duration)
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if pos < self.requested_duration {
+            self.input.try_seek(Duration::from_secs(0))?;
+            let remaining = self.requested_duration - pos;
+            let samples = remaining.as_nanos() * self.input.sample_rate() as u128 / 1_000_000_000
+                * self.input.channels() as u128;
+            self.remaining_samples = samples as usize;
+            Ok(())
+        } else {
+            self.input.try_seek(pos - self.requested_duration)?;
+            self.remaining_samples = 0;
+            Ok(())
+        }
+    }
 }
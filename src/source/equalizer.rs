@@ -0,0 +1,477 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::source::blt::{BltApplier, BltFormula, BltState};
+use crate::source::SeekError;
+use crate::Source;
+
+/// Number of inter-channel samples between two updates of the filters while their parameters
+/// move towards the ones set through the controller.
+const UPDATE_PERIOD: usize = 32;
+
+/// Time it takes for the parameters to cover about two thirds of the way to a new value.
+const SMOOTHING_TIME: f32 = 0.02;
+
+/// Internal function that builds an `Equalizer` object.
+pub fn equalizer<I>(input: I, bands: Vec<EqBand>) -> Equalizer<I>
+where
+    I: Source<Item = f32>,
+{
+    for band in &bands {
+        band.check();
+    }
+    let sample_rate = input.sample_rate();
+    let filters = bands
+        .iter()
+        .map(|&band| BandFilter {
+            current: band,
+            target: band,
+            applier: band.to_formula().to_applier(sample_rate),
+            states: Vec::new(),
+        })
+        .collect();
+
+    Equalizer {
+        input,
+        controller: Arc::new(EqualizerController {
+            changed: AtomicBool::new(false),
+            bands: Mutex::new(bands),
+        }),
+        filters,
+        sample_rate,
+        channel: 0,
+        until_update: 0,
+    }
+}
+
+/// The response of a band of an [`Equalizer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EqBandKind {
+    /// Changes the gain of the frequencies around the frequency of the band.
+    Peaking,
+    /// Changes the gain of the frequencies below the frequency of the band.
+    LowShelf,
+    /// Changes the gain of the frequencies above the frequency of the band.
+    HighShelf,
+}
+
+/// A band of an [`Equalizer`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EqBand {
+    /// The response of the band.
+    pub kind: EqBandKind,
+    /// Center frequency of a peaking band, or corner frequency of a shelf, in Hz.
+    pub freq: f32,
+    /// The higher the Q, the narrower the band, or the steeper the shelf.
+    pub q: f32,
+    /// Gain of the band in decibels. Zero leaves the sound unchanged.
+    pub gain_db: f32,
+}
+
+impl EqBand {
+    /// Builds a peaking band.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `freq` or `q` is not positive.
+    #[inline]
+    pub fn peaking(freq: f32, q: f32, gain_db: f32) -> EqBand {
+        let band = EqBand {
+            kind: EqBandKind::Peaking,
+            freq,
+            q,
+            gain_db,
+        };
+        band.check();
+        band
+    }
+
+    /// Builds a low-shelf band.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `freq` or `q` is not positive.
+    #[inline]
+    pub fn low_shelf(freq: f32, q: f32, gain_db: f32) -> EqBand {
+        let band = EqBand {
+            kind: EqBandKind::LowShelf,
+            freq,
+            q,
+            gain_db,
+        };
+        band.check();
+        band
+    }
+
+    /// Builds a high-shelf band.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `freq` or `q` is not positive.
+    #[inline]
+    pub fn high_shelf(freq: f32, q: f32, gain_db: f32) -> EqBand {
+        let band = EqBand {
+            kind: EqBandKind::HighShelf,
+            freq,
+            q,
+            gain_db,
+        };
+        band.check();
+        band
+    }
+
+    /// Panics if the frequency or the Q is not positive, which the filters cannot handle.
+    #[inline]
+    fn check(&self) {
+        assert!(
+            self.freq > 0.0,
+            "the frequency of a band must be positive, got {}",
+            self.freq
+        );
+        assert!(
+            self.q > 0.0,
+            "the Q of a band must be positive, got {}",
+            self.q
+        );
+    }
+
+    fn to_formula(self) -> BltFormula {
+        let EqBand {
+            freq, q, gain_db, ..
+        } = self;
+        match self.kind {
+            EqBandKind::Peaking => BltFormula::Peaking { freq, q, gain_db },
+            EqBandKind::LowShelf => BltFormula::LowShelf { freq, q, gain_db },
+            EqBandKind::HighShelf => BltFormula::HighShelf { freq, q, gain_db },
+        }
+    }
+
+    /// Moves the parameters towards the ones of `target` by the given fraction of the way.
+    ///
+    /// The gain moves linearly in decibels, and the frequency and Q geometrically, which is how
+    /// they are perceived.
+    fn approach(&mut self, target: &EqBand, fraction: f32) {
+        if self.kind != target.kind {
+            *self = *target;
+            return;
+        }
+
+        self.gain_db += (target.gain_db - self.gain_db) * fraction;
+        self.freq *= (target.freq / self.freq).powf(fraction);
+        self.q *= (target.q / self.q).powf(fraction);
+
+        // A band that cannot get there, such as one that started from an infinite frequency,
+        // jumps to the target.
+        if !(self.gain_db.is_finite() && self.freq.is_finite() && self.q.is_finite()) {
+            *self = *target;
+            return;
+        }
+
+        // Close enough not to be heard.
+        if (target.gain_db - self.gain_db).abs() < 0.01
+            && (target.freq / self.freq - 1.0).abs() < 0.001
+            && (target.q / self.q - 1.0).abs() < 0.001
+        {
+            *self = *target;
+        }
+    }
+}
+
+/// Changes the bands of an [`Equalizer`] from any thread.
+///
+/// The changes are smoothed over a few tens of milliseconds, so that they can be made while the
+/// sound plays without producing clicks.
+pub struct EqualizerController {
+    changed: AtomicBool,
+    bands: Mutex<Vec<EqBand>>,
+}
+
+impl EqualizerController {
+    /// Returns the bands, as last set.
+    pub fn bands(&self) -> Vec<EqBand> {
+        self.bands.lock().unwrap().clone()
+    }
+
+    /// Replaces a band.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no band at `index`, or if the frequency or the Q of `band` is not
+    /// positive.
+    pub fn set_band(&self, index: usize, band: EqBand) {
+        band.check();
+        self.update(index, |b| *b = band);
+    }
+
+    /// Sets the gain of a band, in decibels.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no band at `index`.
+    pub fn set_gain(&self, index: usize, gain_db: f32) {
+        self.update(index, |b| b.gain_db = gain_db);
+    }
+
+    /// Sets the frequency of a band, in Hz.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no band at `index`, or if `freq` is not positive.
+    pub fn set_frequency(&self, index: usize, freq: f32) {
+        assert!(
+            freq > 0.0,
+            "the frequency of a band must be positive, got {}",
+            freq
+        );
+        self.update(index, |b| b.freq = freq);
+    }
+
+    /// Sets the Q of a band.
+    ///
+    /// # Panic
+    ///
+    /// Panics if there is no band at `index`, or if `q` is not positive.
+    pub fn set_q(&self, index: usize, q: f32) {
+        assert!(q > 0.0, "the Q of a band must be positive, got {}", q);
+        self.update(index, |b| b.q = q);
+    }
+
+    fn update<F>(&self, index: usize, change: F)
+    where
+        F: FnOnce(&mut EqBand),
+    {
+        let mut bands = self.bands.lock().unwrap();
+        let count = bands.len();
+        if index >= count {
+            // Panicking while holding the lock would poison it for the audio thread.
+            drop(bands);
+            panic!("no band {} in an equalizer of {} bands", index, count);
+        }
+        change(&mut bands[index]);
+        self.changed.store(true, Ordering::Release);
+    }
+}
+
+/// Multi-band parametric equalizer, made of peaking and shelf filters applied one after the
+/// other to each channel.
+///
+/// The bands can be changed while playing with the [`EqualizerController`] returned by
+/// [`controller`](Equalizer::controller).
+pub struct Equalizer<I> {
+    input: I,
+    controller: Arc<EqualizerController>,
+    filters: Vec<BandFilter>,
+    // Sample rate for which the filters have been computed.
+    sample_rate: u32,
+    // Channel of the next sample.
+    channel: usize,
+    // Number of inter-channel samples until the filters are updated again.
+    until_update: usize,
+}
+
+/// The filter of a band of an `Equalizer`.
+struct BandFilter {
+    // Parameters of the filter, which move towards `target`.
+    current: EqBand,
+    target: EqBand,
+    applier: BltApplier,
+    // State of the filter of each channel.
+    states: Vec<BltState>,
+}
+
+impl<I> Equalizer<I>
+where
+    I: Source<Item = f32>,
+{
+    /// Returns the controller of the bands, which can be sent to other threads.
+    #[inline]
+    pub fn controller(&self) -> Arc<EqualizerController> {
+        self.controller.clone()
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Moves the filters towards the bands set through the controller.
+    fn update_filters(&mut self) {
+        if self.controller.changed.swap(false, Ordering::Acquire) {
+            let bands = self.controller.bands.lock().unwrap();
+            for (filter, band) in self.filters.iter_mut().zip(bands.iter()) {
+                filter.target = *band;
+            }
+        }
+
+        let sample_rate = self.input.sample_rate();
+        let fraction =
+            1.0 - (-(UPDATE_PERIOD as f32) / (SMOOTHING_TIME * sample_rate as f32)).exp();
+        for filter in &mut self.filters {
+            if filter.current != filter.target {
+                filter.current.approach(&filter.target, fraction);
+            } else if sample_rate == self.sample_rate {
+                continue;
+            }
+            filter.applier = filter.current.to_formula().to_applier(sample_rate);
+        }
+        self.sample_rate = sample_rate;
+        self.until_update = UPDATE_PERIOD;
+    }
+}
+
+impl<I> Iterator for Equalizer<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let last_in_frame = self.input.current_frame_len() == Some(1);
+
+        if self.channel == 0 {
+            if self.until_update == 0 || self.input.sample_rate() != self.sample_rate {
+                self.update_filters();
+            }
+            self.until_update -= 1;
+        }
+
+        // The channels count can only change at the start of a frame.
+        let channels = self.input.channels() as usize;
+        let mut sample = self.input.next()?;
+        for filter in &mut self.filters {
+            if filter.states.len() != channels {
+                filter.states.resize(channels, BltState::default());
+            }
+            sample = filter
+                .applier
+                .apply(sample, &mut filter.states[self.channel]);
+        }
+
+        self.channel += 1;
+        if self.channel == channels || last_in_frame {
+            self.channel = 0;
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> ExactSizeIterator for Equalizer<I> where I: Source<Item = f32> + ExactSizeIterator {}
+
+impl<I> Source for Equalizer<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        // Seeking lands on the first channel.
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::{EqBand, SineWave, Source};
+
+    /// Returns the amplitude of the samples.
+    fn amplitude(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |max, s| s.abs().max(max))
+    }
+
+    #[test]
+    fn smooths_changes() {
+        let mut source = SineWave::new(1000).equalizer(vec![
+            EqBand::low_shelf(100.0, 0.7, -12.0),
+            EqBand::peaking(1000.0, 1.0, 6.0),
+        ]);
+        let controller = source.controller();
+
+        let samples: Vec<f32> = source.by_ref().take(48000).collect();
+        assert!((amplitude(&samples[44100..]) - 2.0).abs() < 0.05);
+
+        controller.set_gain(1, 0.0);
+        assert_eq!(controller.bands()[1].gain_db, 0.0);
+        // The gain goes down over a few tens of milliseconds, not all at once.
+        let samples: Vec<f32> = source.by_ref().take(4410).collect();
+        assert!(amplitude(&samples[..100]) > 1.8);
+        assert!(amplitude(&samples[4000..]) < 1.1);
+
+        let samples: Vec<f32> = source.take(4410).collect();
+        assert!((amplitude(&samples) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_if_zero_q() {
+        EqBand::peaking(1000.0, 0.0, 6.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_if_negative_frequency() {
+        let source = SineWave::new(1000).equalizer(vec![EqBand::peaking(1000.0, 1.0, 6.0)]);
+        source.controller().set_frequency(0, -1000.0);
+    }
+
+    #[test]
+    fn survives_a_missing_band() {
+        let mut source = SineWave::new(1000).equalizer(vec![EqBand::peaking(1000.0, 1.0, 6.0)]);
+        let controller = source.controller();
+        let result = std::panic::catch_unwind(|| controller.set_gain(1, 0.0));
+        assert!(result.is_err());
+
+        // The bands can still be read and changed.
+        controller.set_gain(0, 0.0);
+        assert_eq!(source.by_ref().take(4410).count(), 4410);
+        assert_eq!(controller.bands()[0].gain_db, 0.0);
+    }
+
+    #[test]
+    fn jumps_from_infinite_frequency() {
+        let mut band = EqBand::peaking(f32::INFINITY, 1.0, 6.0);
+        let target = EqBand::peaking(1000.0, 1.0, 6.0);
+        band.approach(&target, 0.1);
+        assert_eq!(band, target);
+    }
+}
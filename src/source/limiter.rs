@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use cpal::Sample as CpalSample;
+
+use crate::source::compressor::smoothing_coefficient;
+use crate::source::SeekError;
+use crate::{Sample, Source};
+
+/// How far ahead of the samples it plays the limiter reads the input, to see the peaks coming.
+const LOOKAHEAD: Duration = Duration::from_millis(5);
+
+/// Time it takes for the gain to cover about two thirds of the way back up after a peak.
+const RELEASE: Duration = Duration::from_millis(100);
+
+/// Internal function that builds a `Limiter` object.
+pub fn limiter<I>(input: I, ceiling_db: f32) -> Limiter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    let channels = input.channels();
+    let sample_rate = input.sample_rate();
+    let lookahead = (LOOKAHEAD.as_secs_f32() * sample_rate as f32)
+        .ceil()
+        .max(1.0) as usize;
+
+    Limiter {
+        input,
+        ceiling: 10f32.powf(ceiling_db / 20.0),
+        lookahead,
+        channels,
+        sample_rate,
+        delayed: VecDeque::new(),
+        frames_gains: VecDeque::new(),
+        minima: VecDeque::new(),
+        window_gains: vec![1.0; lookahead].into(),
+        window_sum: lookahead as f64,
+        pushed: 0,
+        popped: 0,
+        ended: false,
+        gain: 1.0,
+        frame_left: 0,
+    }
+}
+
+/// Keeps the peaks of the sound below a ceiling, by lowering the gain just before they come.
+///
+/// The input is read a few milliseconds ahead, so that the gain is already low enough when a peak
+/// plays. The gain is the same for all the channels.
+#[derive(Clone, Debug)]
+pub struct Limiter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    // Highest amplitude of the output.
+    ceiling: f32,
+    // Number of inter-channel samples of look-ahead.
+    lookahead: usize,
+    // Format of the delayed samples.
+    channels: u16,
+    sample_rate: u32,
+    // Samples read from the input and not played yet.
+    delayed: VecDeque<I::Item>,
+    // Highest gain that keeps each delayed inter-channel sample below the ceiling.
+    frames_gains: VecDeque<f32>,
+    // Candidates for the lowest of `frames_gains` over the look-ahead, in increasing order of both
+    // index and gain.
+    minima: VecDeque<(u64, f32)>,
+    // Lowest gains over the look-ahead for the last `lookahead` inter-channel samples played, and
+    // their sum. Averaging them makes the gain ramp down over the look-ahead while still reaching
+    // the lowest gain by the time the peak plays.
+    window_gains: VecDeque<f32>,
+    window_sum: f64,
+    // Number of inter-channel samples read from the input and played.
+    pushed: u64,
+    popped: u64,
+    ended: bool,
+    // Gain of the inter-channel sample being played.
+    gain: f32,
+    // Number of samples left in the inter-channel sample being played.
+    frame_left: usize,
+}
+
+impl<I> Limiter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    /// Returns the current gain reduction in decibels.
+    #[inline]
+    pub fn gain_reduction(&self) -> f32 {
+        -20.0 * self.gain.log10()
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Returns true if the samples that follow the delayed ones have the same format.
+    #[inline]
+    fn same_format(&self) -> bool {
+        self.input.channels() == self.channels && self.input.sample_rate() == self.sample_rate
+    }
+
+    /// Reads an inter-channel sample from the input. Returns false at the end of the input.
+    fn read_frame(&mut self) -> bool {
+        let mut peak = 0.0f32;
+        let mut len = 0;
+        for sample in self.input.by_ref().take(self.channels as usize) {
+            peak = peak.max(sample.to_f32().abs());
+            self.delayed.push_back(sample);
+            len += 1;
+        }
+        if len == 0 {
+            return false;
+        }
+
+        let gain = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+        self.frames_gains.push_back(gain);
+        while matches!(self.minima.back(), Some(&(_, g)) if g >= gain) {
+            self.minima.pop_back();
+        }
+        self.minima.push_back((self.pushed, gain));
+        self.pushed += 1;
+        true
+    }
+
+    /// Computes the gain of the next inter-channel sample to play. Returns false if there is none.
+    fn next_frame(&mut self) -> bool {
+        while self.frames_gains.len() < self.lookahead && !self.ended {
+            if !self.same_format() {
+                // The samples of the previous format are played out before starting over.
+                if !self.frames_gains.is_empty() {
+                    break;
+                }
+                self.channels = self.input.channels();
+                self.sample_rate = self.input.sample_rate();
+            }
+            if !self.read_frame() {
+                self.ended = true;
+            }
+        }
+        let frame_gain = match self.frames_gains.pop_front() {
+            Some(gain) => gain,
+            None => return false,
+        };
+
+        while matches!(self.minima.front(), Some(&(i, _)) if i < self.popped) {
+            self.minima.pop_front();
+        }
+        // The look-ahead past the delayed samples is silence.
+        let lowest = self.minima.front().map_or(frame_gain, |&(_, g)| g);
+        self.popped += 1;
+
+        self.window_sum += lowest as f64 - self.window_gains.pop_front().unwrap_or(1.0) as f64;
+        self.window_gains.push_back(lowest);
+        let target = (self.window_sum / self.lookahead as f64) as f32;
+
+        // Lowering the gain is already smoothed by the average, but raising it is slower.
+        self.gain = if target < self.gain {
+            target
+        } else {
+            let coefficient = smoothing_coefficient(RELEASE, self.sample_rate);
+            target + (self.gain - target) * coefficient
+        };
+        self.frame_left = (self.channels as usize).min(self.delayed.len());
+        true
+    }
+}
+
+impl<I> Iterator for Limiter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        if self.frame_left == 0 && !self.next_frame() {
+            return None;
+        }
+        self.frame_left -= 1;
+        self.delayed
+            .pop_front()
+            .map(|sample| sample.amplify(self.gain))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.delayed.len();
+        let (min, max) = self.input.size_hint();
+        (min + buffered, max.map(|max| max + buffered))
+    }
+}
+
+impl<I> ExactSizeIterator for Limiter<I>
+where
+    I: Source + ExactSizeIterator,
+    I::Item: Sample,
+{
+}
+
+impl<I> Source for Limiter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        let buffered = self.delayed.len();
+        if buffered == 0 {
+            self.input.current_frame_len()
+        } else if self.same_format() {
+            self.input.current_frame_len().map(|len| len + buffered)
+        } else {
+            Some(buffered)
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        if self.delayed.is_empty() {
+            self.input.channels()
+        } else {
+            self.channels
+        }
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        if self.delayed.is_empty() {
+            self.input.sample_rate()
+        } else {
+            self.sample_rate
+        }
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.delayed.clear();
+        self.frames_gains.clear();
+        self.minima.clear();
+        self.popped = self.pushed;
+        self.ended = false;
+        self.frame_left = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::SamplesBuffer;
+    use crate::source::Source;
+
+    #[test]
+    fn stays_below_ceiling() {
+        // Quiet on both channels, with a loud burst on the left channel only.
+        let samples: Vec<f32> = (0..4000)
+            .map(|i| {
+                let sign = if i / 20 % 2 == 0 { 1.0 } else { -1.0 };
+                let loud = i % 2 == 0 && (2000..2200).contains(&i);
+                sign * if loud { 1.0 } else { 0.25 }
+            })
+            .collect();
+        let limited: Vec<f32> = SamplesBuffer::new(2, 10000, samples.clone())
+            .limiter(-6.0)
+            .collect();
+        assert_eq!(limited.len(), samples.len());
+
+        let ceiling = 10f32.powf(-6.0 / 20.0);
+        assert!(limited.iter().all(|s| s.abs() <= ceiling + 1e-6));
+        // Away from the burst, the sound is unchanged.
+        assert_eq!(limited[..1800], samples[..1800]);
+        // Both channels are lowered by the same gain during the burst.
+        assert!((limited[2100].abs() - ceiling).abs() < 1e-6);
+        assert!((limited[2101].abs() - ceiling / 4.0).abs() < 1e-6);
+    }
+}
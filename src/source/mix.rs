@@ -2,6 +2,7 @@ use std::cmp;
 use std::time::Duration;
 
 use crate::source::uniform::UniformSourceIterator;
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// Internal function that builds a `Mix` object.
@@ -118,4 +119,14 @@ where
             _ => None,
         }
     }
+
+    /// Seeks both sources, the first one first.
+    ///
+    /// If the second source fails to seek, its error is returned while the first source has
+    /// already moved to `pos`, so the two are out of step until they are seeked again.
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input1.try_seek(pos)?;
+        self.input2.try_seek(pos)
+    }
 }
@@ -1,24 +1,35 @@
 //! Sources of sound and various filters.
 
+use std::error::Error;
+use std::fmt;
 use std::time::Duration;
 
+use crate::decoder::Marker;
 use crate::Sample;
 
+use self::blt::BltFormula;
+
 pub use self::amplify::Amplify;
 pub use self::blt::BltFilter;
 pub use self::buffered::Buffered;
 pub use self::channel_volume::ChannelVolume;
+pub use self::compressor::Compressor;
 pub use self::crossfade::Crossfade;
 pub use self::delay::Delay;
 pub use self::done::Done;
 pub use self::empty::Empty;
+pub use self::equalizer::{EqBand, EqBandKind, Equalizer, EqualizerController};
 pub use self::fadein::FadeIn;
 pub use self::from_factory::{from_factory, FromFactoryIter};
 pub use self::from_iter::{from_iter, FromIter};
+pub use self::limiter::Limiter;
 pub use self::mix::Mix;
+pub use self::on_markers::OnMarkers;
 pub use self::pausable::Pausable;
 pub use self::periodic::PeriodicAccess;
+pub use self::prefetch::Prefetch;
 pub use self::repeat::Repeat;
+pub use self::reverb::{Reverb, ReverbSettings};
 pub use self::samples_converter::SamplesConverter;
 pub use self::sine::SineWave;
 pub use self::skip::SkipDuration;
@@ -26,6 +37,7 @@ pub use self::spatial::Spatial;
 pub use self::speed::Speed;
 pub use self::stoppable::Stoppable;
 pub use self::take::TakeDuration;
+pub use self::time_stretch::{TimeStretch, TimeStretchController};
 pub use self::uniform::UniformSourceIterator;
 pub use self::zero::Zero;
 
@@ -33,17 +45,23 @@ mod amplify;
 mod blt;
 mod buffered;
 mod channel_volume;
+mod compressor;
 mod crossfade;
 mod delay;
 mod done;
 mod empty;
+mod equalizer;
 mod fadein;
 mod from_factory;
 mod from_iter;
+mod limiter;
 mod mix;
+mod on_markers;
 mod pausable;
 mod periodic;
+mod prefetch;
 mod repeat;
+mod reverb;
 mod samples_converter;
 mod sine;
 mod skip;
@@ -51,6 +69,7 @@ mod spatial;
 mod speed;
 mod stoppable;
 mod take;
+mod time_stretch;
 mod uniform;
 mod zero;
 
@@ -144,6 +163,21 @@ where
     /// `None` indicates at the same time "infinite" or "unknown".
     fn total_duration(&self) -> Option<Duration>;
 
+    /// Attempts to seek to a given position in the current source.
+    ///
+    /// The position is measured from the start of the source. Seeking beyond the end of the
+    /// source is not an error; the source will simply end.
+    ///
+    /// As long as the duration of the source is known, seek is guaranteed to saturate at the end
+    /// of the source. If a source does not support seeking (the default), a
+    /// [`SeekError::NotSupported`] is returned and the source is left untouched.
+    #[inline]
+    fn try_seek(&mut self, _pos: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: std::any::type_name::<Self>(),
+        })
+    }
+
     /// Stores the source in a buffer in addition to returning it. This iterator can be cloned.
     #[inline]
     fn buffered(self) -> Buffered<Self>
@@ -257,6 +291,62 @@ where
         periodic::periodic(self, period, access)
     }
 
+    /// Calls `callback` with each marker when the samples at its position are reached, which
+    /// lets a program follow the playback of a source handed over to a `Sink`.
+    ///
+    /// The markers of a file are available from
+    /// [`Decoder::metadata`](crate::Decoder::metadata). The callback is called from the thread
+    /// that reads the samples, which is usually the audio thread, so it should return quickly,
+    /// for example by sending the marker through a channel. Markers skipped over by a seek are
+    /// not reported.
+    ///
+    /// ```
+    /// use std::sync::mpsc;
+    /// use rodio::decoder::Marker;
+    /// use rodio::source::{SineWave, Source};
+    /// use rodio::Sink;
+    ///
+    /// let markers = vec![Marker { position: 48000, label: "one second".to_owned() }];
+    /// let (tx, rx) = mpsc::channel();
+    /// let source = SineWave::new(440).on_markers(markers, move |marker| {
+    ///     let _ = tx.send(marker.label.clone());
+    /// });
+    ///
+    /// let (sink, mut output) = Sink::new_idle();
+    /// sink.append(source);
+    /// output.by_ref().take(48001).count();
+    /// assert_eq!(rx.try_recv().unwrap(), "one second");
+    /// ```
+    #[inline]
+    fn on_markers<F>(self, markers: Vec<Marker>, callback: F) -> OnMarkers<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Marker),
+    {
+        on_markers::on_markers(self, markers, callback)
+    }
+
+    /// Decodes the source on a separate thread, keeping up to `duration` of samples ready to be
+    /// played.
+    ///
+    /// Reading the samples then only copies them out of a lock-free buffer, which keeps
+    /// expensive decoding off the audio thread. If the thread falls behind, silence is played
+    /// until it catches up, and [`Prefetch::underrun_counter`] is incremented.
+    ///
+    /// Seeking waits for the thread to seek the source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the thread cannot be spawned.
+    #[inline]
+    fn prefetch(self, duration: Duration) -> Prefetch<Self::Item>
+    where
+        Self: Sized + Send + 'static,
+        Self::Item: Send,
+    {
+        prefetch::prefetch(self, duration)
+    }
+
     /// Changes the play speed of the sound. Does not adjust the samples, only the play speed.
     #[inline]
     fn speed(self, ratio: f32) -> Speed<Self>
@@ -266,25 +356,55 @@ where
         speed::speed(self, ratio)
     }
 
-    /// Adds a basic reverb effect.
+    /// Plays the sound faster or slower by `ratio` without changing its pitch, unlike
+    /// [`speed`](Source::speed).
     ///
-    /// This function requires the source to implement `Clone`. This can be done by using
-    /// `buffered()`.
+    /// A ratio of 2.0 plays the sound twice as fast. The ratio can be changed while the sound
+    /// plays, from any thread, through [`TimeStretch::controller`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if `ratio` is not strictly positive.
     ///
     /// # Example
     ///
-    /// ```ignore
-    /// use std::time::Duration;
+    /// ```
+    /// use rodio::source::{SineWave, Source};
+    ///
+    /// let source = SineWave::new(440).time_stretch(1.5);
+    /// let controller = source.controller();
+    /// controller.set_ratio(0.75);
+    /// ```
+    #[inline]
+    fn time_stretch(self, ratio: f32) -> TimeStretch<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        time_stretch::time_stretch(self, ratio)
+    }
+
+    /// Adds a reverb to the sound, as if it was played in a room.
+    ///
+    /// The reverb keeps playing for a while after the source ends.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rodio::source::{ReverbSettings, SineWave, Source};
     ///
-    /// let source = source.buffered().reverb(Duration::from_millis(100), 0.7);
+    /// let source = SineWave::new(440).reverb(ReverbSettings {
+    ///     room_size: 0.8,
+    ///     ..ReverbSettings::default()
+    /// });
     /// ```
     #[inline]
-    fn reverb(self, duration: Duration, amplitude: f32) -> Mix<Self, Delay<Amplify<Self>>>
+    fn reverb(self, settings: ReverbSettings) -> Reverb<Self>
     where
-        Self: Sized + Clone,
+        Self: Sized,
+        Self: Source<Item = f32>,
     {
-        let echo = self.clone().amplify(amplitude).delay(duration);
-        self.mix(echo)
+        reverb::reverb(self, settings)
     }
 
     /// Converts the samples of this source to another type.
@@ -317,8 +437,11 @@ where
         stoppable::stoppable(self)
     }
 
-    /// Applies a low-pass filter to the source.
-    /// **Warning**: Probably buggy.
+    /// Applies a low-pass filter to the source, with a Q of 0.5.
+    ///
+    /// Like the other filters below, it is a biquad filter designed with the formulas of the
+    /// Audio EQ Cookbook, and each channel is filtered separately. The filters that take a Q
+    /// panic if it is not positive.
     #[inline]
     fn low_pass(self, freq: u32) -> BltFilter<Self>
     where
@@ -327,6 +450,245 @@ where
     {
         blt::low_pass(self, freq)
     }
+
+    /// Applies a low-pass filter to the source, with the given Q. A Q of 0.707 gives the
+    /// flattest pass band.
+    #[inline]
+    fn low_pass_with_q(self, freq: u32, q: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::LowPass {
+                freq: freq as f32,
+                q,
+            },
+        )
+    }
+
+    /// Applies a high-pass filter to the source, with a Q of 0.5.
+    #[inline]
+    fn high_pass(self, freq: u32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::high_pass(self, freq)
+    }
+
+    /// Applies a high-pass filter to the source, with the given Q.
+    #[inline]
+    fn high_pass_with_q(self, freq: u32, q: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::HighPass {
+                freq: freq as f32,
+                q,
+            },
+        )
+    }
+
+    /// Applies a band-pass filter to the source, which keeps the frequencies around `freq`
+    /// with a gain of 0 dB at `freq`. A higher Q makes the band narrower.
+    #[inline]
+    fn band_pass(self, freq: u32, q: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::BandPass {
+                freq: freq as f32,
+                q,
+            },
+        )
+    }
+
+    /// Applies a notch filter to the source, which removes the frequencies around `freq`. A
+    /// higher Q makes the notch narrower.
+    #[inline]
+    fn notch(self, freq: u32, q: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::Notch {
+                freq: freq as f32,
+                q,
+            },
+        )
+    }
+
+    /// Applies an all-pass filter to the source, which keeps the gain of every frequency but
+    /// shifts their phase around `freq`.
+    #[inline]
+    fn all_pass(self, freq: u32, q: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::AllPass {
+                freq: freq as f32,
+                q,
+            },
+        )
+    }
+
+    /// Applies a peaking filter to the source, which changes the gain of the frequencies
+    /// around `freq` by `gain_db` decibels. A higher Q makes the band narrower.
+    #[inline]
+    fn peaking(self, freq: u32, q: f32, gain_db: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::Peaking {
+                freq: freq as f32,
+                q,
+                gain_db,
+            },
+        )
+    }
+
+    /// Applies a low-shelf filter to the source, which changes the gain of the frequencies
+    /// below `freq` by `gain_db` decibels.
+    #[inline]
+    fn low_shelf(self, freq: u32, q: f32, gain_db: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::LowShelf {
+                freq: freq as f32,
+                q,
+                gain_db,
+            },
+        )
+    }
+
+    /// Applies a high-shelf filter to the source, which changes the gain of the frequencies
+    /// above `freq` by `gain_db` decibels.
+    #[inline]
+    fn high_shelf(self, freq: u32, q: f32, gain_db: f32) -> BltFilter<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        blt::blt_filter(
+            self,
+            BltFormula::HighShelf {
+                freq: freq as f32,
+                q,
+                gain_db,
+            },
+        )
+    }
+
+    /// Applies a multi-band parametric equalizer to the source, made of peaking and shelf
+    /// bands.
+    ///
+    /// The bands can be changed while the sound plays, from any thread, through
+    /// [`Equalizer::controller`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the frequency or the Q of a band is not positive.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rodio::source::{EqBand, SineWave, Source};
+    ///
+    /// let source = SineWave::new(440).equalizer(vec![
+    ///     EqBand::low_shelf(100.0, 0.7, 3.0),
+    ///     EqBand::peaking(1000.0, 1.0, -2.0),
+    ///     EqBand::high_shelf(8000.0, 0.7, 1.5),
+    /// ]);
+    /// let controller = source.controller();
+    /// controller.set_gain(1, 0.0);
+    /// ```
+    #[inline]
+    fn equalizer(self, bands: Vec<EqBand>) -> Equalizer<Self>
+    where
+        Self: Sized,
+        Self: Source<Item = f32>,
+    {
+        equalizer::equalizer(self, bands)
+    }
+
+    /// Lowers the level of the parts of the sound that are louder than `threshold_db`, in
+    /// decibels relative to full scale.
+    ///
+    /// Above the threshold, the level only goes up by one decibel every `ratio` decibels of the
+    /// input. The gain reduction reacts within `attack` when the sound gets louder, and goes away
+    /// within `release` when it gets quieter. The output is then amplified by `makeup_db`
+    /// decibels, to make up for the lower level.
+    ///
+    /// All the channels get the same gain, based on the loudest of them.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `ratio` is lower than 1, which would make the sound louder above the threshold.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use rodio::source::{SineWave, Source};
+    ///
+    /// let source = SineWave::new(440).compressor(
+    ///     -18.0,
+    ///     4.0,
+    ///     Duration::from_millis(5),
+    ///     Duration::from_millis(100),
+    ///     6.0,
+    /// );
+    /// ```
+    #[inline]
+    fn compressor(
+        self,
+        threshold_db: f32,
+        ratio: f32,
+        attack: Duration,
+        release: Duration,
+        makeup_db: f32,
+    ) -> Compressor<Self>
+    where
+        Self: Sized,
+        Self::Item: Sample,
+    {
+        compressor::compressor(self, threshold_db, ratio, attack, release, makeup_db)
+    }
+
+    /// Keeps the peaks of the sound below `ceiling_db`, in decibels relative to full scale,
+    /// without changing the level of the rest of the sound.
+    ///
+    /// The source is read 5 ms ahead, so that the gain goes down smoothly before each peak. All
+    /// the channels get the same gain. Putting a limiter after a mixer prevents the sum of the
+    /// sounds from clipping.
+    #[inline]
+    fn limiter(self, ceiling_db: f32) -> Limiter<Self>
+    where
+        Self: Sized,
+        Self::Item: Sample,
+    {
+        limiter::limiter(self, ceiling_db)
+    }
 }
 
 impl<S> Source for Box<dyn Source<Item = S>>
@@ -352,6 +714,11 @@ where
     fn total_duration(&self) -> Option<Duration> {
         (**self).total_duration()
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        (**self).try_seek(pos)
+    }
 }
 
 impl<S> Source for Box<dyn Source<Item = S> + Send>
@@ -377,6 +744,11 @@ where
     fn total_duration(&self) -> Option<Duration> {
         (**self).total_duration()
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        (**self).try_seek(pos)
+    }
 }
 
 impl<S> Source for Box<dyn Source<Item = S> + Send + Sync>
@@ -402,4 +774,94 @@ where
     fn total_duration(&self) -> Option<Duration> {
         (**self).total_duration()
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        (**self).try_seek(pos)
+    }
+}
+
+/// Error that can happen when seeking a source with [`Source::try_seek`].
+#[derive(Debug)]
+pub enum SeekError {
+    /// One of the underlying sources does not support seeking.
+    NotSupported {
+        /// The type name of the source that could not seek.
+        underlying_source: &'static str,
+    },
+    /// The WAV decoder failed to reposition the stream.
+    #[cfg(feature = "wav")]
+    HoundDecoder(std::io::Error),
+    /// The Vorbis decoder failed to reposition the stream.
+    #[cfg(feature = "vorbis")]
+    LewtonDecoder(lewton::VorbisError),
+    /// The Flac decoder failed to reposition the stream.
+    #[cfg(feature = "flac")]
+    ClaxonDecoder(claxon::Error),
+    /// The MP3 decoder failed to reposition the stream.
+    #[cfg(feature = "mp3")]
+    Minimp3Decoder(minimp3::Error),
+    /// The Opus decoder failed to reposition the stream.
+    #[cfg(feature = "opus")]
+    OpusDecoder(crate::decoder::DecoderError),
+    /// A [custom decoder](crate::decoder::CustomDecoder) failed to reposition the stream.
+    CustomDecoder(crate::decoder::DecoderError),
+    /// Rewinding the underlying reader failed.
+    Io(std::io::Error),
+    /// The [`Sink`](crate::Sink) had no sound to seek: it was empty or stopped, or the sound
+    /// ended before it could be seeked.
+    NotPlaying,
+    /// The [`Sink`](crate::Sink) is not being played, so the seek was given up.
+    TimedOut,
+    /// Another seek of the [`Sink`](crate::Sink) was requested before this one could be
+    /// performed.
+    Superseded,
+}
+
+impl fmt::Display for SeekError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeekError::NotSupported { underlying_source } => write!(
+                f,
+                "Seeking is not supported by source: {}",
+                underlying_source
+            ),
+            #[cfg(feature = "wav")]
+            SeekError::HoundDecoder(err) => write!(f, "Error seeking wav: {}", err),
+            #[cfg(feature = "vorbis")]
+            SeekError::LewtonDecoder(err) => write!(f, "Error seeking vorbis: {}", err),
+            #[cfg(feature = "flac")]
+            SeekError::ClaxonDecoder(err) => write!(f, "Error seeking flac: {}", err),
+            #[cfg(feature = "mp3")]
+            SeekError::Minimp3Decoder(err) => write!(f, "Error seeking mp3: {}", err),
+            #[cfg(feature = "opus")]
+            SeekError::OpusDecoder(err) => write!(f, "Error seeking opus: {}", err),
+            SeekError::CustomDecoder(err) => write!(f, "Error seeking custom format: {}", err),
+            SeekError::Io(err) => write!(f, "IO error while seeking: {}", err),
+            SeekError::NotPlaying => write!(f, "No sound is playing"),
+            SeekError::TimedOut => write!(f, "The sound is not being played"),
+            SeekError::Superseded => write!(f, "Another seek was requested"),
+        }
+    }
+}
+
+impl Error for SeekError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SeekError::NotSupported { .. } => None,
+            #[cfg(feature = "wav")]
+            SeekError::HoundDecoder(err) => Some(err),
+            #[cfg(feature = "vorbis")]
+            SeekError::LewtonDecoder(err) => Some(err),
+            #[cfg(feature = "flac")]
+            SeekError::ClaxonDecoder(err) => Some(err),
+            #[cfg(feature = "mp3")]
+            SeekError::Minimp3Decoder(err) => Some(err),
+            #[cfg(feature = "opus")]
+            SeekError::OpusDecoder(err) => Some(err),
+            SeekError::CustomDecoder(err) => Some(err),
+            SeekError::Io(err) => Some(err),
+            SeekError::NotPlaying | SeekError::TimedOut | SeekError::Superseded => None,
+        }
+    }
 }
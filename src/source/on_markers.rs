@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use crate::decoder::Marker;
+use crate::source::SeekError;
+use crate::{Sample, Source};
+
+/// Internal function that builds an `OnMarkers` object.
+pub fn on_markers<I, F>(input: I, mut markers: Vec<Marker>, callback: F) -> OnMarkers<I, F>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    markers.sort_by_key(|marker| marker.position);
+    OnMarkers {
+        input,
+        markers,
+        callback,
+        next_marker: 0,
+        position: 0,
+        sample_in_frame: 0,
+    }
+}
+
+/// Calls a function with each marker when the source reaches its position.
+#[derive(Clone, Debug)]
+pub struct OnMarkers<I, F> {
+    input: I,
+    // Markers sorted by position.
+    markers: Vec<Marker>,
+    callback: F,
+    // Index of the first marker not reached yet.
+    next_marker: usize,
+    // Position of the current sample, in inter-channel samples.
+    position: u64,
+    // Index of the channel of the current sample.
+    sample_in_frame: u16,
+}
+
+impl<I, F> OnMarkers<I, F>
+where
+    I: Source,
+    I::Item: Sample,
+    F: FnMut(&Marker),
+{
+    /// Returns the markers, sorted by position.
+    #[inline]
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+}
+
+impl<I, F> Iterator for OnMarkers<I, F>
+where
+    I: Source,
+    I::Item: Sample,
+    F: FnMut(&Marker),
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        let sample = self.input.next()?;
+
+        if self.sample_in_frame == 0 {
+            while let Some(marker) = self.markers.get(self.next_marker) {
+                if marker.position > self.position {
+                    break;
+                }
+                (self.callback)(marker);
+                self.next_marker += 1;
+            }
+        }
+
+        self.sample_in_frame += 1;
+        if self.sample_in_frame >= self.input.channels() {
+            self.sample_in_frame = 0;
+            self.position += 1;
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I, F> ExactSizeIterator for OnMarkers<I, F>
+where
+    I: Source + ExactSizeIterator,
+    I::Item: Sample,
+    F: FnMut(&Marker),
+{
+}
+
+impl<I, F> Source for OnMarkers<I, F>
+where
+    I: Source,
+    I::Item: Sample,
+    F: FnMut(&Marker),
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    /// The markers between the current position and the target of the seek are skipped.
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.position = (pos.as_secs_f64() * self.input.sample_rate() as f64) as u64;
+        self.sample_in_frame = 0;
+        let position = self.position;
+        self.next_marker = self
+            .markers
+            .partition_point(|marker| marker.position < position);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::buffer::SamplesBuffer;
+    use crate::decoder::Marker;
+    use crate::source::Source;
+
+    fn marker(position: u64, label: &str) -> Marker {
+        Marker {
+            position,
+            label: label.to_owned(),
+        }
+    }
+
+    #[test]
+    fn calls_at_each_marker() {
+        // Stereo, 1 Hz audio buffer.
+        let inner = SamplesBuffer::new(2, 1, vec![1i16, 1, 2, 2, 3, 3, 4, 4]);
+        let markers = vec![
+            marker(2, "b"),
+            marker(0, "a"),
+            marker(2, "c"),
+            marker(9, "d"),
+        ];
+
+        let reached = RefCell::new(Vec::new());
+        let mut source = inner.on_markers(markers, |marker| {
+            reached.borrow_mut().push(marker.label.clone())
+        });
+
+        assert_eq!(source.next(), Some(1));
+        assert_eq!(*reached.borrow(), ["a"]);
+        assert_eq!(source.by_ref().take(3).count(), 3);
+        assert_eq!(*reached.borrow(), ["a"]);
+        // Both markers of the third frame are reached with its first sample.
+        assert_eq!(source.next(), Some(3));
+        assert_eq!(*reached.borrow(), ["a", "b", "c"]);
+        // Markers past the end are never reached.
+        assert_eq!(source.by_ref().count(), 3);
+        assert_eq!(*reached.borrow(), ["a", "b", "c"]);
+    }
+}
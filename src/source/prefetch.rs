@@ -0,0 +1,463 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::source::SeekError;
+use crate::{Sample, Source};
+
+/// Internal function that builds a `Prefetch` object.
+pub fn prefetch<I>(input: I, duration: Duration) -> Prefetch<I::Item>
+where
+    I: Source + Send + 'static,
+    I::Item: Sample + Send,
+{
+    let frame = FrameInfo::of(&input);
+    let total_duration = input.total_duration();
+
+    let capacity = (duration.as_secs_f64() * frame.sample_rate as f64 * frame.channels as f64)
+        .max(MIN_CAPACITY as f64) as usize;
+    // The worker wakes up several times per buffer length, so that it does not fall behind.
+    let period = (duration / 4)
+        .max(Duration::from_millis(1))
+        .min(Duration::from_millis(50));
+
+    let shared = Arc::new(Shared {
+        samples: Ring::new(capacity, I::Item::zero_value()),
+        frames: Ring::new(
+            (capacity / MIN_FRAME_LEN).max(MIN_CAPACITY / MIN_FRAME_LEN),
+            frame,
+        ),
+        finished: AtomicBool::new(false),
+        underruns: Arc::new(AtomicUsize::new(0)),
+    });
+    let (commands_tx, commands_rx) = mpsc::channel();
+
+    let worker_shared = shared.clone();
+    thread::Builder::new()
+        .name("rodio prefetch".to_owned())
+        .spawn(move || run_worker(input, worker_shared, commands_rx, period))
+        .expect("failed to spawn the prefetch thread");
+
+    Prefetch {
+        shared,
+        commands: commands_tx,
+        frame,
+        frame_left: frame.len,
+        total_duration,
+        silence_len: None,
+    }
+}
+
+/// Minimum number of samples in the buffer.
+const MIN_CAPACITY: usize = 1024;
+
+/// Number of samples decoded between two checks for commands, so that a seek does not wait for
+/// the buffer to be full.
+const COMMANDS_INTERVAL: usize = 256;
+
+/// Shortest frame that can fill the buffer without running out of room for the formats of the
+/// frames, in samples.
+const MIN_FRAME_LEN: usize = 64;
+
+/// Decodes a source on a separate thread ahead of time, so that reading its samples only copies
+/// them out of a buffer.
+///
+/// When the thread falls behind, silence is played until enough samples are available again.
+/// Each time that happens, the counter returned by
+/// [`underrun_counter`](Prefetch::underrun_counter) is incremented.
+pub struct Prefetch<S>
+where
+    S: Sample + Send,
+{
+    shared: Arc<Shared<S>>,
+    commands: mpsc::Sender<Command>,
+    // Format of the current frame.
+    frame: FrameInfo,
+    // Number of samples left in the current frame.
+    frame_left: Option<usize>,
+    total_duration: Option<Duration>,
+    // Number of samples of silence played since the buffer ran out, if it did.
+    silence_len: Option<usize>,
+}
+
+impl<S> Prefetch<S>
+where
+    S: Sample + Send,
+{
+    /// Returns a counter of the number of times the buffer ran out before the end of the source.
+    ///
+    /// The counter can be kept after the source has been handed over to a `Sink`.
+    #[inline]
+    pub fn underrun_counter(&self) -> Arc<AtomicUsize> {
+        self.shared.underruns.clone()
+    }
+
+    /// Returns the number of samples ready to be played.
+    #[inline]
+    pub fn buffered_len(&self) -> usize {
+        self.shared.samples.len()
+    }
+
+    /// Plays silence until the samples are available again.
+    fn underrun(&mut self) -> Option<S> {
+        let silence_len = match &mut self.silence_len {
+            Some(len) => len,
+            None => {
+                self.shared.underruns.fetch_add(1, Ordering::Relaxed);
+                self.silence_len.insert(0)
+            }
+        };
+        *silence_len += 1;
+        Some(S::zero_value())
+    }
+}
+
+impl<S> Iterator for Prefetch<S>
+where
+    S: Sample + Send,
+{
+    type Item = S;
+
+    fn next(&mut self) -> Option<S> {
+        // Silence is played in whole inter-channel samples, so that the channels stay in order.
+        if let Some(len) = self.silence_len {
+            if len % self.frame.channels as usize != 0 {
+                return self.underrun();
+            }
+        }
+
+        if self.frame_left == Some(0) {
+            // Once the worker has finished, all the frames have been sent already.
+            let finished = self.shared.finished.load(Ordering::Acquire);
+            match self.shared.frames.pop() {
+                Some(frame) => {
+                    self.frame = frame;
+                    self.frame_left = frame.len;
+                }
+                None if finished => return None,
+                None => return self.underrun(),
+            }
+            if self.frame_left == Some(0) {
+                return None;
+            }
+        }
+
+        let finished = self.shared.finished.load(Ordering::Acquire);
+        match self.shared.samples.pop() {
+            Some(sample) => {
+                self.silence_len = None;
+                if let Some(left) = &mut self.frame_left {
+                    *left -= 1;
+                }
+                // The format of the next frame is reported as soon as the current one ends, if
+                // it is already known.
+                if self.frame_left == Some(0) {
+                    if let Some(frame) = self.shared.frames.pop() {
+                        self.frame = frame;
+                        self.frame_left = frame.len;
+                    }
+                }
+                Some(sample)
+            }
+            None if finished => None,
+            None => self.underrun(),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.shared.samples.len(), None)
+    }
+}
+
+impl<S> Source for Prefetch<S>
+where
+    S: Sample + Send,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.frame_left
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.frame.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.frame.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    /// Waits for the thread to seek the source, then drops the samples decoded before.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        let disconnected = || SeekError::NotSupported {
+            underlying_source: std::any::type_name::<Self>(),
+        };
+        self.commands
+            .send(Command::Seek(pos, reply_tx))
+            .map_err(|_| disconnected())?;
+        let frame = reply_rx.recv().map_err(|_| disconnected())??;
+
+        self.frame = frame;
+        self.frame_left = frame.len;
+        self.silence_len = None;
+        Ok(())
+    }
+}
+
+/// State shared between a `Prefetch` and its thread.
+struct Shared<S> {
+    samples: Ring<S>,
+    // Format of the frames that start after the current one.
+    frames: Ring<FrameInfo>,
+    // True once the last sample of the source has been added to `samples`.
+    finished: AtomicBool,
+    underruns: Arc<AtomicUsize>,
+}
+
+/// Format of a frame of the source.
+#[derive(Clone, Copy, Debug)]
+struct FrameInfo {
+    channels: u16,
+    sample_rate: u32,
+    len: Option<usize>,
+}
+
+impl FrameInfo {
+    fn of<I>(source: &I) -> FrameInfo
+    where
+        I: Source,
+        I::Item: Sample,
+    {
+        FrameInfo {
+            channels: source.channels(),
+            sample_rate: source.sample_rate(),
+            len: source.current_frame_len(),
+        }
+    }
+}
+
+enum Command {
+    /// Seeks the source, then replies with the format of its new current frame.
+    Seek(Duration, SyncSender<Result<FrameInfo, SeekError>>),
+}
+
+/// Decodes the source into the buffer until the `Prefetch` is dropped.
+fn run_worker<I>(
+    mut input: I,
+    shared: Arc<Shared<I::Item>>,
+    commands: Receiver<Command>,
+    period: Duration,
+) where
+    I: Source,
+    I::Item: Sample + Send,
+{
+    let mut frame_left = input.current_frame_len();
+    let mut finished = false;
+    loop {
+        let mut pending = None;
+        let mut decoded = 0;
+        while !finished && !shared.samples.is_full() {
+            if decoded % COMMANDS_INTERVAL == 0 {
+                match commands.try_recv() {
+                    Ok(command) => {
+                        pending = Some(command);
+                        break;
+                    }
+                    Err(TryRecvError::Empty) => (),
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            decoded += 1;
+
+            if frame_left == Some(0) {
+                if !shared.frames.push(FrameInfo::of(&input)) {
+                    break;
+                }
+                frame_left = input.current_frame_len();
+            }
+            match input.next() {
+                Some(sample) => {
+                    shared.samples.push(sample);
+                    if let Some(left) = &mut frame_left {
+                        *left = left.saturating_sub(1);
+                    }
+                }
+                None => {
+                    finished = true;
+                    shared.finished.store(true, Ordering::Release);
+                }
+            }
+        }
+
+        // Once the source has ended, only a seek can bring more samples.
+        let command = match pending {
+            Some(command) => Ok(command),
+            None if finished => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            None => commands.recv_timeout(period),
+        };
+        match command {
+            Ok(Command::Seek(pos, reply)) => {
+                let result = input.try_seek(pos).map(|()| {
+                    // The `Prefetch` waits for the reply, so it does not read the buffers.
+                    shared.samples.clear();
+                    shared.frames.clear();
+                    finished = false;
+                    shared.finished.store(false, Ordering::Release);
+                    frame_left = input.current_frame_len();
+                    FrameInfo::of(&input)
+                });
+                let _ = reply.send(result);
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// A lock-free queue of fixed capacity with a single producer and a single consumer.
+struct Ring<T> {
+    slots: Box<[UnsafeCell<T>]>,
+    // Number of values ever popped, only written by the consumer.
+    read: AtomicUsize,
+    // Number of values ever pushed, only written by the producer.
+    write: AtomicUsize,
+}
+
+// Each slot is only accessed by one side at a time, as guarded by `read` and `write`.
+unsafe impl<T> Sync for Ring<T> where T: Send {}
+
+impl<T> Ring<T>
+where
+    T: Copy,
+{
+    fn new(capacity: usize, value: T) -> Ring<T> {
+        Ring {
+            slots: (0..capacity).map(|_| UnsafeCell::new(value)).collect(),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        let write = self.write.load(Ordering::Acquire);
+        write.wrapping_sub(self.read.load(Ordering::Acquire))
+    }
+
+    #[inline]
+    fn is_full(&self) -> bool {
+        self.len() == self.slots.len()
+    }
+
+    /// Adds a value, from the producer. Returns false if the queue is full.
+    #[inline]
+    fn push(&self, value: T) -> bool {
+        let write = self.write.load(Ordering::Relaxed);
+        if write.wrapping_sub(self.read.load(Ordering::Acquire)) == self.slots.len() {
+            return false;
+        }
+        unsafe { *self.slots[write % self.slots.len()].get() = value };
+        self.write.store(write.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes the oldest value, from the consumer.
+    #[inline]
+    fn pop(&self) -> Option<T> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.write.load(Ordering::Acquire) {
+            return None;
+        }
+        let value = unsafe { *self.slots[read % self.slots.len()].get() };
+        self.read.store(read.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Removes all the values. Only allowed while the consumer is not using the queue.
+    #[inline]
+    fn clear(&self) {
+        let write = self.write.load(Ordering::Relaxed);
+        self.read.store(write, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::buffer::SamplesBuffer;
+    use crate::source::{SeekError, SineWave, Source};
+
+    /// A sine wave that takes a while to compute.
+    struct SlowSine(SineWave);
+
+    impl Iterator for SlowSine {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            thread::sleep(Duration::from_micros(100));
+            self.0.next()
+        }
+    }
+
+    impl Source for SlowSine {
+        fn current_frame_len(&self) -> Option<usize> {
+            self.0.current_frame_len()
+        }
+
+        fn channels(&self) -> u16 {
+            self.0.channels()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.0.sample_rate()
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            self.0.total_duration()
+        }
+
+        fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+            self.0.try_seek(pos)
+        }
+    }
+
+    #[test]
+    fn keeps_samples() {
+        let samples: Vec<i16> = (0..3000).map(|i| i as i16).collect();
+        let mut source =
+            SamplesBuffer::new(2, 1000, samples.clone()).prefetch(Duration::from_secs(10));
+        assert_eq!((source.channels(), source.sample_rate()), (2, 1000));
+
+        // The buffer is larger than the source, so it ends up holding all of it.
+        while source.buffered_len() < 3000 {
+            thread::yield_now();
+        }
+        let counter = source.underrun_counter();
+        assert_eq!(source.by_ref().collect::<Vec<i16>>(), samples);
+        assert_eq!(source.next(), None);
+        assert_eq!(counter.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn seeks_while_filling() {
+        // Filling the buffer takes several seconds.
+        let mut source = SlowSine(SineWave::new(440)).prefetch(Duration::from_secs(1));
+        let start = Instant::now();
+        source.try_seek(Duration::from_secs(1)).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,458 @@
+use std::time::Duration;
+
+use crate::source::SeekError;
+use crate::Source;
+
+/// Delays of the comb filters, in samples at 44100 Hz.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+
+/// Delays of the all-pass filters, in samples at 44100 Hz.
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+
+/// Difference between the delays of the filters of two adjacent channels, in samples at 44100 Hz.
+const STEREO_SPREAD: usize = 23;
+
+const TUNING_RATE: f32 = 44100.0;
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_WET: f32 = 3.0;
+const SCALE_DAMPING: f32 = 0.4;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// Level below which the tail of the reverb is considered silent.
+const SILENCE: f32 = 1e-4;
+
+/// Internal function that builds a `Reverb` object.
+pub fn reverb<I>(input: I, settings: ReverbSettings) -> Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    let channels = input.channels();
+    let sample_rate = input.sample_rate();
+    Reverb {
+        input,
+        settings: settings.clamped(),
+        channels,
+        sample_rate,
+        frame: Vec::with_capacity(channels as usize),
+        frame_pos: 0,
+        banks: build_banks(channels, sample_rate),
+        outputs: Vec::with_capacity(channels as usize),
+        ended: false,
+        silent_frames: 0,
+    }
+}
+
+/// Parameters of a [`Reverb`].
+///
+/// The room size, damping and width are clamped between 0 and 1 when given to the reverb.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReverbSettings {
+    /// Size of the room, from 0 to 1. The larger the room, the longer the reverb lasts.
+    pub room_size: f32,
+    /// How much the high frequencies are absorbed, from 0 to 1.
+    pub damping: f32,
+    /// Level of the reverberated sound, from 0 to 1.
+    pub wet: f32,
+    /// Gain of the original sound.
+    pub dry: f32,
+    /// How much the reverberated sound of each channel differs from the others, from 0 to 1.
+    pub width: f32,
+}
+
+impl Default for ReverbSettings {
+    #[inline]
+    fn default() -> ReverbSettings {
+        ReverbSettings {
+            room_size: 0.5,
+            damping: 0.5,
+            wet: 1.0 / SCALE_WET,
+            dry: 1.0,
+            width: 1.0,
+        }
+    }
+}
+
+impl ReverbSettings {
+    /// Keeps the parameters that go from 0 to 1 in that range, where the reverb stays stable.
+    #[inline]
+    fn clamped(self) -> ReverbSettings {
+        ReverbSettings {
+            room_size: self.room_size.clamp(0.0, 1.0),
+            damping: self.damping.clamp(0.0, 1.0),
+            width: self.width.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+}
+
+/// Freeverb reverb: each channel goes through eight comb filters in parallel then four all-pass
+/// filters in series, with slightly different delays for each channel.
+///
+/// The input of the filters is the mix of all the channels. Once the source ends, the tail of the
+/// reverb plays until it dies out.
+#[derive(Clone, Debug)]
+pub struct Reverb<I> {
+    input: I,
+    settings: ReverbSettings,
+    // Format of the current inter-channel frame, for which the filters have been built.
+    channels: u16,
+    sample_rate: u32,
+    // Samples of the current inter-channel frame, with the reverb applied.
+    frame: Vec<f32>,
+    frame_pos: usize,
+    // Filters of each channel.
+    banks: Vec<Bank>,
+    // Output of the filters of each channel for the current frame.
+    outputs: Vec<f32>,
+    // True once the source has ended and the tail is playing.
+    ended: bool,
+    // Number of inter-channel samples of the tail below `SILENCE`.
+    silent_frames: usize,
+}
+
+impl<I> Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    /// Returns the parameters of the reverb.
+    #[inline]
+    pub fn settings(&self) -> ReverbSettings {
+        self.settings
+    }
+
+    /// Changes the parameters of the reverb.
+    #[inline]
+    pub fn set_settings(&mut self, settings: ReverbSettings) {
+        self.settings = settings.clamped();
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Reads the next inter-channel frame and applies the reverb to it. Returns false once the
+    /// tail has died out.
+    fn next_frame(&mut self) -> bool {
+        self.frame.clear();
+        self.frame_pos = 0;
+
+        if !self.ended {
+            if !self.same_format() {
+                self.channels = self.input.channels();
+                self.sample_rate = self.input.sample_rate();
+                self.banks = build_banks(self.channels, self.sample_rate);
+            }
+            self.frame
+                .extend(self.input.by_ref().take(self.channels as usize));
+            if self.frame.is_empty() {
+                self.ended = true;
+                self.silent_frames = 0;
+            }
+        }
+        let longest_delay = self
+            .banks
+            .iter()
+            .map(Bank::longest_delay)
+            .max()
+            .unwrap_or(0);
+        if self.ended && (self.channels == 0 || self.silent_frames > longest_delay) {
+            return false;
+        }
+        // A truncated last frame is completed with silence, so that the tail keeps its channels
+        // in order.
+        self.frame.resize(self.channels as usize, 0.0);
+
+        let ReverbSettings {
+            room_size,
+            damping,
+            wet,
+            dry,
+            width,
+        } = self.settings;
+        let feedback = room_size * SCALE_ROOM + OFFSET_ROOM;
+        let damping = damping * SCALE_DAMPING;
+        let wet1 = wet * SCALE_WET * (width / 2.0 + 0.5);
+        let wet2 = wet * SCALE_WET * ((1.0 - width) / 2.0);
+
+        let input = self.frame.iter().sum::<f32>() * 2.0 / self.channels as f32 * FIXED_GAIN;
+        self.outputs.clear();
+        self.outputs.extend(
+            self.banks
+                .iter_mut()
+                .map(|bank| bank.process(input, feedback, damping)),
+        );
+
+        let outputs = &self.outputs;
+        let mut silent = true;
+        for (channel, sample) in self.frame.iter_mut().enumerate() {
+            // Each channel takes a bit of the reverb of the next one, less so the wider the sound.
+            let other = outputs[(channel + 1) % outputs.len()];
+            *sample = *sample * dry + outputs[channel] * wet1 + other * wet2;
+            silent &= outputs[channel].abs() < SILENCE;
+        }
+        if silent {
+            self.silent_frames += 1;
+        } else {
+            self.silent_frames = 0;
+        }
+        true
+    }
+
+    /// Returns true if the samples that follow the current frame have the same format.
+    #[inline]
+    fn same_format(&self) -> bool {
+        self.input.channels() == self.channels && self.input.sample_rate() == self.sample_rate
+    }
+}
+
+fn build_banks(channels: u16, sample_rate: u32) -> Vec<Bank> {
+    let scale =
+        |tuning: usize| (tuning as f32 * sample_rate as f32 / TUNING_RATE).max(1.0) as usize;
+    (0..channels as usize)
+        .map(|channel| {
+            let spread = channel * STEREO_SPREAD;
+            Bank {
+                combs: COMB_TUNINGS
+                    .iter()
+                    .map(|&tuning| Comb::new(scale(tuning + spread)))
+                    .collect(),
+                allpasses: ALLPASS_TUNINGS
+                    .iter()
+                    .map(|&tuning| Allpass::new(scale(tuning + spread)))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// The filters of a channel.
+#[derive(Clone, Debug)]
+struct Bank {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Bank {
+    #[inline]
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let combined = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum();
+        self.allpasses
+            .iter_mut()
+            .fold(combined, |sample, allpass| allpass.process(sample))
+    }
+
+    /// Returns the length of the longest delay, in inter-channel samples.
+    #[inline]
+    fn longest_delay(&self) -> usize {
+        self.combs
+            .iter()
+            .map(|comb| comb.buffer.len())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Comb filter with a low-pass filter in its feedback loop.
+#[derive(Clone, Debug)]
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    // Last output of the low-pass filter.
+    store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Comb {
+        Comb {
+            buffer: vec![0.0; len],
+            pos: 0,
+            store: 0.0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.pos];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// All-pass filter, which diffuses the echoes without colouring the sound.
+#[derive(Clone, Debug)]
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Allpass {
+        Allpass {
+            buffer: vec![0.0; len],
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = input + delayed * ALLPASS_FEEDBACK;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+impl<I> Iterator for Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.frame_pos == self.frame.len() && !self.next_frame() {
+            return None;
+        }
+        let sample = self.frame[self.frame_pos];
+        self.frame_pos += 1;
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.frame.len() - self.frame_pos;
+        (self.input.size_hint().0 + buffered, None)
+    }
+}
+
+impl<I> Source for Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        let buffered = self.frame.len() - self.frame_pos;
+        if self.ended {
+            None
+        } else if buffered == 0 {
+            self.input.current_frame_len()
+        } else if self.same_format() {
+            self.input.current_frame_len().map(|len| len + buffered)
+        } else {
+            Some(buffered)
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        if self.ended || self.frame_pos < self.frame.len() {
+            self.channels
+        } else {
+            self.input.channels()
+        }
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        if self.ended || self.frame_pos < self.frame.len() {
+            self.sample_rate
+        } else {
+            self.input.sample_rate()
+        }
+    }
+
+    /// The length of the tail is not known in advance.
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Seeks the source. The reverb of the sound played before the seek keeps ringing.
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.frame.clear();
+        self.frame_pos = 0;
+        self.ended = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::SamplesBuffer;
+    use crate::source::{ReverbSettings, Source};
+
+    #[test]
+    fn impulse_response() {
+        let mut samples = vec![0.0; 2000];
+        samples[0] = 1.0;
+        samples[1] = 1.0;
+        let output: Vec<f32> = SamplesBuffer::new(2, 44100, samples)
+            .reverb(ReverbSettings::default())
+            .collect();
+
+        // The tail goes on after the source, then stops, in whole inter-channel samples.
+        assert!(output.len() > 44100);
+        assert_eq!(output.len() % 2, 0);
+        assert!(output[output.len() - 2..].iter().all(|s| s.abs() < 1e-3));
+
+        // The dry sound is kept, and the first echo comes from the shortest comb filter.
+        assert_eq!(output[..2], [1.0, 1.0]);
+        assert!(output[2..2 * 1116].iter().all(|&s| s == 0.0));
+        assert!(output[2 * 1116..4 * 1200].iter().any(|&s| s != 0.0));
+
+        // The channels reverberate differently.
+        let left: f32 = output.iter().step_by(2).map(|s| s.abs()).sum();
+        let right: f32 = output.iter().skip(1).step_by(2).map(|s| s.abs()).sum();
+        assert!(left > 0.0 && right > 0.0 && left != right);
+    }
+
+    #[test]
+    fn clamps_settings() {
+        let settings = ReverbSettings {
+            room_size: 2.0,
+            damping: -1.0,
+            width: 1.5,
+            ..ReverbSettings::default()
+        };
+        let mut reverb = SamplesBuffer::new(1, 44100, vec![0.0f32; 10]).reverb(settings);
+        assert_eq!(
+            reverb.settings(),
+            ReverbSettings {
+                room_size: 1.0,
+                damping: 0.0,
+                width: 1.0,
+                ..settings
+            }
+        );
+
+        reverb.set_settings(ReverbSettings {
+            room_size: -0.5,
+            ..settings
+        });
+        assert_eq!(reverb.settings().room_size, 0.0);
+    }
+}
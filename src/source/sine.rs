@@ -1,6 +1,7 @@
 use std::f32::consts::PI;
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::Source;
 
 /// An infinite source that produces a sine.
@@ -55,4 +56,9 @@ impl Source for SineWave {
     fn total_duration(&self) -> Option<Duration> {
         None
     }
+
+    #[inline]
+    fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
+        Ok(())
+    }
 }
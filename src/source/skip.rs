@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 const NS_PER_SECOND: u128 = 1_000_000_000;
@@ -151,6 +152,12 @@ where
                 .unwrap_or_else(|| Duration::from_secs(0))
         })
     }
+
+    /// Seeks the source, the position being counted from the end of the skipped part.
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos + self.skipped_duration)
+    }
 }
 
 #[cfg(test)]
@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// Internal function that builds a `Speed` object.
@@ -97,4 +98,10 @@ where
             None
         }
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        // The inner source plays `factor` times faster, so the position is scaled accordingly.
+        self.input.try_seek(pos.mul_f32(self.factor))
+    }
 }
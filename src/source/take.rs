@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// Internal function that builds a `TakeDuration` object.
@@ -176,4 +177,14 @@ where
             None
         }
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.remaining_duration = self
+            .requested_duration
+            .checked_sub(pos)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        Ok(())
+    }
 }
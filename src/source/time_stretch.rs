@@ -0,0 +1,418 @@
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::source::SeekError;
+use crate::Source;
+
+/// Time between the starts of two consecutive segments of the output, in seconds. The segments
+/// are twice as long, so that each one overlaps half of the previous one.
+const HOP_TIME: f32 = 0.01;
+
+/// How far from its nominal position a segment of the input can be taken to line up with the
+/// previous one, in seconds. This covers a period of the lowest pitches of the human voice.
+const TOLERANCE_TIME: f32 = 0.005;
+
+/// Only one out of this number of inter-channel samples is used to compare the segments.
+const CORRELATION_STEP: usize = 4;
+
+/// Internal function that builds a `TimeStretch` object.
+///
+/// # Panic
+///
+/// Panics if `ratio` is not strictly positive.
+pub fn time_stretch<I>(input: I, ratio: f32) -> TimeStretch<I>
+where
+    I: Source<Item = f32>,
+{
+    assert!(
+        ratio > 0.0,
+        "the time stretch ratio must be strictly positive"
+    );
+    let mut stretch = TimeStretch {
+        controller: Arc::new(TimeStretchController {
+            ratio: AtomicU32::new(ratio.to_bits()),
+        }),
+        channels: input.channels(),
+        sample_rate: input.sample_rate(),
+        input,
+        hop: 0,
+        tolerance: 0,
+        window: Vec::new(),
+        buffer: Vec::new(),
+        input_end: InputEnd::None,
+        pos: 0.0,
+        natural: 0,
+        first: true,
+        overlap: Vec::new(),
+        out: Vec::new(),
+        out_pos: 0,
+        finished: false,
+    };
+    stretch.reset();
+    stretch.next_segment();
+    stretch
+}
+
+/// Changes the ratio of a [`TimeStretch`] from any thread.
+pub struct TimeStretchController {
+    ratio: AtomicU32,
+}
+
+impl TimeStretchController {
+    /// Returns the ratio, as last set.
+    #[inline]
+    pub fn ratio(&self) -> f32 {
+        f32::from_bits(self.ratio.load(Ordering::Relaxed))
+    }
+
+    /// Sets the ratio. It is applied from the next segment of the output, within about 10 ms.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `ratio` is not strictly positive.
+    #[inline]
+    pub fn set_ratio(&self, ratio: f32) {
+        assert!(
+            ratio > 0.0,
+            "the time stretch ratio must be strictly positive"
+        );
+        self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Plays a source faster or slower without changing its pitch.
+///
+/// This uses WSOLA: the output is made of overlapping segments of the input, each taken close to
+/// where the ratio puts it, at the offset that best lines up its waveform with the previous one.
+/// The ratio can be changed while playing with the [`TimeStretchController`] returned by
+/// [`controller`](TimeStretch::controller).
+pub struct TimeStretch<I> {
+    input: I,
+    controller: Arc<TimeStretchController>,
+    // Format of the input being stretched.
+    channels: u16,
+    sample_rate: u32,
+    // Number of inter-channel samples between two segments.
+    hop: usize,
+    // Largest offset of a segment from its nominal position, in inter-channel samples.
+    tolerance: usize,
+    // Hann window, as long as a segment.
+    window: Vec<f32>,
+    // Samples of the input that can still be part of a segment, starting with `hop` inter-channel
+    // samples of silence before the start of the input.
+    buffer: Vec<f32>,
+    input_end: InputEnd,
+    // Nominal position of the next segment in `buffer`, in inter-channel samples.
+    pos: f64,
+    // Position in `buffer` of the samples that follow the previous segment.
+    natural: usize,
+    // True if no segment has been taken since the last reset.
+    first: bool,
+    // Second half of the previous segment, windowed, to be added to the first half of the next.
+    overlap: Vec<f32>,
+    // Samples of the output ready to be played.
+    out: Vec<f32>,
+    out_pos: usize,
+    finished: bool,
+}
+
+/// Whether there are more samples of the current format in the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InputEnd {
+    None,
+    FormatChange,
+    End,
+}
+
+impl<I> TimeStretch<I>
+where
+    I: Source<Item = f32>,
+{
+    /// Returns the controller of the ratio, which can be sent to other threads.
+    #[inline]
+    pub fn controller(&self) -> Arc<TimeStretchController> {
+        self.controller.clone()
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Starts over from the current position of the input, in its current format.
+    fn reset(&mut self) {
+        self.channels = self.input.channels();
+        self.sample_rate = self.input.sample_rate();
+        self.hop = ((HOP_TIME * self.sample_rate as f32) as usize).max(1);
+        self.tolerance = (TOLERANCE_TIME * self.sample_rate as f32) as usize;
+
+        let len = 2 * self.hop;
+        self.window = (0..len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos())
+            .collect();
+
+        let channels = self.channels as usize;
+        self.buffer.clear();
+        self.buffer.resize(self.hop * channels, 0.0);
+        self.input_end = InputEnd::None;
+        self.pos = 0.0;
+        self.natural = 0;
+        self.first = true;
+        self.overlap.clear();
+        self.overlap.resize(self.hop * channels, 0.0);
+        self.out.clear();
+        self.out_pos = 0;
+        self.finished = false;
+    }
+
+    /// Number of inter-channel samples in `buffer`.
+    #[inline]
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels.max(1) as usize
+    }
+
+    /// Reads the input until `buffer` holds `frames` inter-channel samples or there are no more
+    /// samples of the current format.
+    fn fill(&mut self, frames: usize) {
+        let channels = self.channels as usize;
+        while self.input_end == InputEnd::None && self.buffered_frames() < frames {
+            if self.input.channels() != self.channels
+                || self.input.sample_rate() != self.sample_rate
+            {
+                self.input_end = InputEnd::FormatChange;
+                break;
+            }
+            let len = self.buffer.len();
+            self.buffer.extend(self.input.by_ref().take(channels));
+            if self.buffer.len() == len {
+                self.input_end = InputEnd::End;
+            } else {
+                // A truncated last frame is completed with silence.
+                self.buffer.resize(len + channels, 0.0);
+            }
+        }
+    }
+
+    /// Returns a sample of `buffer`, or silence past its end.
+    #[inline]
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        self.buffer
+            .get(frame * self.channels as usize + channel)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Returns the sum of the channels of an inter-channel sample of `buffer`.
+    #[inline]
+    fn mono(&self, frame: usize) -> f32 {
+        (0..self.channels as usize)
+            .map(|channel| self.sample(frame, channel))
+            .sum()
+    }
+
+    /// Returns the position around `pos` where a segment best continues the previous one.
+    fn best_start(&self, pos: usize) -> usize {
+        let len = self.window.len();
+        let natural: Vec<f32> = (0..len)
+            .step_by(CORRELATION_STEP)
+            .map(|i| self.mono(self.natural + i))
+            .collect();
+
+        let mut best = (pos, f32::MIN);
+        for start in pos.saturating_sub(self.tolerance)..=pos + self.tolerance {
+            let (correlation, energy) = natural
+                .iter()
+                .enumerate()
+                .map(|(i, &n)| (n, self.mono(start + i * CORRELATION_STEP)))
+                .fold((0.0, 0.0), |(c, e), (n, s)| (c + n * s, e + s * s));
+            let score = correlation / (energy + 1e-9f32).sqrt();
+            if score > best.1 {
+                best = (start, score);
+            }
+        }
+        best.0
+    }
+
+    /// Computes the next `hop` inter-channel samples of the output.
+    fn next_segment(&mut self) {
+        let channels = self.channels as usize;
+        let len = self.window.len();
+        loop {
+            if self.finished {
+                return;
+            }
+
+            let pos = self.pos.round() as usize;
+            self.fill((pos + self.tolerance).max(self.natural) + len);
+            if self.input_end != InputEnd::None && pos >= self.buffered_frames() {
+                if self.input_end == InputEnd::FormatChange {
+                    self.reset();
+                    continue;
+                }
+                self.finished = true;
+                self.out.clear();
+                self.out_pos = 0;
+                return;
+            }
+
+            let start = if self.first {
+                pos
+            } else {
+                self.best_start(pos)
+            };
+            self.out.clear();
+            self.out_pos = 0;
+            for i in 0..self.hop {
+                for channel in 0..channels {
+                    let index = i * channels + channel;
+                    let sample =
+                        self.overlap[index] + self.window[i] * self.sample(start + i, channel);
+                    self.out.push(sample);
+                    self.overlap[index] =
+                        self.window[self.hop + i] * self.sample(start + self.hop + i, channel);
+                }
+            }
+            self.natural = start + self.hop;
+            self.pos += self.hop as f64 * self.controller.ratio() as f64;
+
+            // Drops the samples that no segment can start from anymore.
+            let keep_from = self
+                .natural
+                .min((self.pos.round() as usize).saturating_sub(self.tolerance));
+            if keep_from > 2 * len {
+                self.buffer.drain(..keep_from * channels);
+                self.pos -= keep_from as f64;
+                self.natural -= keep_from;
+            }
+
+            // The first half of the first segment is the silence before the input.
+            if self.first {
+                self.first = false;
+                continue;
+            }
+            return;
+        }
+    }
+}
+
+impl<I> Iterator for TimeStretch<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = *self.out.get(self.out_pos)?;
+        self.out_pos += 1;
+        // The next segment is computed right away, so that its format can be reported.
+        if self.out_pos == self.out.len() {
+            self.next_segment();
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.out.len() - self.out_pos, None)
+    }
+}
+
+impl<I> Source for TimeStretch<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.input.current_frame_len().is_none() && self.input_end != InputEnd::FormatChange {
+            None
+        } else {
+            Some(self.out.len() - self.out_pos)
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input
+            .total_duration()
+            .map(|duration| duration.div_f32(self.controller.ratio()))
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.reset();
+        self.next_segment();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::source::{SineWave, Source};
+
+    /// Returns the number of times the samples go from negative to positive.
+    fn periods(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn keeps_pitch() {
+        for &ratio in &[0.5, 1.0, 2.0, 3.0] {
+            let source = SineWave::new(441).take_duration(Duration::from_secs(1));
+            let stretched: Vec<f32> = source.time_stretch(ratio).collect();
+
+            let expected_len = 48000.0 / ratio;
+            assert!((stretched.len() as f32 - expected_len).abs() < 1000.0);
+            let frequency = periods(&stretched) as f32 * 48000.0 / stretched.len() as f32;
+            assert!((frequency - 441.0).abs() < 10.0, "{} Hz", frequency);
+        }
+    }
+
+    #[test]
+    fn changes_ratio_while_playing() {
+        let mut source = SineWave::new(441)
+            .take_duration(Duration::from_secs(2))
+            .time_stretch(1.0);
+        let controller = source.controller();
+        assert_eq!(source.by_ref().take(24000).count(), 24000);
+
+        // The remaining 1.5 s of the input are played in 0.75 s.
+        controller.set_ratio(2.0);
+        assert_eq!(controller.ratio(), 2.0);
+        let samples: Vec<f32> = source.collect();
+        assert!((samples.len() as f32 - 36000.0).abs() < 1000.0);
+        let frequency = periods(&samples) as f32 * 48000.0 / samples.len() as f32;
+        assert!((frequency - 441.0).abs() < 10.0, "{} Hz", frequency);
+    }
+}
@@ -1,7 +1,10 @@
 use std::cmp;
 use std::time::Duration;
 
-use crate::conversions::{ChannelCountConverter, DataConverter, SampleRateConverter};
+use crate::conversions::{
+    ChannelCountConverter, DataConverter, ResampleQuality, SampleRateConverter,
+};
+use crate::source::SeekError;
 use crate::{Sample, Source};
 
 /// An iterator that reads from a `Source` and converts the samples to a specific rate and
@@ -19,6 +22,7 @@ where
     inner: Option<DataConverter<ChannelCountConverter<SampleRateConverter<Take<I>>>, D>>,
     target_channels: u16,
     target_sample_rate: u32,
+    quality: ResampleQuality,
     total_duration: Option<Duration>,
 }
 
@@ -33,14 +37,32 @@ where
         input: I,
         target_channels: u16,
         target_sample_rate: u32,
+    ) -> UniformSourceIterator<I, D> {
+        UniformSourceIterator::with_quality(
+            input,
+            target_channels,
+            target_sample_rate,
+            ResampleQuality::Linear,
+        )
+    }
+
+    /// Builds an iterator that converts the sample rate with the given algorithm.
+    #[inline]
+    pub fn with_quality(
+        input: I,
+        target_channels: u16,
+        target_sample_rate: u32,
+        quality: ResampleQuality,
     ) -> UniformSourceIterator<I, D> {
         let total_duration = input.total_duration();
-        let input = UniformSourceIterator::bootstrap(input, target_channels, target_sample_rate);
+        let input =
+            UniformSourceIterator::bootstrap(input, target_channels, target_sample_rate, quality);
 
         UniformSourceIterator {
             inner: Some(input),
             target_channels,
             target_sample_rate,
+            quality,
             total_duration,
         }
     }
@@ -50,22 +72,23 @@ where
         input: I,
         target_channels: u16,
         target_sample_rate: u32,
+        quality: ResampleQuality,
     ) -> DataConverter<ChannelCountConverter<SampleRateConverter<Take<I>>>, D> {
-        // Limit the frame length to something reasonable
-        let frame_len = input.current_frame_len().map(|x| x.min(32768));
-
         let from_channels = input.channels();
         let from_sample_rate = input.sample_rate();
 
         let input = Take {
+            n: frame_len(&input),
             iter: input,
-            n: frame_len,
+            channels: from_channels,
+            sample_rate: from_sample_rate,
         };
         let input = SampleRateConverter::new(
             input,
             cpal::SampleRate(from_sample_rate),
             cpal::SampleRate(target_sample_rate),
             from_channels,
+            quality,
         );
         let input = ChannelCountConverter::new(input, from_channels, target_channels);
 
@@ -96,8 +119,12 @@ where
             .into_inner()
             .iter;
 
-        let mut input =
-            UniformSourceIterator::bootstrap(input, self.target_channels, self.target_sample_rate);
+        let mut input = UniformSourceIterator::bootstrap(
+            input,
+            self.target_channels,
+            self.target_sample_rate,
+            self.quality,
+        );
 
         let value = input.next();
         self.inner = Some(input);
@@ -135,22 +162,55 @@ where
     fn total_duration(&self) -> Option<Duration> {
         self.total_duration
     }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if let Some(input) = self.inner.as_mut() {
+            input.inner_mut().inner_mut().inner_mut().iter.try_seek(pos)
+        } else {
+            Ok(())
+        }
+    }
 }
 
+/// Returns the length of the current frame of a source, limited to something reasonable.
+#[inline]
+fn frame_len<I>(input: &I) -> Option<usize>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input.current_frame_len().map(|x| x.min(32768))
+}
+
+/// Reads a source until its channels count or sample rate changes.
 #[derive(Clone, Debug)]
 struct Take<I> {
     iter: I,
+    // Number of samples left in the current frame.
     n: Option<usize>,
+    channels: u16,
+    sample_rate: u32,
 }
 
 impl<I> Iterator for Take<I>
 where
-    I: Iterator,
+    I: Source,
+    I::Item: Sample,
 {
     type Item = <I as Iterator>::Item;
 
     #[inline]
     fn next(&mut self) -> Option<<I as Iterator>::Item> {
+        // The next frame is read as well if it has the same format, so that the sample rate
+        // converter does not start over at each frame.
+        if self.n == Some(0)
+            && self.iter.channels() == self.channels
+            && self.iter.sample_rate() == self.sample_rate
+        {
+            self.n = frame_len(&self.iter);
+        }
+
         if let Some(n) = &mut self.n {
             if *n != 0 {
                 *n -= 1;
@@ -167,19 +227,33 @@ where
     fn size_hint(&self) -> (usize, Option<usize>) {
         if let Some(n) = self.n {
             let (lower, upper) = self.iter.size_hint();
-
-            let lower = cmp::min(lower, n);
-
-            let upper = match upper {
-                Some(x) if x < n => Some(x),
-                _ => Some(n),
-            };
-
-            (lower, upper)
+            (cmp::min(lower, n), upper)
         } else {
             self.iter.size_hint()
         }
     }
 }
 
-impl<I> ExactSizeIterator for Take<I> where I: ExactSizeIterator {}
+#[cfg(test)]
+mod tests {
+    use crate::buffer::SamplesBuffer;
+    use crate::conversions::sine;
+    use crate::source::{from_iter, UniformSourceIterator};
+    use crate::ResampleQuality;
+
+    #[test]
+    fn resamples_across_frames() {
+        // Frames of 441 samples, which the resampler goes through without starting over.
+        let frames = (0..10)
+            .map(|i| SamplesBuffer::new(1, 44100, sine(1000.0, 44100, i * 441..(i + 1) * 441)));
+        let output: Vec<f32> =
+            UniformSourceIterator::with_quality(from_iter(frames), 1, 48000, ResampleQuality::High)
+                .collect();
+        assert_eq!(output.len(), 4800);
+
+        let expected = sine(1000.0, 48000, 0..4800);
+        for (out, exp) in output[100..4700].iter().zip(&expected[100..4700]) {
+            assert!((out - exp).abs() < 1e-3);
+        }
+    }
+}
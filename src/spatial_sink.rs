@@ -3,7 +3,7 @@ use std::fmt::Debug;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use crate::source::Spatial;
+use crate::source::{SeekError, Spatial};
 use crate::stream::{OutputStreamHandle, PlayError};
 use crate::{Sample, Sink, Source};
 
@@ -121,6 +121,14 @@ impl SpatialSink {
         self.sink.stop()
     }
 
+    /// Attempts to seek to a given position in the sound that is currently playing.
+    ///
+    /// See [`Sink::try_seek`] for details.
+    #[inline]
+    pub fn try_seek(&self, pos: Duration) -> Result<(), SeekError> {
+        self.sink.try_seek(pos)
+    }
+
     /// Destroys the sink without stopping the sounds that are still playing.
     #[inline]
     pub fn detach(self) {
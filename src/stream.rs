@@ -6,6 +6,7 @@ use crate::decoder;
 use crate::dynamic_mixer::{self, DynamicMixerController};
 use crate::sink::Sink;
 use crate::source::Source;
+use crate::ResampleQuality;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::Sample;
 
@@ -57,6 +58,17 @@ impl OutputStreamHandle {
         Ok(())
     }
 
+    /// Sets the algorithm that converts the sample rate of the sounds played and the sinks created
+    /// from now on, when it differs from the one of the device. Defaults to
+    /// `ResampleQuality::Linear`.
+    ///
+    /// This has no effect once the `OutputStream` has been dropped.
+    pub fn set_resample_quality(&self, quality: ResampleQuality) {
+        if let Some(mixer) = self.mixer.upgrade() {
+            mixer.set_resample_quality(quality);
+        }
+    }
+
     /// Plays a sound once. Returns a `Sink` that can be used to control the sound.
     pub fn play_once<R>(&self, input: R) -> Result<Sink, PlayError>
     where
@@ -0,0 +1,116 @@
+#![cfg(feature = "aiff")]
+
+mod common;
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use rodio::decoder::DecoderError;
+use rodio::{Decoder, Source};
+
+use crate::common::aiff_chunk;
+
+/// 44100 as an 80 bits extended precision number.
+const SAMPLE_RATE_44100: [u8; 10] = [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0];
+
+/// Builds an AIFF file, or an AIFF-C file if a compression type is given.
+fn aiff(
+    channels: u16,
+    bits_per_sample: u16,
+    compression_type: Option<&[u8]>,
+    samples: &[u8],
+) -> Vec<u8> {
+    let sample_len = samples.len() / bits_per_sample.div_ceil(8) as usize;
+    let mut common = channels.to_be_bytes().to_vec();
+    common.extend_from_slice(&(sample_len as u32 / channels as u32).to_be_bytes());
+    common.extend_from_slice(&bits_per_sample.to_be_bytes());
+    common.extend_from_slice(&SAMPLE_RATE_44100);
+    if let Some(compression_type) = compression_type {
+        common.extend_from_slice(compression_type);
+        // Empty compression name.
+        common.extend_from_slice(&[0, 0]);
+    }
+
+    let mut sound_data = vec![0; 8];
+    sound_data.extend_from_slice(samples);
+
+    let mut content = if compression_type.is_some() {
+        b"AIFC".to_vec()
+    } else {
+        b"AIFF".to_vec()
+    };
+    content.extend(aiff_chunk(b"NAME", b"Ramp"));
+    content.extend(aiff_chunk(b"COMM", &common));
+    content.extend(aiff_chunk(b"SSND", &sound_data));
+    aiff_chunk(b"FORM", &content)
+}
+
+#[test]
+fn big_endian_pcm() {
+    let samples = [0x40, 0x00, 0xc0, 0x00, 0x00, 0x01, 0x7f, 0xff];
+    let decoder = Decoder::new(Cursor::new(aiff(2, 16, None, &samples))).unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.sample_rate(), 44100);
+    assert_eq!(decoder.metadata().title(), Some("Ramp"));
+    let expected = [16384.0 / 32767.0, -0.5, 1.0 / 32767.0, 1.0];
+    assert_eq!(decoder.collect::<Vec<f32>>(), expected);
+}
+
+#[test]
+fn bit_depths() {
+    // 24 bits samples.
+    let samples = [0x80, 0x00, 0x00, 0x20, 0x00, 0x00];
+    let decoder = Decoder::new(Cursor::new(aiff(1, 24, None, &samples))).unwrap();
+    assert_eq!(decoder.collect::<Vec<f32>>(), [-1.0, 2097152.0 / 8388607.0]);
+
+    // 12 bits samples are stored left-justified in 2 bytes.
+    let samples = [0x7f, 0xf0, 0xc0, 0x00];
+    let decoder = Decoder::new(Cursor::new(aiff(1, 12, None, &samples))).unwrap();
+    assert_eq!(decoder.collect::<Vec<f32>>(), [32752.0 / 32767.0, -0.5]);
+}
+
+#[test]
+fn aifc_compression_types() {
+    let samples = [0x00, 0x40, 0x00, 0xc0];
+    let decoder = Decoder::new(Cursor::new(aiff(1, 16, Some(b"sowt"), &samples))).unwrap();
+    assert_eq!(decoder.collect::<Vec<f32>>(), [16384.0 / 32767.0, -0.5]);
+
+    let samples = [0.25f32.to_be_bytes(), (-0.75f32).to_be_bytes()].concat();
+    let decoder = Decoder::new(Cursor::new(aiff(1, 32, Some(b"fl32"), &samples))).unwrap();
+    assert_eq!(decoder.collect::<Vec<f32>>(), [0.25, -0.75]);
+
+    let samples = [0x40, 0x00];
+    let decoder = Decoder::new(Cursor::new(aiff(1, 16, Some(b"NONE"), &samples))).unwrap();
+    assert_eq!(decoder.collect::<Vec<f32>>(), [16384.0 / 32767.0]);
+}
+
+#[test]
+fn unsupported_compression_type() {
+    let data = aiff(1, 16, Some(b"ima4"), &[0; 34]);
+    assert!(matches!(
+        Decoder::new(Cursor::new(data)),
+        Err(DecoderError::UnsupportedEncoding(_))
+    ));
+}
+
+#[test]
+fn seek_and_duration() {
+    let samples: Vec<u8> = (0..44100u32)
+        .flat_map(|i| ((i % 32768) as i16).to_be_bytes().to_vec())
+        .collect();
+    let mut decoder = Decoder::new_aiff(Cursor::new(aiff(1, 16, None, &samples))).unwrap();
+    assert_eq!(decoder.total_duration(), Some(Duration::from_secs(1)));
+
+    decoder.try_seek(Duration::from_millis(500)).unwrap();
+    assert_eq!(decoder.next(), Some(22050.0 / 32767.0));
+    assert_eq!(decoder.count(), 22049);
+}
+
+#[test]
+fn truncated() {
+    let mut data = aiff(1, 16, None, &[0x40; 1000]);
+    data.truncate(data.len() - 100);
+    let mut decoder = Decoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.by_ref().count(), 450);
+    assert!(matches!(decoder.error(), Some(DecoderError::Truncated)));
+}
@@ -0,0 +1,85 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Cursor;
+
+use rodio::decoder::{DecoderBuilder, DecoderError, Format};
+use rodio::{Decoder, Source};
+
+#[cfg(feature = "wav")]
+#[test]
+fn disabled_formats_are_not_detected() {
+    let data = std::fs::read("tests/audacity16bit.wav").unwrap();
+    assert!(DecoderBuilder::new()
+        .with_format(Format::Flac, false)
+        .build(Cursor::new(data.clone()))
+        .is_ok());
+    assert!(matches!(
+        DecoderBuilder::new()
+            .with_format(Format::Wav, false)
+            .build(Cursor::new(data.clone())),
+        Err(DecoderError::UnrecognizedFormat)
+    ));
+
+    // Enabling a format again undoes disabling it.
+    assert!(DecoderBuilder::new()
+        .with_format(Format::Wav, false)
+        .with_format(Format::Wav, true)
+        .build(Cursor::new(data))
+        .is_ok());
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn wrong_hints_fall_back_to_other_formats() {
+    let data = std::fs::read("tests/audacity16bit.wav").unwrap();
+    let decoder = DecoderBuilder::new()
+        .with_extension("mp3")
+        .with_mime_type("audio/ogg; codecs=vorbis")
+        .build(Cursor::new(data))
+        .unwrap();
+    assert_eq!(decoder.sample_rate(), 44100);
+}
+
+#[cfg(feature = "mp3")]
+#[test]
+fn hinted_mp3_with_leading_junk() {
+    let mut data = vec![0x55; 1000];
+    data.extend(std::fs::read("examples/music.mp3").unwrap());
+    let decoder = DecoderBuilder::new()
+        .with_mime_type("audio/mpeg")
+        .build(Cursor::new(data))
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert!(decoder.count() > 0);
+}
+
+#[cfg(feature = "vorbis")]
+#[test]
+fn opens_paths() {
+    let decoder = Decoder::open("examples/music.ogg").unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.sample_rate(), 44100);
+
+    assert!(matches!(
+        DecoderBuilder::new()
+            .with_format(Format::Vorbis, false)
+            .open("examples/music.ogg"),
+        Err(DecoderError::UnrecognizedFormat)
+    ));
+    match Decoder::open("examples/missing.ogg") {
+        Err(err @ DecoderError::IoError(_)) => {
+            let source = std::error::Error::source(&err).unwrap();
+            let source = source.downcast_ref::<std::io::Error>().unwrap();
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        _ => panic!("opening a missing file should fail with an IO error"),
+    }
+}
+
+#[cfg(feature = "flac")]
+#[test]
+fn try_from_file() {
+    let file = File::open("examples/music.flac").unwrap();
+    let decoder = Decoder::try_from(file).unwrap();
+    assert_eq!(decoder.channels(), 2);
+}
@@ -0,0 +1,24 @@
+//! Helpers shared by the tests that build audio files by hand.
+
+// Each test file only uses some of the helpers.
+#![allow(dead_code)]
+
+/// Builds a RIFF chunk, as found in WAV files, padded to an even length.
+pub fn riff_chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+    chunk(id, &(content.len() as u32).to_le_bytes(), content)
+}
+
+/// Builds an IFF chunk, as found in AIFF files, padded to an even length.
+pub fn aiff_chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+    chunk(id, &(content.len() as u32).to_be_bytes(), content)
+}
+
+fn chunk(id: &[u8], len: &[u8], content: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(len);
+    chunk.extend_from_slice(content);
+    if content.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
@@ -0,0 +1,117 @@
+use std::io::{self, Cursor, SeekFrom};
+use std::time::Duration;
+
+use rodio::decoder::{register_format, CustomDecoder, CustomFormat, DecoderError, ReadSeek};
+use rodio::source::SeekError;
+use rodio::{Decoder, Source};
+
+/// A made-up format: the `RAW!` magic, the number of channels on one byte, then 8 bits samples.
+struct RawFormat;
+
+struct RawDecoder {
+    channels: u16,
+}
+
+const HEADER_LEN: u64 = 5;
+
+impl CustomFormat for RawFormat {
+    fn probe(&self, data: &mut dyn ReadSeek) -> io::Result<bool> {
+        let mut magic = [0; 4];
+        let is_raw = data.read_exact(&mut magic).is_ok() && &magic == b"RAW!";
+        data.seek(SeekFrom::Start(0))?;
+        Ok(is_raw)
+    }
+
+    fn open(&self, data: &mut dyn ReadSeek) -> Result<Box<dyn CustomDecoder>, DecoderError> {
+        let mut header = [0; HEADER_LEN as usize];
+        data.read_exact(&mut header)?;
+        Ok(Box::new(RawDecoder {
+            channels: header[4] as u16,
+        }))
+    }
+}
+
+impl CustomDecoder for RawDecoder {
+    fn decode(
+        &mut self,
+        data: &mut dyn ReadSeek,
+        buffer: &mut Vec<f32>,
+    ) -> Result<(), DecoderError> {
+        // Packets of 4 inter-channel samples.
+        let mut packet = vec![0; 4 * self.channels as usize];
+        let len = data.read(&mut packet)?;
+        buffer.extend(packet[..len].iter().map(|&s| s as f32));
+        Ok(())
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        10
+    }
+
+    fn seek(&mut self, data: &mut dyn ReadSeek, pos: Duration) -> Result<(), SeekError> {
+        let frame = (pos.as_secs_f64() * 10.0) as u64;
+        data.seek(SeekFrom::Start(HEADER_LEN + frame * self.channels as u64))
+            .map_err(SeekError::Io)?;
+        Ok(())
+    }
+
+    fn tags(&self) -> Vec<(String, String)> {
+        vec![("title".to_owned(), "Raw".to_owned())]
+    }
+}
+
+/// Claims any data, as a last resort.
+struct SilenceFormat;
+
+impl CustomFormat for SilenceFormat {
+    fn probe(&self, _data: &mut dyn ReadSeek) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    fn open(&self, _data: &mut dyn ReadSeek) -> Result<Box<dyn CustomDecoder>, DecoderError> {
+        Err(DecoderError::UnsupportedEncoding("silence".to_owned()))
+    }
+}
+
+fn raw(channels: u8, samples: impl Iterator<Item = u8>) -> Cursor<Vec<u8>> {
+    let mut data = b"RAW!".to_vec();
+    data.push(channels);
+    data.extend(samples);
+    Cursor::new(data)
+}
+
+#[test]
+fn custom_formats() {
+    register_format(RawFormat, 10);
+    register_format(SilenceFormat, -10);
+
+    let mut decoder = Decoder::new(raw(2, 0..20)).unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.sample_rate(), 10);
+    assert_eq!(decoder.current_frame_len(), Some(8));
+    assert_eq!(decoder.metadata().title(), Some("Raw"));
+    assert_eq!(decoder.next(), Some(0.0));
+
+    decoder.try_seek(Duration::from_millis(500)).unwrap();
+    let expected: Vec<f32> = (10..20).map(|s| s as f32).collect();
+    assert_eq!(decoder.collect::<Vec<f32>>(), expected);
+
+    let decoder = Decoder::new_looped(raw(1, 0..3)).unwrap();
+    let expected = [0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0];
+    assert_eq!(decoder.take(7).collect::<Vec<f32>>(), expected);
+
+    // Formats with a negative priority come after the formats of rodio.
+    #[cfg(feature = "wav")]
+    {
+        let data = std::fs::read("tests/audacity16bit.wav").unwrap();
+        assert!(Decoder::new(Cursor::new(data)).unwrap().metadata().title() != Some("Raw"));
+    }
+    assert!(matches!(
+        Decoder::new(Cursor::new(vec![0; 100])),
+        Err(DecoderError::UnsupportedEncoding(_))
+    ));
+}
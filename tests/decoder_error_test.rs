@@ -0,0 +1,118 @@
+use rodio::decoder::{DecoderBuilder, DecoderError};
+use rodio::Decoder;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+fn truncated(path: &str) -> Cursor<Vec<u8>> {
+    let mut data = std::fs::read(path).unwrap();
+    data.truncate(data.len() / 2);
+    Cursor::new(data)
+}
+
+#[test]
+fn unrecognized_format() {
+    let data = Cursor::new(vec![0u8; 4096]);
+    assert!(matches!(
+        Decoder::new(data),
+        Err(DecoderError::UnrecognizedFormat)
+    ));
+}
+
+#[test]
+fn unsupported_wav_encoding() {
+    let mut data = std::fs::read("tests/lmms16bit.wav").unwrap();
+    // Replace the PCM format tag by the one of MPEG Layer 3.
+    data[20..22].copy_from_slice(&0x0055u16.to_le_bytes());
+    assert!(matches!(
+        Decoder::new(Cursor::new(data)),
+        Err(DecoderError::UnsupportedEncoding(_))
+    ));
+}
+
+#[test]
+fn truncated_wav() {
+    let mut decoder = Decoder::new(truncated("tests/lmms16bit.wav")).unwrap();
+    assert!(decoder.by_ref().count() > 0);
+    assert!(matches!(decoder.error(), Some(DecoderError::Truncated)));
+    assert_eq!(decoder.next(), None);
+}
+
+#[test]
+fn error_callback() {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let mut decoder = Decoder::new(truncated("tests/audacity16bit_level5.flac")).unwrap();
+    let errors_clone = errors.clone();
+    decoder.set_error_callback(move |error| errors_clone.lock().unwrap().push(error));
+
+    assert!(decoder.count() > 0);
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], DecoderError::Truncated));
+}
+
+/// Returns the data of the file with a run of bytes in its middle overwritten.
+fn damaged(path: &str) -> Cursor<Vec<u8>> {
+    let mut data = std::fs::read(path).unwrap();
+    let middle = data.len() / 2;
+    for byte in &mut data[middle..middle + 2000] {
+        *byte = 0x55;
+    }
+    Cursor::new(data)
+}
+
+/// Checks that the damaged file stops early by default, and plays up to the end with a glitch
+/// when resynchronizing.
+fn assert_resyncs(path: &str) {
+    let total = Decoder::new(Cursor::new(std::fs::read(path).unwrap()))
+        .unwrap()
+        .count();
+
+    let mut decoder = Decoder::new(damaged(path)).unwrap();
+    assert!(decoder.by_ref().count() < total * 3 / 4);
+    assert!(matches!(
+        decoder.error(),
+        Some(DecoderError::DecodeError(_))
+    ));
+    assert_eq!(decoder.skipped_frames(), 0);
+
+    let mut decoder = DecoderBuilder::new()
+        .with_resync(true)
+        .build(damaged(path))
+        .unwrap();
+    let len = decoder.by_ref().count();
+    assert!(len > total * 9 / 10 && len < total, "{} of {}", len, total);
+    assert!(decoder.error().is_none());
+    assert!(decoder.skipped_frames() > 0);
+}
+
+#[test]
+fn resync_flac() {
+    assert_resyncs("tests/audacity16bit_level5.flac");
+}
+
+#[test]
+fn resync_vorbis() {
+    assert_resyncs("examples/music.ogg");
+}
+
+#[test]
+fn resync_mp3() {
+    let path = "examples/music.mp3";
+    let total = Decoder::new(Cursor::new(std::fs::read(path).unwrap()))
+        .unwrap()
+        .count();
+
+    // Damaged MP3 data is always skipped, but only counted when resynchronizing.
+    let mut decoder = Decoder::new(damaged(path)).unwrap();
+    assert!(decoder.by_ref().count() > total * 9 / 10);
+    assert_eq!(decoder.skipped_frames(), 0);
+
+    let mut decoder = DecoderBuilder::new()
+        .with_resync(true)
+        .build(damaged(path))
+        .unwrap();
+    let len = decoder.by_ref().count();
+    assert!(len > total * 9 / 10 && len < total, "{} of {}", len, total);
+    assert!(decoder.error().is_none());
+    assert!(decoder.skipped_frames() > 0);
+}
@@ -0,0 +1,36 @@
+use rodio::{Decoder, Source};
+use std::io::Cursor;
+
+/// Checks that the duration reported by the decoder matches the number of decoded samples.
+fn assert_duration_matches(data: Vec<u8>, name: &str) {
+    let decoder = Decoder::new(Cursor::new(data)).unwrap();
+    let reported = decoder.total_duration().expect(name).as_secs_f64();
+    let samples_per_sec = decoder.sample_rate() as f64 * decoder.channels() as f64;
+    let decoded = decoder.count() as f64 / samples_per_sec;
+
+    assert!(
+        (reported - decoded).abs() < 0.06,
+        "{}: reported {}s, decoded {}s",
+        name,
+        reported,
+        decoded
+    );
+}
+
+#[test]
+fn mp3_duration() {
+    let data = std::fs::read("examples/music.mp3").unwrap();
+    assert_duration_matches(data.clone(), "xing");
+
+    // Without the Xing header, the duration is found by reading all the frame headers.
+    let mut data = data;
+    let xing = data.windows(4).position(|w| w == b"Xing").unwrap();
+    data[xing..xing + 4].copy_from_slice(b"None");
+    assert_duration_matches(data, "frame scan");
+}
+
+#[test]
+fn vorbis_duration() {
+    let data = std::fs::read("examples/music.ogg").unwrap();
+    assert_duration_matches(data, "vorbis");
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "wav")]
+
+use rodio::buffer::SamplesBuffer;
+use rodio::encoder::{self, WavSampleFormat};
+use rodio::source::{SineWave, Source};
+use rodio::Decoder;
+use std::io::{Cursor, Seek, SeekFrom};
+use std::time::Duration;
+
+#[test]
+fn round_trip() {
+    let source = || {
+        SineWave::new(440)
+            .take_duration(Duration::from_millis(100))
+            .amplify(0.5)
+    };
+    let expected: Vec<f32> = source().collect();
+
+    for &(format, tolerance) in &[
+        (WavSampleFormat::Int16, 0.5 / 32767.0),
+        (WavSampleFormat::Int24, 0.5 / 8388607.0),
+        (WavSampleFormat::Float32, 0.0),
+    ] {
+        let mut file = Cursor::new(Vec::new());
+        encoder::write_wav(source(), &mut file, format).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        let decoder = Decoder::new(file).unwrap();
+        assert_eq!(decoder.channels(), 1);
+        assert_eq!(decoder.sample_rate(), 48000);
+        let actual: Vec<f32> = decoder.collect();
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() <= tolerance, "{:?}: {} != {}", format, a, e);
+        }
+    }
+}
+
+#[test]
+fn keeps_source_format() {
+    // Values spread over the whole range, extremes included.
+    let mut samples: Vec<i16> = (0..8818).map(|i| (i as i16).wrapping_mul(7919)).collect();
+    samples.extend_from_slice(&[i16::MIN, i16::MAX]);
+    let source = SamplesBuffer::new(2, 44100, samples.clone());
+
+    let mut file = Cursor::new(Vec::new());
+    encoder::write_wav(source, &mut file, WavSampleFormat::Int16).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+
+    let decoder = Decoder::new(file).unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.sample_rate(), 44100);
+    let actual: Vec<i16> = decoder.convert_samples().collect();
+    assert_eq!(actual, samples);
+}
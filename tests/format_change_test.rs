@@ -0,0 +1,103 @@
+use std::io::Cursor;
+
+use rodio::source::UniformSourceIterator;
+use rodio::{Decoder, Source};
+
+/// Returns the length of each run of samples sharing the same channels count and sample rate.
+fn format_runs<S>(mut source: S) -> Vec<(u16, u32, usize)>
+where
+    S: Source<Item = f32>,
+{
+    let mut runs: Vec<(u16, u32, usize)> = Vec::new();
+    loop {
+        let format = (source.channels(), source.sample_rate());
+        if source.next().is_none() {
+            return runs;
+        }
+        match runs.last_mut() {
+            Some(run) if (run.0, run.1) == format => run.2 += 1,
+            _ => runs.push((format.0, format.1, 1)),
+        }
+    }
+}
+
+/// Builds MPEG-1 layer III frames of 128 kbit/s that decode to silence.
+#[cfg(feature = "mp3")]
+fn silent_mp3_frames(count: usize, sample_rate: u32, mono: bool) -> Vec<u8> {
+    let sample_rate_index = match sample_rate {
+        44100 => 0,
+        48000 => 1,
+        _ => unreachable!(),
+    };
+    let mut frame = vec![0; 144 * 128_000 / sample_rate as usize];
+    frame[..4].copy_from_slice(&[
+        0xff,
+        0xfb,
+        0x90 | (sample_rate_index << 2),
+        if mono { 0xc0 } else { 0x00 },
+    ]);
+    frame.repeat(count)
+}
+
+#[cfg(feature = "mp3")]
+#[test]
+fn mp3_format_change() {
+    let mut data = silent_mp3_frames(20, 44100, false);
+    data.extend(silent_mp3_frames(20, 48000, true));
+
+    // minimp3 only accepts a frame when it is followed by a matching header, so the last frame
+    // before a change of sample rate is skipped.
+    let decoder = Decoder::new(Cursor::new(data.clone())).unwrap();
+    assert_eq!(
+        format_runs(decoder),
+        [(2, 44100, 19 * 1152 * 2), (1, 48000, 20 * 1152)]
+    );
+
+    // The second part is converted from mono at 48 kHz. Each frame is resampled on its own,
+    // which can add a sample per channel to each of them.
+    let decoder = Decoder::new(Cursor::new(data)).unwrap();
+    let converted: UniformSourceIterator<_, f32> = UniformSourceIterator::new(decoder, 2, 44100);
+    let expected = 19 * 1152 * 2 + 20 * 1152 * 44100 / 48000 * 2;
+    let len = converted.count();
+    assert!((len as i64 - expected as i64).abs() <= 20 * 2, "{}", len);
+}
+
+#[cfg(feature = "mp3")]
+#[test]
+fn mp3_format_change_prefetch() {
+    let mut data = silent_mp3_frames(20, 44100, false);
+    data.extend(silent_mp3_frames(20, 48000, true));
+
+    // Decoding on a separate thread keeps the changes. The buffer is larger than the sound, so
+    // it ends up holding all of it and there is no underrun.
+    let decoder = Decoder::new(Cursor::new(data))
+        .unwrap()
+        .prefetch(std::time::Duration::from_secs(10));
+    while decoder.buffered_len() < 19 * 1152 * 2 + 20 * 1152 {
+        std::thread::yield_now();
+    }
+    let underruns = decoder.underrun_counter();
+    assert_eq!(
+        format_runs(decoder),
+        [(2, 44100, 19 * 1152 * 2), (1, 48000, 20 * 1152)]
+    );
+    assert_eq!(underruns.load(std::sync::atomic::Ordering::Relaxed), 0);
+}
+
+#[cfg(feature = "vorbis")]
+#[test]
+fn chained_vorbis_streams() {
+    // A mono stream followed by a stereo one.
+    let mut data = std::fs::read("examples/beep3.ogg").unwrap();
+    data.extend(std::fs::read("examples/music.ogg").unwrap());
+
+    let decoder = Decoder::new(Cursor::new(data.clone())).unwrap();
+    assert_eq!(
+        format_runs(decoder),
+        [(1, 44100, 441000), (2, 44100, 3057408 * 2)]
+    );
+
+    let decoder = Decoder::new(Cursor::new(data)).unwrap();
+    let converted: UniformSourceIterator<_, f32> = UniformSourceIterator::new(decoder, 2, 44100);
+    assert_eq!(converted.count(), 441000 * 2 + 3057408 * 2);
+}
@@ -0,0 +1,113 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use rodio::{Decoder, Source};
+
+fn decode(data: Vec<u8>) -> Vec<f32> {
+    Decoder::new(Cursor::new(data)).unwrap().collect()
+}
+
+#[cfg(feature = "mp3")]
+#[test]
+fn mp3_encoder_delay_and_padding() {
+    // The file has 390 frames of 1152 samples, with 576 samples of encoder delay and 984 samples
+    // of padding written in its LAME tag.
+    let samples_per_channel = 390 * 1152 - 576 - 984;
+
+    let data = std::fs::read("examples/music.mp3").unwrap();
+    let mut decoder = Decoder::new(Cursor::new(data.clone())).unwrap();
+    assert_eq!(
+        decoder.total_duration(),
+        Some(Duration::from_micros(
+            samples_per_channel * 1_000_000 / 44100
+        ))
+    );
+    let samples: Vec<f32> = decoder.by_ref().collect();
+    assert_eq!(samples.len() as u64, samples_per_channel * 2);
+
+    // Seeking back to the start skips the delay again.
+    decoder.try_seek(Duration::from_secs(0)).unwrap();
+    assert_eq!(decoder.count(), samples.len());
+
+    // Without the Xing header, its frame decodes to silence and nothing is trimmed.
+    let mut data = data;
+    let xing = data.windows(4).position(|w| w == b"Xing").unwrap();
+    data[xing..xing + 4].copy_from_slice(b"None");
+    assert_eq!(decode(data).len(), 391 * 1152 * 2);
+}
+
+/// Changes the granule position of the audio pages of an Ogg stream.
+#[cfg(feature = "vorbis")]
+fn patch_granules(data: &mut [u8], mut patch: impl FnMut(u64) -> u64) {
+    let mut page = 0;
+    while page + 27 <= data.len() {
+        let segments = data[page + 26] as usize;
+        let len = 27
+            + segments
+            + data[page + 27..page + 27 + segments]
+                .iter()
+                .map(|&s| s as usize)
+                .sum::<usize>();
+
+        let mut granule = [0; 8];
+        granule.copy_from_slice(&data[page + 6..page + 14]);
+        let granule = u64::from_le_bytes(granule);
+        if granule != 0 && granule != u64::MAX {
+            data[page + 6..page + 14].copy_from_slice(&patch(granule).to_le_bytes());
+            data[page + 22..page + 26].copy_from_slice(&[0; 4]);
+            let crc = ogg_crc(&data[page..page + len]);
+            data[page + 22..page + 26].copy_from_slice(&crc.to_le_bytes());
+        }
+        page += len;
+    }
+}
+
+#[cfg(feature = "vorbis")]
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |mut crc, &byte| {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(feature = "vorbis")]
+#[test]
+fn vorbis_samples_before_first_granule() {
+    let data = std::fs::read("examples/music.ogg").unwrap();
+    let original = decode(data.clone());
+
+    // Lowering the granule position of the first audio page drops samples at the start.
+    let mut data = data;
+    let mut first = true;
+    patch_granules(&mut data, |granule| {
+        let patched = if first { granule - 1000 } else { granule };
+        first = false;
+        patched
+    });
+    assert_eq!(decode(data), &original[1000 * 2..]);
+}
+
+#[cfg(feature = "vorbis")]
+#[test]
+fn vorbis_stream_starting_after_zero() {
+    let data = std::fs::read("examples/music.ogg").unwrap();
+    let original = Decoder::new(Cursor::new(data.clone())).unwrap();
+    let duration = original.total_duration();
+    let len = original.count();
+
+    // A stream cut from a longer one starts at a later granule position.
+    let mut data = data;
+    patch_granules(&mut data, |granule| granule + 44100 * 10);
+    let mut decoder = Decoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.total_duration(), duration);
+
+    decoder.try_seek(Duration::from_secs(60)).unwrap();
+    assert_eq!(decoder.count(), len - 60 * 44100 * 2);
+}
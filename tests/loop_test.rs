@@ -0,0 +1,111 @@
+mod common;
+
+use std::io::Cursor;
+
+use rodio::{Decoder, Source};
+
+use crate::common::riff_chunk;
+
+/// Builds a mono 16 bits WAV file of `len` samples whose values are their position, with a
+/// `smpl` chunk holding one loop.
+fn wav_with_loop(len: u16, loop_start: u32, loop_last: u32) -> Vec<u8> {
+    let mut format = 1u16.to_le_bytes().to_vec();
+    format.extend_from_slice(&1u16.to_le_bytes());
+    format.extend_from_slice(&44100u32.to_le_bytes());
+    format.extend_from_slice(&(44100u32 * 2).to_le_bytes());
+    format.extend_from_slice(&2u16.to_le_bytes());
+    format.extend_from_slice(&16u16.to_le_bytes());
+
+    let mut sampler = vec![0; 28];
+    sampler.extend_from_slice(&1u32.to_le_bytes());
+    sampler.extend_from_slice(&0u32.to_le_bytes());
+    sampler.extend_from_slice(&[0; 8]);
+    sampler.extend_from_slice(&loop_start.to_le_bytes());
+    sampler.extend_from_slice(&loop_last.to_le_bytes());
+    sampler.extend_from_slice(&[0; 8]);
+
+    let samples: Vec<u8> = (0..len).flat_map(|i| i.to_le_bytes().to_vec()).collect();
+
+    let mut content = b"WAVE".to_vec();
+    content.extend(riff_chunk(b"fmt ", &format));
+    content.extend(riff_chunk(b"smpl", &sampler));
+    content.extend(riff_chunk(b"data", &samples));
+    riff_chunk(b"RIFF", &content)
+}
+
+fn positions(samples: impl Iterator<Item = f32>) -> Vec<u32> {
+    samples.map(|s| (s * 32768.0).round() as u32).collect()
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn wav_sampler_loop() {
+    let decoder = Decoder::new_looped(Cursor::new(wav_with_loop(100, 20, 59))).unwrap();
+    assert_eq!(decoder.loop_points(), (20, Some(60)));
+
+    let expected: Vec<u32> = (0..60).chain(20..60).chain(20..60).collect();
+    assert_eq!(positions(decoder.take(140)), expected);
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn explicit_loop_points() {
+    let mut decoder = Decoder::new_looped(Cursor::new(wav_with_loop(100, 20, 59))).unwrap();
+    decoder.set_loop_points(90, None);
+    let expected: Vec<u32> = (0..100).chain(90..100).chain(90..95).collect();
+    assert_eq!(positions(decoder.take(115)), expected);
+
+    // The loop can start at zero and end before the end of the file.
+    let mut decoder = Decoder::new_looped(Cursor::new(wav_with_loop(100, 20, 59))).unwrap();
+    decoder.set_loop_points(0, Some(10));
+    let expected: Vec<u32> = (0..10).chain(0..10).chain(0..5).collect();
+    assert_eq!(positions(decoder.take(25)), expected);
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn loop_points_out_of_range() {
+    // Neither the start nor the end of the loop can be reached, so the whole file loops.
+    let mut decoder = Decoder::new_looped(Cursor::new(wav_with_loop(100, 20, 59))).unwrap();
+    decoder.set_loop_points(u64::MAX - 1, Some(u64::MAX));
+    let expected: Vec<u32> = (0..100).chain(0..10).collect();
+    assert_eq!(positions(decoder.take(110)), expected);
+}
+
+#[cfg(feature = "wav")]
+#[test]
+#[should_panic]
+fn loop_ending_before_its_start() {
+    let mut decoder = Decoder::new_looped(Cursor::new(wav_with_loop(100, 20, 59))).unwrap();
+    decoder.set_loop_points(50, Some(50));
+}
+
+#[cfg(feature = "vorbis")]
+#[test]
+fn vorbis_loop_is_sample_accurate() {
+    let data = std::fs::read("examples/music.ogg").unwrap();
+    let samples: Vec<f32> = Decoder::new(Cursor::new(data.clone())).unwrap().collect();
+
+    let mut decoder = Decoder::new_looped(Cursor::new(data)).unwrap();
+    let (start, end) = (100_000, 500_000);
+    decoder.set_loop_points(start, Some(end));
+    let looped: Vec<f32> = decoder.take(end as usize * 2 + 1000).collect();
+    assert!(looped[..end as usize * 2] == samples[..end as usize * 2]);
+    assert!(looped[end as usize * 2..] == samples[start as usize * 2..start as usize * 2 + 1000]);
+}
+
+#[cfg(feature = "flac")]
+#[test]
+fn flac_loop_to_end() {
+    let data = std::fs::read("tests/audacity16bit_level5.flac").unwrap();
+    let decoder = Decoder::new(Cursor::new(data.clone())).unwrap();
+    let channels = decoder.channels() as usize;
+    let samples: Vec<f32> = decoder.collect();
+
+    let mut decoder = Decoder::new_looped(Cursor::new(data)).unwrap();
+    let start = 10_000;
+    decoder.set_loop_points(start, None);
+    let looped: Vec<f32> = decoder.take(samples.len() + 1000).collect();
+    assert!(looped[..samples.len()] == samples[..]);
+    assert!(looped[samples.len()..] == samples[start as usize * channels..][..1000]);
+}
@@ -0,0 +1,40 @@
+use rodio::Decoder;
+use std::fs::File;
+use std::io::BufReader;
+
+fn open(path: &str) -> Decoder<BufReader<File>> {
+    let file = File::open(path).unwrap();
+    Decoder::new(BufReader::new(file)).unwrap()
+}
+
+#[test]
+fn tags() {
+    for path in &[
+        "examples/music.flac",
+        "examples/music.mp3",
+        "examples/music.ogg",
+    ] {
+        let decoder = open(path);
+        let metadata = decoder.metadata();
+        assert_eq!(
+            metadata.title(),
+            Some("Corelli Trio Sonata 11, m1"),
+            "{}",
+            path
+        );
+        assert_eq!(metadata.artist(), Some("RP and E Goldstein"), "{}", path);
+    }
+}
+
+#[test]
+fn wav_info() {
+    let decoder = open("tests/lmms16bit.wav");
+    assert_eq!(
+        decoder.metadata().get("encoder"),
+        Some("LMMS (libsndfile-1.0.26pre5)")
+    );
+
+    // The decoder still starts at the beginning of the audio.
+    assert!(open("tests/lmms16bit.wav").any(|x| x != 0.0));
+    assert!(open("tests/audacity16bit.wav").metadata().is_empty());
+}
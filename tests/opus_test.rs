@@ -0,0 +1,132 @@
+#![cfg(feature = "opus")]
+
+use std::f32::consts::PI;
+use std::io::Cursor;
+use std::time::Duration;
+
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use opus_codec::{Application, Channels, Encoder};
+use rodio::decoder::DecoderError;
+use rodio::{Decoder, Source};
+
+/// Samples per channel of each encoded packet, 20 ms at 48 kHz.
+const FRAME_LEN: usize = 960;
+
+fn sine(len: usize) -> Vec<f32> {
+    (0..len)
+        .flat_map(|i| {
+            let value = 0.25 * (2.0 * PI * 440.0 * i as f32 / 48000.0).sin();
+            vec![value, value]
+        })
+        .collect()
+}
+
+/// Encodes stereo samples into an Ogg Opus stream with the given output gain, in dB in Q7.8
+/// format.
+fn encode(samples: &[f32], output_gain: i16) -> Vec<u8> {
+    let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
+    let pre_skip = encoder.get_lookahead().unwrap() as u16;
+    let mut writer = PacketWriter::new(Vec::new());
+
+    let mut head = b"OpusHead\x01\x02".to_vec();
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&48000u32.to_le_bytes());
+    head.extend_from_slice(&output_gain.to_le_bytes());
+    head.push(0);
+    writer
+        .write_packet(head.into(), 1, PacketWriteEndInfo::EndPage, 0)
+        .unwrap();
+
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&4u32.to_le_bytes());
+    tags.extend_from_slice(b"test");
+    tags.extend_from_slice(&1u32.to_le_bytes());
+    tags.extend_from_slice(&10u32.to_le_bytes());
+    tags.extend_from_slice(b"title=Sine");
+    writer
+        .write_packet(tags.into(), 1, PacketWriteEndInfo::EndPage, 0)
+        .unwrap();
+
+    // The encoder delay is compensated by the pre-skip, so the end of the input is only encoded
+    // once enough silence has been appended.
+    let mut input = samples.to_vec();
+    input.resize(
+        (samples.len() / 2 + pre_skip as usize + FRAME_LEN) / FRAME_LEN * FRAME_LEN * 2,
+        0.0,
+    );
+    let frames = input.len() / (FRAME_LEN * 2);
+    let end = pre_skip as u64 + samples.len() as u64 / 2;
+    for (i, frame) in input.chunks(FRAME_LEN * 2).enumerate() {
+        let packet = encoder.encode_vec_float(frame, 4000).unwrap();
+        let (info, granule) = if i + 1 == frames {
+            (PacketWriteEndInfo::EndStream, end)
+        } else {
+            (PacketWriteEndInfo::EndPage, ((i + 1) * FRAME_LEN) as u64)
+        };
+        writer
+            .write_packet(packet.into(), 1, info, granule)
+            .unwrap();
+    }
+
+    writer.into_inner()
+}
+
+#[test]
+fn decodes_at_48khz_without_pre_skip() {
+    let input = sine(48000);
+    let decoder = Decoder::new(Cursor::new(encode(&input, 0))).unwrap();
+    assert_eq!(decoder.sample_rate(), 48000);
+    assert_eq!(decoder.channels(), 2);
+    assert_eq!(decoder.total_duration(), Some(Duration::from_secs(1)));
+    assert_eq!(decoder.metadata().title(), Some("Sine"));
+
+    // Without the pre-skip, the decoded samples line up with the encoded ones.
+    let output: Vec<f32> = decoder.collect();
+    assert_eq!(output.len(), input.len());
+    let error = input[2000..94000]
+        .iter()
+        .zip(&output[2000..94000])
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f32::max);
+    assert!(error < 0.05, "error: {}", error);
+}
+
+#[test]
+fn applies_output_gain() {
+    let input = sine(48000);
+    let peak = |data: Vec<u8>| {
+        Decoder::new_opus(Cursor::new(data))
+            .unwrap()
+            .map(f32::abs)
+            .fold(0.0, f32::max)
+    };
+
+    // A gain of -6.02 dB halves the amplitude.
+    let ratio = peak(encode(&input, -1541)) / peak(encode(&input, 0));
+    assert!((ratio - 0.5).abs() < 0.01, "ratio: {}", ratio);
+}
+
+#[test]
+fn seeks() {
+    let input = sine(48000);
+    let mut decoder = Decoder::new(Cursor::new(encode(&input, 0))).unwrap();
+    decoder.try_seek(Duration::from_millis(750)).unwrap();
+
+    let output: Vec<f32> = decoder.collect();
+    assert_eq!(output.len(), input.len() / 4);
+    let error = input[72000..]
+        .iter()
+        .zip(&output)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f32::max);
+    assert!(error < 0.05, "error: {}", error);
+}
+
+#[test]
+fn rejects_other_formats() {
+    let data = std::fs::read("tests/audacity16bit.wav").unwrap();
+    match Decoder::new_opus(Cursor::new(data)) {
+        Err(DecoderError::UnrecognizedFormat) => (),
+        _ => panic!("wav data decoded as opus"),
+    }
+}
@@ -0,0 +1,221 @@
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn open(path: &str) -> Decoder<BufReader<File>> {
+    let file = File::open(path).unwrap();
+    Decoder::new(BufReader::new(file)).unwrap()
+}
+
+/// Checks that seeking to `pos` yields the same samples as decoding from the start.
+fn assert_seek_matches(path: &str, pos: Duration) {
+    let reference = open(path);
+    let offset = (pos.as_secs_f64() * reference.sample_rate() as f64) as usize
+        * reference.channels() as usize;
+    let expected: Vec<f32> = reference.skip(offset).take(1000).collect();
+
+    let mut decoder = open(path);
+    // Read a little first, so that the seek has to discard some state.
+    assert!(decoder.by_ref().take(5000).count() > 0);
+    decoder.try_seek(pos).unwrap();
+    let actual: Vec<f32> = decoder.take(1000).collect();
+
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn seek_wav() {
+    assert_seek_matches("tests/lmms16bit.wav", Duration::from_millis(1500));
+    assert_seek_matches("tests/audacity32bit.wav", Duration::from_secs(2));
+}
+
+#[test]
+fn seek_flac() {
+    assert_seek_matches(
+        "tests/audacity16bit_level5.flac",
+        Duration::from_millis(1500),
+    );
+    assert_seek_matches("tests/audacity24bit_level8.flac", Duration::from_secs(2));
+}
+
+#[test]
+fn seek_flac_with_seek_table() {
+    // Lists the frames that start at 0 s, 0.93 s and 1.86 s, followed by a placeholder.
+    let original = std::fs::read("tests/audacity16bit_level5.flac").unwrap();
+    let mut points = Vec::new();
+    for &(time, offset) in &[(0, 0), (40960, 40982), (81920, 90774), (u64::MAX, 0)] {
+        points.extend_from_slice(&u64::to_be_bytes(time));
+        points.extend_from_slice(&u64::to_be_bytes(offset));
+        points.extend_from_slice(&4096u16.to_be_bytes());
+    }
+    // The SEEKTABLE block goes right after the STREAMINFO block.
+    let mut data = original[..42].to_vec();
+    data.extend_from_slice(&[3, 0, 0, points.len() as u8]);
+    data.extend(points);
+    data.extend_from_slice(&original[42..]);
+
+    let mut decoder = Decoder::new(Cursor::new(data)).unwrap();
+    for &millis in &[1500, 500, 2000] {
+        let reference = open("tests/audacity16bit_level5.flac");
+        let offset =
+            millis * reference.sample_rate() as usize / 1000 * reference.channels() as usize;
+        let expected: Vec<f32> = reference.skip(offset).take(1000).collect();
+
+        decoder
+            .try_seek(Duration::from_millis(millis as u64))
+            .unwrap();
+        let actual: Vec<f32> = decoder.by_ref().take(1000).collect();
+        assert_eq!(actual, expected, "{} ms", millis);
+    }
+}
+
+#[test]
+fn seek_vorbis() {
+    assert_seek_matches("examples/music.ogg", Duration::from_secs(3));
+}
+
+#[test]
+fn seek_backwards() {
+    for path in &["tests/audacity16bit_level5.flac", "examples/music.ogg"] {
+        let start: Vec<f32> = open(path).take(1000).collect();
+
+        let mut decoder = open(path);
+        decoder.try_seek(Duration::from_secs(2)).unwrap();
+        assert!(decoder.by_ref().take(1000).count() > 0);
+        decoder.try_seek(Duration::from_secs(0)).unwrap();
+        let actual: Vec<f32> = decoder.take(1000).collect();
+
+        assert_eq!(actual, start, "{}", path);
+    }
+}
+
+/// Checks that seeking an MP3 stream to `pos` leaves about the expected number of samples.
+fn assert_mp3_seek_close(data: &[u8], pos: Duration) {
+    let reference = Decoder::new(Cursor::new(data.to_vec())).unwrap();
+    let samples_per_sec = reference.sample_rate() as f64 * reference.channels() as f64;
+    let expected = reference.count() as f64 - pos.as_secs_f64() * samples_per_sec;
+
+    let mut decoder = Decoder::new(Cursor::new(data.to_vec())).unwrap();
+    decoder.try_seek(pos).unwrap();
+    let remaining = decoder.count() as f64;
+
+    // MP3 seeking is approximate, but should be off by a few frames at most.
+    let error = (remaining - expected).abs() / samples_per_sec;
+    assert!(error < 0.1, "seeking to {:?} is off by {}s", pos, error);
+}
+
+#[test]
+fn seek_mp3() {
+    // The variable bitrate stream is sought with the table of contents of its Xing header.
+    let data = std::fs::read("examples/music.mp3").unwrap();
+    for secs in 1..10 {
+        assert_mp3_seek_close(&data, Duration::from_secs(secs));
+    }
+
+    // Without it, the average bitrate is used.
+    let mut data = data;
+    let xing = data.windows(4).position(|w| w == b"Xing").unwrap();
+    data[xing..xing + 4].copy_from_slice(b"None");
+    for secs in 1..10 {
+        assert_mp3_seek_close(&data, Duration::from_secs(secs));
+    }
+}
+
+/// Data whose seeks fail once `fail` is set.
+struct FailingSeeks {
+    data: BufReader<File>,
+    fail: Arc<AtomicBool>,
+}
+
+impl Read for FailingSeeks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl Seek for FailingSeeks {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(io::ErrorKind::Other.into());
+        }
+        self.data.seek(pos)
+    }
+}
+
+#[test]
+fn failed_seek_keeps_playing() {
+    let expected: Vec<f32> = open("examples/music.mp3").skip(5000).take(1000).collect();
+
+    let fail = Arc::new(AtomicBool::new(false));
+    let data = FailingSeeks {
+        data: BufReader::new(File::open("examples/music.mp3").unwrap()),
+        fail: fail.clone(),
+    };
+    let mut decoder = Decoder::new(data).unwrap();
+    assert_eq!(decoder.by_ref().take(5000).count(), 5000);
+
+    fail.store(true, Ordering::SeqCst);
+    assert!(decoder.try_seek(Duration::from_secs(5)).is_err());
+    let actual: Vec<f32> = decoder.take(1000).collect();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn seek_past_end() {
+    for path in &[
+        "tests/lmms16bit.wav",
+        "tests/audacity16bit_level5.flac",
+        "examples/music.ogg",
+        "examples/music.mp3",
+    ] {
+        let mut decoder = open(path);
+        decoder.try_seek(Duration::from_secs(3600)).unwrap();
+        assert_eq!(decoder.next(), None, "{}", path);
+    }
+}
+
+#[test]
+fn seek_through_filters() {
+    let mut source = open("tests/lmms16bit.wav")
+        .amplify(0.5)
+        .pausable(false)
+        .stoppable();
+    assert!(source.try_seek(Duration::from_secs(1)).is_ok());
+
+    let mut buffered = open("tests/lmms16bit.wav").buffered();
+    assert!(buffered.try_seek(Duration::from_secs(1)).is_err());
+}
+
+#[test]
+fn seek_skipped() {
+    let reference = open("tests/lmms16bit.wav");
+    let offset = reference.sample_rate() as usize * reference.channels() as usize * 3 / 2;
+    let expected: Vec<f32> = reference.skip(offset).take(1000).collect();
+
+    // The position is counted from the end of the skipped part.
+    let mut source = open("tests/lmms16bit.wav").skip_duration(Duration::from_millis(500));
+    source.try_seek(Duration::from_secs(1)).unwrap();
+    let actual: Vec<f32> = source.take(1000).collect();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn seek_prefetched() {
+    let reference = open("tests/lmms16bit.wav");
+    let offset = reference.sample_rate() as usize * reference.channels() as usize;
+    let expected: Vec<f32> = reference.skip(offset).take(1000).collect();
+
+    let mut source = open("tests/lmms16bit.wav").prefetch(Duration::from_millis(200));
+    assert!(source.by_ref().take(5000).count() > 0);
+    source.try_seek(Duration::from_secs(1)).unwrap();
+
+    // Reading before the samples are ready would play silence. The buffer holds more than that.
+    while source.buffered_len() < 1000 {
+        std::thread::yield_now();
+    }
+    let actual: Vec<f32> = source.take(1000).collect();
+    assert_eq!(actual, expected);
+}
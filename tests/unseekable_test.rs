@@ -0,0 +1,79 @@
+use std::io::{self, Read};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{Decoder, Source};
+
+/// A reader that hands out the data in small pieces and cannot seek, like a pipe.
+struct Pipe {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Pipe {
+    fn open(path: &str) -> Pipe {
+        Pipe {
+            data: std::fs::read(path).unwrap(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(1000).min(self.data.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Checks that decoding from a pipe gives the same samples as decoding from the file.
+fn assert_same_samples(path: &str) {
+    let expected: Vec<f32> = Decoder::open(path).unwrap().collect();
+    let decoder = Decoder::from_read(Pipe::open(path)).unwrap();
+    assert!(decoder.error().is_none());
+    let samples: Vec<f32> = decoder.collect();
+    assert_eq!(samples.len(), expected.len());
+    assert!(samples == expected);
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn decodes_wav() {
+    assert_same_samples("tests/audacity16bit.wav");
+}
+
+#[cfg(feature = "flac")]
+#[test]
+fn decodes_flac() {
+    assert_same_samples("tests/audacity24bit_level5.flac");
+}
+
+#[cfg(feature = "vorbis")]
+#[test]
+fn decodes_vorbis_without_duration() {
+    assert_same_samples("examples/music.ogg");
+    let decoder = Decoder::from_read(Pipe::open("examples/music.ogg")).unwrap();
+    assert_eq!(decoder.total_duration(), None);
+}
+
+#[cfg(feature = "mp3")]
+#[test]
+fn decodes_mp3() {
+    assert_same_samples("examples/music.mp3");
+    // The duration comes from the Xing header, which is at the start.
+    let decoder = Decoder::from_read(Pipe::open("examples/music.mp3")).unwrap();
+    assert!(decoder.total_duration().is_some());
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn seeking_is_not_supported() {
+    let mut decoder = Decoder::from_read(Pipe::open("tests/audacity16bit.wav")).unwrap();
+    assert!(matches!(
+        decoder.try_seek(Duration::from_millis(100)),
+        Err(SeekError::NotSupported { .. })
+    ));
+    assert!(decoder.next().is_some());
+}
@@ -1,29 +1,347 @@
-use std::io::BufReader;
+mod common;
+
+use std::io::{BufReader, Cursor};
+use std::time::Duration;
+
+use rodio::cpal::Sample;
+use rodio::decoder::DecoderError;
+use rodio::Source;
+
+use crate::common::riff_chunk;
 
 #[test]
 fn test_wav_encodings() {
     // 16 bit wav file exported from Audacity (1 channel)
     let file = std::fs::File::open("tests/audacity16bit.wav").unwrap();
     let mut decoder = rodio::Decoder::new(BufReader::new(file)).unwrap();
-    assert!(decoder.any(|x| x != 0)); // Assert not all zeros
+    let duration = decoder.total_duration();
+    assert!(decoder.any(|x| x != 0.0)); // Assert not all zeros
+    assert_eq!(decoder.total_duration(), duration); // The whole file is still counted
 
     // 16 bit wav file exported from LMMS (2 channels)
     let file = std::fs::File::open("tests/lmms16bit.wav").unwrap();
     let mut decoder = rodio::Decoder::new(BufReader::new(file)).unwrap();
-    assert!(decoder.any(|x| x != 0));
+    assert!(decoder.any(|x| x != 0.0));
 
     // 24 bit wav file exported from LMMS (2 channels)
     let file = std::fs::File::open("tests/lmms24bit.wav").unwrap();
     let mut decoder = rodio::Decoder::new(BufReader::new(file)).unwrap();
-    assert!(decoder.any(|x| x != 0));
+    assert!(decoder.any(|x| x != 0.0));
+    // The lower 8 bits are not discarded
+    assert!(decoder.any(|x| x != x.to_i16().to_f32()));
 
     // 32 bit wav file exported from Audacity (1 channel)
     let file = std::fs::File::open("tests/audacity32bit.wav").unwrap();
     let mut decoder = rodio::Decoder::new(BufReader::new(file)).unwrap();
-    assert!(decoder.any(|x| x != 0));
+    assert!(decoder.any(|x| x != 0.0));
 
     // 32 bit wav file exported from LMMS (2 channels)
     let file = std::fs::File::open("tests/lmms32bit.wav").unwrap();
     let mut decoder = rodio::Decoder::new(BufReader::new(file)).unwrap();
-    assert!(decoder.any(|x| x != 0));
+    assert!(decoder.any(|x| x != 0.0));
+}
+
+/// Builds a WAV file whose `fmt ` chunk has the given format tag, block alignment, bits per
+/// sample and extra bytes.
+fn wav(
+    format_tag: u16,
+    channels: u16,
+    block_align: u16,
+    bits_per_sample: u16,
+    extra: &[u8],
+    frames: Option<u32>,
+    samples: &[u8],
+) -> Vec<u8> {
+    let mut format = format_tag.to_le_bytes().to_vec();
+    format.extend_from_slice(&channels.to_le_bytes());
+    format.extend_from_slice(&8000u32.to_le_bytes());
+    format.extend_from_slice(&(8000 * block_align as u32).to_le_bytes());
+    format.extend_from_slice(&block_align.to_le_bytes());
+    format.extend_from_slice(&bits_per_sample.to_le_bytes());
+    format.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+    format.extend_from_slice(extra);
+
+    let mut content = b"WAVE".to_vec();
+    content.extend(riff_chunk(b"fmt ", &format));
+    if let Some(frames) = frames {
+        content.extend(riff_chunk(b"fact", &frames.to_le_bytes()));
+    }
+    content.extend(riff_chunk(b"data", samples));
+    riff_chunk(b"RIFF", &content)
+}
+
+fn sine(len: usize) -> Vec<i16> {
+    (0..len)
+        .map(|i| (8000.0 * (i as f32 * 0.05).sin()) as i16)
+        .collect()
+}
+
+fn max_error(decoded: &[f32], expected: &[i16]) -> f32 {
+    assert_eq!(decoded.len(), expected.len());
+    decoded
+        .iter()
+        .zip(expected)
+        .map(|(&a, &b)| (a - b.to_f32()).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn g711() {
+    let data = wav(7, 1, 1, 8, &[], None, &[0xff, 0x80, 0x00]);
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    let expected = [0i16, 32124, -32124].map(|s| s.to_f32());
+    assert_eq!(decoder.collect::<Vec<f32>>(), expected);
+
+    let data = wav(6, 2, 2, 8, &[], None, &[0xd5, 0x55, 0xaa, 0x2a]);
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.channels(), 2);
+    let expected = [8i16, -8, 32256, -32256].map(|s| s.to_f32());
+    assert_eq!(decoder.collect::<Vec<f32>>(), expected);
+}
+
+/// Encodes mono samples as IMA ADPCM blocks of 256 bytes, holding 505 samples each.
+fn encode_ima_adpcm(samples: &[i16]) -> Vec<u8> {
+    const STEPS: [i32; 89] = [
+        7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60,
+        66, 73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371,
+        408, 449, 494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878,
+        2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845,
+        8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086,
+        29794, 32767,
+    ];
+    const INDEX_CHANGES: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+    let mut data = Vec::new();
+    let mut index = 40i32;
+    for block in samples.chunks(505) {
+        let mut predicted = block[0] as i32;
+        data.extend_from_slice(&block[0].to_le_bytes());
+        data.extend_from_slice(&[index as u8, 0]);
+
+        let mut nibbles = Vec::new();
+        for &sample in &block[1..] {
+            let step = STEPS[index as usize];
+            let diff = sample as i32 - predicted;
+            let mut nibble = if diff < 0 { 8 } else { 0 };
+            let magnitude = (diff.abs() * 4 / step).min(7);
+            nibble |= magnitude;
+            let mut delta = step >> 3;
+            if magnitude & 1 != 0 {
+                delta += step >> 2;
+            }
+            if magnitude & 2 != 0 {
+                delta += step >> 1;
+            }
+            if magnitude & 4 != 0 {
+                delta += step;
+            }
+            predicted += if nibble & 8 != 0 { -delta } else { delta };
+            predicted = predicted.clamp(-32768, 32767);
+            index = (index + INDEX_CHANGES[magnitude as usize]).clamp(0, 88);
+            nibbles.push(nibble as u8);
+        }
+        nibbles.resize(504, 0);
+        data.extend(nibbles.chunks(2).map(|pair| pair[0] | (pair[1] << 4)));
+    }
+    data
+}
+
+#[test]
+fn ima_adpcm() {
+    // Two blocks and a part of a third one, whose padding is ignored thanks to the fact chunk.
+    let samples = sine(1200);
+    let extra = 505u16.to_le_bytes();
+    let data = wav(
+        0x11,
+        1,
+        256,
+        4,
+        &extra,
+        Some(1200),
+        &encode_ima_adpcm(&samples),
+    );
+    let mut decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.total_duration(), Some(Duration::from_millis(150)));
+    assert_eq!(decoder.size_hint(), (1200, Some(1200)));
+    let decoded: Vec<f32> = decoder.by_ref().collect();
+    assert!(max_error(&decoded, &samples) < 0.02);
+    assert!(decoder.error().is_none());
+
+    assert_eq!(decoder.total_duration(), Some(Duration::from_millis(150)));
+
+    decoder.try_seek(Duration::from_millis(100)).unwrap();
+    assert_eq!(decoder.total_duration(), Some(Duration::from_millis(150)));
+    let rest: Vec<f32> = decoder.collect();
+    assert!(rest == decoded[800..]);
+
+    // In stereo blocks, groups of 8 samples of each channel alternate. With the smallest step,
+    // the nibble 1 adds 1 to the sample.
+    let mut block = vec![100, 0, 0, 0, 0x9c, 0xff, 0, 0];
+    block.extend_from_slice(&[0x11; 4]);
+    block.extend_from_slice(&[0x00; 4]);
+    let data = wav(0x11, 2, 16, 4, &9u16.to_le_bytes(), None, &block);
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    let expected: Vec<f32> = (0..9)
+        .flat_map(|i| vec![(100 + i as i16).to_f32(), (-100i16).to_f32()])
+        .collect();
+    assert_eq!(decoder.collect::<Vec<f32>>(), expected);
+}
+
+/// Encodes mono samples as Microsoft ADPCM blocks of 256 bytes, holding 500 samples each, always
+/// predicting from the previous sample.
+fn encode_ms_adpcm(samples: &[i16]) -> Vec<u8> {
+    const ADAPTATION: [i32; 16] = [
+        230, 230, 230, 230, 307, 409, 512, 614, 768, 614, 512, 409, 307, 230, 230, 230,
+    ];
+
+    let mut data = Vec::new();
+    for block in samples.chunks(500) {
+        let mut delta = 16;
+        let mut sample1 = block[1] as i32;
+        data.push(0);
+        data.extend_from_slice(&(delta as i16).to_le_bytes());
+        data.extend_from_slice(&block[1].to_le_bytes());
+        data.extend_from_slice(&block[0].to_le_bytes());
+
+        let mut nibbles = Vec::new();
+        for &sample in &block[2..] {
+            let predicted = (sample1 * 256) >> 8;
+            let error = sample as i32 - predicted;
+            let nibble = ((error + delta / 2).div_euclid(delta)).clamp(-8, 7);
+            sample1 = (predicted + nibble * delta).clamp(-32768, 32767);
+            delta = ((ADAPTATION[(nibble & 0x0f) as usize] * delta) >> 8).max(16);
+            nibbles.push((nibble & 0x0f) as u8);
+        }
+        nibbles.resize(498, 0);
+        data.extend(nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    }
+    data
+}
+
+#[test]
+fn ms_adpcm() {
+    let samples = sine(1000);
+    // The standard 7 predictors.
+    let mut extra = 500u16.to_le_bytes().to_vec();
+    extra.extend_from_slice(&7u16.to_le_bytes());
+    for (c1, c2) in [
+        (256, 0),
+        (512, -256),
+        (0, 0),
+        (192, 64),
+        (240, 0),
+        (460, -208),
+        (392, -232),
+    ] {
+        extra.extend_from_slice(&(c1 as i16).to_le_bytes());
+        extra.extend_from_slice(&(c2 as i16).to_le_bytes());
+    }
+    let data = wav(2, 1, 256, 4, &extra, None, &encode_ms_adpcm(&samples));
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    let decoded: Vec<f32> = decoder.collect();
+    assert!(max_error(&decoded, &samples) < 0.02);
+}
+
+#[test]
+fn unsupported_format_tag() {
+    // MPEG layer 3 in a WAV file.
+    let data = wav(0x55, 1, 2, 16, &[], None, &[0; 100]);
+    assert!(matches!(
+        rodio::Decoder::new(Cursor::new(data)),
+        Err(DecoderError::UnsupportedEncoding(_))
+    ));
+}
+
+#[test]
+fn extensible_channel_mask() {
+    use rodio::{ChannelLayout, Speaker};
+
+    // 16 bits PCM with a 5.1 layout that uses the side speakers.
+    let mut extra = 16u16.to_le_bytes().to_vec();
+    extra.extend_from_slice(&0x60fu32.to_le_bytes());
+    extra.extend_from_slice(&[
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ]);
+    let data = wav(0xfffe, 6, 12, 16, &extra, None, &[0; 120]);
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    let layout = decoder.channel_layout();
+    assert_eq!(layout.channels(), 6);
+    assert_eq!(layout.index_of(Speaker::LowFrequency), Some(3));
+    assert_eq!(layout.index_of(Speaker::SideLeft), Some(4));
+    assert_eq!(layout.index_of(Speaker::BackLeft), None);
+
+    // Without a mask the usual layout is used.
+    let data = wav(1, 6, 12, 16, &[], None, &[0; 120]);
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.channel_layout(), ChannelLayout::default_for(6));
+}
+
+#[test]
+fn cue_markers() {
+    use rodio::decoder::Marker;
+
+    let mut format = 1u16.to_le_bytes().to_vec();
+    format.extend_from_slice(&1u16.to_le_bytes());
+    format.extend_from_slice(&8000u32.to_le_bytes());
+    format.extend_from_slice(&16000u32.to_le_bytes());
+    format.extend_from_slice(&2u16.to_le_bytes());
+    format.extend_from_slice(&16u16.to_le_bytes());
+
+    // Two cue points, the second one listed first and without a label.
+    let mut cue = 2u32.to_le_bytes().to_vec();
+    for &(id, position) in &[(7u32, 6000u32), (3, 2000)] {
+        cue.extend_from_slice(&id.to_le_bytes());
+        cue.extend_from_slice(&position.to_le_bytes());
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&[0; 8]);
+        cue.extend_from_slice(&position.to_le_bytes());
+    }
+    let mut label = 3u32.to_le_bytes().to_vec();
+    label.extend_from_slice(b"hello\0");
+    let mut list = b"adtl".to_vec();
+    list.extend(riff_chunk(b"labl", &label));
+
+    let mut content = b"WAVE".to_vec();
+    content.extend(riff_chunk(b"fmt ", &format));
+    content.extend(riff_chunk(b"data", &[0; 16000]));
+    content.extend(riff_chunk(b"cue ", &cue));
+    content.extend(riff_chunk(b"LIST", &list));
+    let data = riff_chunk(b"RIFF", &content);
+
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    let markers = decoder.metadata().markers().to_vec();
+    assert_eq!(
+        markers,
+        [
+            Marker {
+                position: 2000,
+                label: "hello".to_owned()
+            },
+            Marker {
+                position: 6000,
+                label: String::new()
+            },
+        ]
+    );
+
+    // Seeking past a marker skips it.
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut source = decoder.on_markers(markers, move |marker| tx.send(marker.position).unwrap());
+    source.try_seek(Duration::from_millis(500)).unwrap();
+    assert_eq!(source.count(), 4000);
+    assert_eq!(rx.iter().collect::<Vec<_>>(), [6000]);
+}
+
+#[test]
+fn oversized_chunk() {
+    // A `LIST` chunk after the samples claims to be almost 4 GiB long.
+    let mut data = wav(1, 1, 2, 16, &[], None, &[0; 16]);
+    data.extend_from_slice(b"LIST");
+    data.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+    data.extend_from_slice(b"INFOINAM");
+
+    let decoder = rodio::Decoder::new(Cursor::new(data)).unwrap();
+    assert_eq!(decoder.metadata().title(), None);
+    assert_eq!(decoder.count(), 8);
 }